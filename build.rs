@@ -0,0 +1,43 @@
+//! Generates the gRPC server of `rec serve --grpc`
+//!
+//! The service is described here rather than compiled from proto/rec.proto,
+//! so building doesn't need protoc; keep the two in sync. Messages are
+//! declared by hand in src/grpc.rs.
+
+use tonic_build::manual::{Builder, Method, Service};
+
+fn main() {
+    let method = |name: &str, route: &str, input: &str, output: &str| {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::{}", input))
+            .output_type(format!("crate::grpc::{}", output))
+            .codec_path("tonic_prost::ProstCodec")
+            .build()
+    };
+    let service = Service::builder()
+        .name("Rec")
+        .package("rec")
+        .method(method("start", "Start", "StartRequest", "StartReply"))
+        .method(method("stop", "Stop", "StopRequest", "Transcription"))
+        .method(method(
+            "transcribe",
+            "Transcribe",
+            "TranscribeRequest",
+            "Transcription",
+        ))
+        .method(method(
+            "history",
+            "History",
+            "HistoryRequest",
+            "HistoryReply",
+        ))
+        .build();
+
+    Builder::new()
+        .build_client(false)
+        .build_transport(false)
+        .compile(&[service]);
+    println!("cargo:rerun-if-changed=build.rs");
+}
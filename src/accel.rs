@@ -0,0 +1,101 @@
+//! Hardware acceleration detection for the local backend
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Accelerator {
+    #[default]
+    Auto,
+    Cpu,
+    Cuda,
+    Metal,
+    Vulkan,
+}
+
+impl std::str::FromStr for Accelerator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "cpu" => Ok(Self::Cpu),
+            "cuda" => Ok(Self::Cuda),
+            "metal" => Ok(Self::Metal),
+            "vulkan" => Ok(Self::Vulkan),
+            _ => Err(format!(
+                "Unknown accelerator: {} (expected auto, cpu, cuda, metal or vulkan)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Accelerator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Auto => "auto",
+            Self::Cpu => "cpu",
+            Self::Cuda => "cuda",
+            Self::Metal => "metal",
+            Self::Vulkan => "vulkan",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Accelerator {
+    /// Resolve `Auto` to the best accelerator available on this machine
+    pub fn resolve(self) -> Self {
+        match self {
+            Self::Auto => available().into_iter().next().unwrap_or(Self::Cpu),
+            other => other,
+        }
+    }
+}
+
+/// GPU accelerators detected on this machine, best first
+pub fn available() -> Vec<Accelerator> {
+    let mut found = Vec::new();
+
+    if cfg!(all(target_os = "macos", target_arch = "aarch64")) {
+        found.push(Accelerator::Metal);
+    }
+
+    if Path::new("/dev/nvidia0").exists() || find_in_path("nvidia-smi").is_some() {
+        found.push(Accelerator::Cuda);
+    }
+
+    let vulkan_libs = [
+        "/usr/lib/x86_64-linux-gnu/libvulkan.so.1",
+        "/usr/lib/aarch64-linux-gnu/libvulkan.so.1",
+        "/usr/lib/libvulkan.so.1",
+        "/usr/lib64/libvulkan.so.1",
+    ];
+    if vulkan_libs.iter().any(|p| Path::new(p).exists()) || find_in_path("vulkaninfo").is_some() {
+        found.push(Accelerator::Vulkan);
+    }
+
+    found
+}
+
+/// Number of CPU threads available
+pub fn cpu_threads() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// Look up an executable in PATH
+pub fn find_in_path(name: &str) -> Option<std::path::PathBuf> {
+    if name.contains(std::path::MAIN_SEPARATOR) {
+        let path = Path::new(name);
+        return path.is_file().then(|| path.to_path_buf());
+    }
+
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
@@ -0,0 +1,672 @@
+//! Microphone capture and WAV encoding
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use hound::{WavSpec, WavWriter};
+use serde::{Deserialize, Serialize};
+use std::io::{BufWriter, Read};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Sample rate requested from PulseAudio and PipeWire sources
+const SERVER_SAMPLE_RATE: u32 = 48000;
+
+/// Audio host picked with --audio-host, the platform's default otherwise
+static HOST: OnceLock<cpal::HostId> = OnceLock::new();
+
+/// Where the samples come from
+enum Source {
+    /// Kept alive for as long as the recorder runs
+    Stream(#[allow(dead_code)] cpal::Stream),
+    /// `parec` or `pw-record` writing raw f32 samples to stdout
+    Process(Child),
+}
+
+impl Drop for Source {
+    fn drop(&mut self) {
+        if let Source::Process(child) = self {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// What the capture callback or thread receives, shared with the recorder
+#[derive(Clone, Default)]
+struct Capture {
+    samples: Arc<Mutex<Vec<f32>>>,
+    level: Arc<AtomicU32>,
+    /// Buffers received while set are dropped rather than kept
+    paused: Arc<AtomicBool>,
+}
+
+impl Capture {
+    /// Keep a buffer and note its level, unless paused
+    fn push(&self, data: &[f32]) {
+        if self.paused.load(Ordering::Relaxed) {
+            self.level.store(0.0f32.to_bits(), Ordering::Relaxed);
+            return;
+        }
+        self.level.store(rms(data).to_bits(), Ordering::Relaxed);
+        self.samples.lock().unwrap().extend_from_slice(data);
+    }
+}
+
+/// Live capture from an input device
+pub struct Recorder {
+    _source: Source,
+    capture: Capture,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Device and host or program recorded from, e.g. "USB Audio (alsa)"
+    pub input: String,
+}
+
+impl Recorder {
+    /// Open an input device and start capturing
+    ///
+    /// `device` is either `pulse:<source>` or `pipewire:<target>` to record
+    /// from a sound server source, the name of a cpal input device, or
+    /// `None` for the default input device. `buffer_ms` sets the capture
+    /// buffer length, `None` leaving it to the device or sound server.
+    pub fn start(
+        device: Option<&str>,
+        buffer_ms: Option<u32>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(name) = device {
+            if let Some(source) = name.strip_prefix("pulse:") {
+                return Self::spawn(
+                    Command::new("parec")
+                        .args([
+                            &format!("--device={}", source),
+                            "--format=float32le",
+                            &format!("--rate={}", SERVER_SAMPLE_RATE),
+                            "--channels=1",
+                            "--raw",
+                        ])
+                        .args(buffer_ms.map(|ms| format!("--latency-msec={}", ms))),
+                    "parec",
+                    source,
+                );
+            }
+            if let Some(target) = name.strip_prefix("pipewire:") {
+                return Self::spawn(
+                    Command::new("pw-record")
+                        .args(buffer_ms.map(|ms| format!("--latency={}ms", ms)))
+                        .args([
+                            "--target",
+                            target,
+                            "--format",
+                            "f32",
+                            "--rate",
+                            &SERVER_SAMPLE_RATE.to_string(),
+                            "--channels",
+                            "1",
+                            "-",
+                        ]),
+                    "pw-record",
+                    target,
+                );
+            }
+        }
+
+        let host = host()?;
+        let device = match device {
+            Some(name) => find_input_device(&host, name)?,
+            None => host.default_input_device().ok_or("No mic")?,
+        };
+        let input = format!(
+            "{} ({})",
+            device
+                .description()
+                .map(|desc| desc.name().to_string())
+                .unwrap_or_else(|_| "unnamed device".to_string()),
+            host.id()
+        );
+        let config = device.default_input_config()?;
+        let sample_rate = config.sample_rate();
+        let channels = config.channels();
+        let mut stream_config: cpal::StreamConfig = config.clone().into();
+        if let Some(ms) = buffer_ms {
+            stream_config.buffer_size =
+                cpal::BufferSize::Fixed(buffer_frames(ms, sample_rate, config.buffer_size()));
+        }
+
+        let capture = Capture::default();
+        let sink = capture.clone();
+
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => device.build_input_stream(
+                &stream_config,
+                move |data: &[f32], _: &_| sink.push(data),
+                |err| eprintln!("Error: {}", err),
+                None,
+            )?,
+            cpal::SampleFormat::I16 => device.build_input_stream(
+                &stream_config,
+                move |data: &[i16], _: &_| {
+                    let floats: Vec<f32> = data.iter().map(|&s| s as f32 / 32768.0).collect();
+                    sink.push(&floats);
+                },
+                |err| eprintln!("Error: {}", err),
+                None,
+            )?,
+            _ => return Err("Unsupported format".into()),
+        };
+
+        stream.play()?;
+
+        Ok(Self {
+            _source: Source::Stream(stream),
+            capture,
+            sample_rate,
+            channels,
+            input,
+        })
+    }
+
+    /// Capture mono f32 samples from the stdout of a recording command
+    fn spawn(
+        command: &mut Command,
+        program: &str,
+        source: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+        let mut stdout = child.stdout.take().ok_or("No stdout")?;
+
+        let capture = Capture::default();
+        let sink = capture.clone();
+
+        std::thread::spawn(move || {
+            let mut buf = vec![0u8; 4096];
+            let mut pending = Vec::new();
+            while let Ok(n) = stdout.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                pending.extend_from_slice(&buf[..n]);
+                let whole = pending.len() - pending.len() % 4;
+                let floats: Vec<f32> = pending[..whole]
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect();
+                pending.drain(..whole);
+                sink.push(&floats);
+            }
+        });
+
+        Ok(Self {
+            _source: Source::Process(child),
+            capture,
+            sample_rate: SERVER_SAMPLE_RATE,
+            channels: 1,
+            input: format!("{} ({})", source, program),
+        })
+    }
+
+    /// Take the samples captured so far, leaving the recorder running
+    pub fn take(&self) -> Vec<f32> {
+        std::mem::take(&mut *self.capture.samples.lock().unwrap())
+    }
+
+    /// RMS level of the most recent buffer (0.0 to 1.0), 0.0 while paused
+    pub fn level(&self) -> f32 {
+        f32::from_bits(self.capture.level.load(Ordering::Relaxed))
+    }
+
+    /// Size of the samples captured and not yet taken, in bytes
+    pub fn buffered_bytes(&self) -> usize {
+        self.capture.samples.lock().unwrap().len() * std::mem::size_of::<f32>()
+    }
+
+    /// Drop what the device captures until resumed, keeping it open
+    pub fn set_paused(&self, paused: bool) {
+        self.capture.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.capture.paused.load(Ordering::Relaxed)
+    }
+
+    /// Stop capturing and return the remaining samples
+    pub fn stop(self) -> Vec<f32> {
+        let samples = self.capture.samples.clone();
+        drop(self);
+        std::mem::take(&mut *samples.lock().unwrap())
+    }
+}
+
+/// Frames in a `ms` long buffer, within what the device supports
+fn buffer_frames(ms: u32, sample_rate: u32, supported: &cpal::SupportedBufferSize) -> u32 {
+    let frames = (sample_rate as u64 * ms as u64 / 1000).max(1) as u32;
+    match *supported {
+        cpal::SupportedBufferSize::Range { min, max } if !(min..=max).contains(&frames) => {
+            let clamped = frames.clamp(min, max);
+            eprintln!(
+                "Warning: The input device takes buffers of {} to {} frames, using {} ({} ms) instead of {} ms",
+                min,
+                max,
+                clamped,
+                clamped as u64 * 1000 / sample_rate as u64,
+                ms
+            );
+            clamped
+        }
+        _ => frames,
+    }
+}
+
+/// Name of the echo-cancelled source created by [`EchoCancel`]
+const ECHO_CANCEL_SOURCE: &str = "rec_echo_cancel";
+
+/// Name of the echo-cancelled sink created by [`EchoCancel`]
+const ECHO_CANCEL_SINK: &str = "rec_echo_cancel_sink";
+
+/// Echo cancellation through the sound server's `module-echo-cancel` (WebRTC AEC)
+///
+/// While loaded, the echo-cancelled sink is the default sink so that speaker
+/// output is used as the echo reference and removed from the mic signal.
+/// Works with PulseAudio and with PipeWire's pulse server.
+pub struct EchoCancel {
+    module: String,
+    previous_sink: Option<String>,
+    /// Device to pass to [`Recorder::start`]
+    pub device: String,
+}
+
+impl EchoCancel {
+    /// Load the echo canceller on top of `device` (or the default source)
+    pub fn load(device: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        // A previous run killed before cleaning up would leave its module behind
+        if let Ok(modules) = pactl(&["list", "short", "modules"]) {
+            for line in modules.lines() {
+                if line.contains(&format!("source_name={}", ECHO_CANCEL_SOURCE))
+                    && let Some(id) = line.split_whitespace().next()
+                {
+                    let _ = pactl(&["unload-module", id]);
+                }
+            }
+        }
+
+        let mut args = vec![
+            "load-module".to_string(),
+            "module-echo-cancel".to_string(),
+            "aec_method=webrtc".to_string(),
+            format!("source_name={}", ECHO_CANCEL_SOURCE),
+            format!("sink_name={}", ECHO_CANCEL_SINK),
+        ];
+        if let Some(name) = device {
+            let master = name
+                .strip_prefix("pulse:")
+                .or_else(|| name.strip_prefix("pipewire:"))
+                .ok_or("--echo-cancel needs a 'pulse:' or 'pipewire:' device")?;
+            args.push(format!("source_master={}", master));
+        }
+
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let module = pactl(&args)?.trim().to_string();
+        let previous_sink = pactl(&["get-default-sink"])
+            .ok()
+            .map(|s| s.trim().to_string());
+        pactl(&["set-default-sink", ECHO_CANCEL_SINK])?;
+
+        Ok(Self {
+            module,
+            previous_sink,
+            device: format!("pulse:{}", ECHO_CANCEL_SOURCE),
+        })
+    }
+}
+
+impl Drop for EchoCancel {
+    fn drop(&mut self) {
+        if let Some(sink) = &self.previous_sink {
+            let _ = pactl(&["set-default-sink", sink]);
+        }
+        let _ = pactl(&["unload-module", &self.module]);
+    }
+}
+
+/// Run `pactl` and return its stdout
+fn pactl(args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new("pactl")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run pactl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "pactl {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Capture device, as listed by `rec devices`
+pub struct InputDevice {
+    pub name: String,
+    /// Whether the system records from it by default
+    pub default: bool,
+    /// Supported configurations, e.g. "1-2 channels, 8000-48000 Hz, f32"
+    pub configs: Vec<String>,
+}
+
+/// Record through the cpal host named `name` (e.g. 'alsa', 'jack', 'wasapi')
+pub fn select_host(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let id = cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            format!(
+                "No audio host named {} (available: {})",
+                name,
+                host_names().join(", ")
+            )
+        })?;
+    HOST.set(id).ok();
+    Ok(())
+}
+
+/// Hosts cpal was built with that this machine can use, e.g. ["alsa", "jack"]
+pub fn host_names() -> Vec<String> {
+    cpal::available_hosts()
+        .into_iter()
+        .map(|id| id.to_string())
+        .collect()
+}
+
+/// The host recorded through
+pub fn host() -> Result<cpal::Host, Box<dyn std::error::Error>> {
+    match HOST.get() {
+        Some(&id) => {
+            cpal::host_from_id(id).map_err(|_| format!("Audio host {} is unavailable", id).into())
+        }
+        None => Ok(cpal::default_host()),
+    }
+}
+
+/// Capture devices of the host, in the order `--device <index>` counts them
+pub fn input_devices() -> Result<Vec<InputDevice>, Box<dyn std::error::Error>> {
+    let host = host()?;
+    let default = host
+        .default_input_device()
+        .and_then(|d| d.description().ok())
+        .map(|desc| desc.name().to_string());
+
+    let mut devices = Vec::new();
+    for device in host.input_devices()? {
+        let name = match device.description() {
+            Ok(desc) => desc.name().to_string(),
+            Err(_) => "(unnamed)".to_string(),
+        };
+        let configs = device
+            .supported_input_configs()
+            .map(|configs| describe_configs(configs.collect()))
+            .unwrap_or_default();
+        devices.push(InputDevice {
+            default: default.as_ref() == Some(&name),
+            name,
+            configs,
+        });
+    }
+    Ok(devices)
+}
+
+/// Input device by name, or by its index in `rec devices`
+fn find_input_device(
+    host: &cpal::Host,
+    name: &str,
+) -> Result<cpal::Device, Box<dyn std::error::Error>> {
+    let named = |d: &cpal::Device| d.description().is_ok_and(|desc| desc.name() == name);
+    if let Some(device) = host.input_devices()?.find(named) {
+        return Ok(device);
+    }
+    name.parse::<usize>()
+        .ok()
+        .and_then(|index| host.input_devices().ok()?.nth(index))
+        .ok_or_else(|| format!("No input device named {} (see `rec devices`)", name).into())
+}
+
+/// One line per sample rate range and format, with the channel counts it takes
+///
+/// Devices list a configuration per channel count, dozens for virtual ones.
+fn describe_configs(configs: Vec<cpal::SupportedStreamConfigRange>) -> Vec<String> {
+    let mut lines: Vec<(u32, u32, cpal::SampleFormat, u16, u16)> = Vec::new();
+    for config in configs {
+        let (min, max, format) = (
+            config.min_sample_rate(),
+            config.max_sample_rate(),
+            config.sample_format(),
+        );
+        match lines
+            .iter_mut()
+            .find(|line| (line.0, line.1, line.2) == (min, max, format))
+        {
+            Some(line) => {
+                line.3 = line.3.min(config.channels());
+                line.4 = line.4.max(config.channels());
+            }
+            None => lines.push((min, max, format, config.channels(), config.channels())),
+        }
+    }
+
+    lines
+        .into_iter()
+        .map(|(min, max, format, fewest, most)| {
+            let channels = match (fewest, most) {
+                (1, 1) => "mono".to_string(),
+                (n, m) if n == m => format!("{} channels", n),
+                (n, m) => format!("{}-{} channels", n, m),
+            };
+            let rates = match min == max {
+                true => format!("{} Hz", min),
+                false => format!("{}-{} Hz", min, max),
+            };
+            format!("{}, {}, {}", channels, rates, format)
+        })
+        .collect()
+}
+
+/// Error for a recording made with the microphone muted
+pub const MUTED: &str = "Microphone is muted or blocked: only digital silence was captured \
+(check the mute switch and the system's microphone privacy setting)";
+
+/// Level below one 16-bit step, which no live microphone stays under
+const SILENCE_FLOOR: f32 = 1.0 / 32768.0;
+
+/// Whether samples are digital silence
+///
+/// Even a quiet room leaves some noise on a live microphone; a hardware mute
+/// or an OS privacy toggle delivers zeros instead.
+pub fn is_digital_silence(samples: &[f32]) -> bool {
+    !samples.is_empty() && samples.iter().all(|s| s.abs() < SILENCE_FLOOR)
+}
+
+/// Whether a WAV file is digital silence (see [`is_digital_silence`])
+///
+/// Other formats are assumed not to be.
+pub fn wav_is_digital_silence(wav_data: &[u8]) -> bool {
+    let Ok(mut reader) = hound::WavReader::new(std::io::Cursor::new(wav_data)) else {
+        return false;
+    };
+    if reader.duration() == 0 {
+        return false;
+    }
+
+    let spec = reader.spec();
+    match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .all(|s| s.is_ok_and(|s| s.abs() < SILENCE_FLOOR)),
+        hound::SampleFormat::Int => {
+            let step = 1i32 << spec.bits_per_sample.saturating_sub(16);
+            reader
+                .samples::<i32>()
+                .all(|s| s.is_ok_and(|s| s.abs() < step))
+        }
+    }
+}
+
+/// Whether the sound server has the recording source muted
+///
+/// Checks the `pulse:` source given as device, or the default source; other
+/// devices and systems without `pactl` are reported as not muted.
+pub fn source_muted(device: Option<&str>) -> bool {
+    let source = match device {
+        None => "@DEFAULT_SOURCE@",
+        Some(name) => match name.strip_prefix("pulse:") {
+            Some(source) => source,
+            None => return false,
+        },
+    };
+    pactl(&["get-source-mute", source]).is_ok_and(|out| out.trim().ends_with("yes"))
+}
+
+/// Multiply samples by `gain`, clipping at full scale
+pub fn amplify(mut samples: Vec<f32>, gain: f32) -> Vec<f32> {
+    if gain != 1.0 {
+        samples
+            .iter_mut()
+            .for_each(|s| *s = (*s * gain).clamp(-1.0, 1.0));
+    }
+    samples
+}
+
+/// Root mean square of a buffer
+pub fn rms(data: &[f32]) -> f32 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    (data.iter().map(|s| s * s).sum::<f32>() / data.len() as f32).sqrt()
+}
+
+/// Length of a WAV file in seconds, if it parses
+pub fn wav_duration(wav_data: &[u8]) -> Option<f32> {
+    let reader = hound::WavReader::new(std::io::Cursor::new(wav_data)).ok()?;
+    Some(reader.duration() as f32 / reader.spec().sample_rate as f32)
+}
+
+/// Sample format of a WAV file
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WavFormat {
+    /// 16-bit PCM, what backends are sent
+    #[default]
+    Pcm16,
+    /// 24-bit PCM
+    Pcm24,
+    /// 32-bit float, the captured samples as they are
+    Float,
+}
+
+/// How kept audio is written (`kept_audio` in config)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WavConfig {
+    /// 'pcm16', 'pcm24' or 'float'
+    #[serde(default)]
+    pub format: WavFormat,
+    /// Add noise of about one step before rounding to PCM, so that quiet
+    /// passages turn into a faint hiss rather than distortion
+    #[serde(default = "default_dither")]
+    pub dither: bool,
+}
+
+impl Default for WavConfig {
+    fn default() -> Self {
+        Self {
+            format: WavFormat::default(),
+            dither: default_dither(),
+        }
+    }
+}
+
+fn default_dither() -> bool {
+    true
+}
+
+/// Encode interleaved f32 samples as a dithered 16-bit PCM WAV file
+pub fn encode_wav(
+    samples: &[f32],
+    sample_rate: u32,
+    channels: u16,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    WavConfig::default().encode(samples, sample_rate, channels)
+}
+
+impl WavConfig {
+    /// Encode interleaved f32 samples as a WAV file in this format
+    pub fn encode(
+        &self,
+        samples: &[f32],
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let (bits_per_sample, sample_format) = match self.format {
+            WavFormat::Pcm16 => (16, hound::SampleFormat::Int),
+            WavFormat::Pcm24 => (24, hound::SampleFormat::Int),
+            WavFormat::Float => (32, hound::SampleFormat::Float),
+        };
+        let mut wav_buffer = Vec::new();
+        {
+            let cursor = std::io::Cursor::new(&mut wav_buffer);
+            let spec = WavSpec {
+                channels,
+                sample_rate,
+                bits_per_sample,
+                sample_format,
+            };
+            let mut writer = WavWriter::new(BufWriter::new(cursor), spec)?;
+
+            if self.format == WavFormat::Float {
+                for &s in samples {
+                    writer.write_sample(s)?;
+                }
+            } else {
+                let max = ((1i32 << (bits_per_sample - 1)) - 1) as f32;
+                let mut dither = Dither::new();
+                for &s in samples {
+                    // Digital silence stays silent, so that a muted input is still told apart
+                    let noise = match self.dither && s != 0.0 {
+                        true => dither.next(),
+                        false => 0.0,
+                    };
+                    let value = (s * max + noise).round().clamp(-max - 1.0, max);
+                    writer.write_sample(value as i32)?;
+                }
+            }
+
+            writer.finalize()?;
+        }
+        Ok(wav_buffer)
+    }
+}
+
+/// Triangular noise spanning one quantization step each way (TPDF dither)
+///
+/// The generator starts from a fixed seed, so the same samples always
+/// encode to the same file.
+struct Dither(u32);
+
+impl Dither {
+    fn new() -> Self {
+        Self(0x9e37_79b9)
+    }
+
+    fn next(&mut self) -> f32 {
+        self.uniform() + self.uniform()
+    }
+
+    /// Uniform in [-0.5, 0.5), from a xorshift generator
+    fn uniform(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32 - 0.5
+    }
+}
@@ -0,0 +1,210 @@
+//! AWS request signing for the Transcribe backend
+//!
+//! Implements Signature Version 4 for the two kinds of requests the backend
+//! makes, without the AWS SDK: JSON API calls to Transcribe signed in the
+//! `Authorization` header, and presigned S3 URLs for uploading and deleting
+//! the audio. Credentials come from the usual environment variables.
+
+use ring::hmac;
+use sha2::{Digest, Sha256};
+
+/// Seconds a presigned S3 URL stays valid
+const PRESIGN_SECS: u32 = 900;
+
+/// Access key from AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and, for
+/// temporary credentials, AWS_SESSION_TOKEN
+#[derive(Clone)]
+pub struct Credentials {
+    pub access_key: String,
+    pub secret_key: String,
+    pub session_token: Option<String>,
+}
+
+impl Credentials {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let env = |var: &str| std::env::var(var).map_err(|_| format!("{} not set", var));
+        Ok(Self {
+            access_key: env("AWS_ACCESS_KEY_ID")?,
+            secret_key: env("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+/// Date and time a request is signed at
+struct Stamp {
+    /// `20250101T120000Z`
+    datetime: String,
+    /// `20250101`
+    date: String,
+}
+
+impl Stamp {
+    fn now() -> Self {
+        let now = chrono::Utc::now();
+        Self {
+            datetime: now.format("%Y%m%dT%H%M%SZ").to_string(),
+            date: now.format("%Y%m%d").to_string(),
+        }
+    }
+
+    fn scope(&self, region: &str, service: &str) -> String {
+        format!("{}/{}/{}/aws4_request", self.date, region, service)
+    }
+}
+
+/// Call a JSON API action, e.g. `Transcribe.StartTranscriptionJob`
+pub fn json_request(
+    client: &reqwest::Client,
+    credentials: &Credentials,
+    region: &str,
+    service: &str,
+    target: &str,
+    body: &serde_json::Value,
+) -> reqwest::RequestBuilder {
+    let host = format!("{}.{}.amazonaws.com", service, region);
+    let body = body.to_string();
+    let stamp = Stamp::now();
+
+    let mut headers = vec![
+        ("content-type", "application/x-amz-json-1.1".to_string()),
+        ("host", host.clone()),
+        ("x-amz-date", stamp.datetime.clone()),
+    ];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+    headers.push(("x-amz-target", target.to_string()));
+
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "POST\n/\n\n{}\n{}\n{}",
+        canonical_headers,
+        signed_headers,
+        sha256_hex(body.as_bytes())
+    );
+
+    let scope = stamp.scope(region, service);
+    let signature = sign(credentials, &stamp, region, service, &canonical_request);
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key, scope, signed_headers, signature
+    );
+
+    let mut req = client
+        .post(format!("https://{}/", host))
+        .header("authorization", authorization);
+    for (name, value) in headers {
+        if name != "host" {
+            req = req.header(name, value);
+        }
+    }
+    req.body(body)
+}
+
+/// Presigned URL for `method` on an S3 object
+///
+/// The URL carries the signature, so the audio can be sent as is, without
+/// hashing it first.
+pub fn presign_s3(
+    credentials: &Credentials,
+    region: &str,
+    bucket: &str,
+    key: &str,
+    method: &str,
+) -> String {
+    let host = format!("{}.s3.{}.amazonaws.com", bucket, region);
+    let path = format!(
+        "/{}",
+        key.split('/').map(uri_encode).collect::<Vec<_>>().join("/")
+    );
+    let stamp = Stamp::now();
+    let scope = stamp.scope(region, "s3");
+
+    let mut query = vec![
+        ("X-Amz-Algorithm", "AWS4-HMAC-SHA256".to_string()),
+        (
+            "X-Amz-Credential",
+            format!("{}/{}", credentials.access_key, scope),
+        ),
+        ("X-Amz-Date", stamp.datetime.clone()),
+        ("X-Amz-Expires", PRESIGN_SECS.to_string()),
+    ];
+    if let Some(token) = &credentials.session_token {
+        query.push(("X-Amz-Security-Token", token.clone()));
+    }
+    query.push(("X-Amz-SignedHeaders", "host".to_string()));
+    let query = query
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, uri_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD",
+        method, path, query, host
+    );
+    let signature = sign(credentials, &stamp, region, "s3", &canonical_request);
+    format!(
+        "https://{}{}?{}&X-Amz-Signature={}",
+        host, path, query, signature
+    )
+}
+
+/// Signature of a canonical request
+fn sign(
+    credentials: &Credentials,
+    stamp: &Stamp,
+    region: &str,
+    service: &str,
+    canonical_request: &str,
+) -> String {
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        stamp.datetime,
+        stamp.scope(region, service),
+        sha256_hex(canonical_request.as_bytes())
+    );
+
+    let secret = format!("AWS4{}", credentials.secret_key);
+    let key = [stamp.date.as_str(), region, service, "aws4_request"]
+        .iter()
+        .fold(secret.into_bytes(), |key, part| {
+            hmac_sha256(&key, part.as_bytes())
+        });
+    hex(&hmac_sha256(&key, string_to_sign.as_bytes()))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    hmac::sign(&key, data).as_ref().to_vec()
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Percent-encode all but the characters SigV4 leaves as is
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
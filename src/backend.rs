@@ -1,43 +1,758 @@
+use crate::accel::Accelerator;
+use crate::aws;
+use crate::config::{BackendConfig, Config};
+use crate::google::Credentials;
+use crate::http;
+use crate::language::{self, locale};
 use reqwest::multipart;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
 
 const MISTRAL_URL: &str = "https://api.mistral.ai/v1/audio/transcriptions";
+const OPENAI_URL: &str = "https://api.openai.com/v1/audio/transcriptions";
+const GOOGLE_URL: &str = "https://speech.googleapis.com/v1/speech:recognize";
+const ELEVENLABS_URL: &str = "https://api.elevenlabs.io/v1/speech-to-text";
+
+/// OpenAI model used unless `backends.openai.model` is set
+const OPENAI_MODEL: &str = "whisper-1";
+
+/// OpenAI model used with `diarize` unless `backends.openai.model` is set
+const OPENAI_DIARIZE_MODEL: &str = "gpt-4o-transcribe-diarize";
+
+/// Speakers AWS Transcribe tells apart at most when diarizing (2 to 30)
+const AWS_MAX_SPEAKERS: u32 = 10;
+
+/// ElevenLabs model used unless `backends.elevenlabs.model` is set
+const ELEVENLABS_MODEL: &str = "scribe_v1";
+
+/// Azure's REST API for short audio takes up to 60 seconds per request
+const AZURE_MAX_SECS: usize = 55;
+
+/// Google's synchronous recognition takes up to a minute of audio
+const GOOGLE_MAX_SECS: usize = 55;
+
+/// Weight of custom words in Google's speech adaptation (0 to 20)
+const GOOGLE_BOOST: f32 = 10.0;
 
 #[derive(Deserialize)]
 struct TranscriptionResponse {
     text: String,
+    #[serde(default)]
+    segments: Vec<ResponseSegment>,
+}
+
+/// Segment as returned by OpenAI-style APIs (verbose_json)
+#[derive(Deserialize)]
+struct ResponseSegment {
+    #[serde(default)]
+    start: f32,
+    #[serde(default)]
+    end: f32,
+    text: String,
+    #[serde(default)]
+    confidence: Option<f32>,
+    #[serde(default)]
+    avg_logprob: Option<f32>,
+    /// Diarization label ("A", "speaker_0", 1...), when the server provides it
+    #[serde(default, alias = "speaker_id")]
+    speaker: Option<serde_json::Value>,
+}
+
+impl From<TranscriptionResponse> for Transcript {
+    fn from(resp: TranscriptionResponse) -> Self {
+        Self {
+            text: resp.text,
+            segments: resp
+                .segments
+                .into_iter()
+                .map(|s| Segment {
+                    start: s.start,
+                    end: s.end,
+                    text: s.text.trim().to_string(),
+                    confidence: s.confidence.or(s.avg_logprob.map(f32::exp)),
+                    speaker: s.speaker.map(|v| match v {
+                        serde_json::Value::String(label) => label,
+                        other => other.to_string(),
+                    }),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Transcription result
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Transcript {
+    pub text: String,
+    /// Timed chunks, when the backend provides them
+    #[serde(default)]
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Segment {
+    pub start: f32,
+    pub end: f32,
+    pub text: String,
+    /// Backend confidence between 0 and 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    /// Who is speaking, for backends that diarize
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<String>,
+}
+
+impl Transcript {
+    pub fn from_text(text: String) -> Self {
+        Self {
+            text,
+            segments: vec![],
+        }
+    }
+
+    /// Whether any segment carries a confidence score
+    pub fn has_confidence(&self) -> bool {
+        self.segments.iter().any(|s| s.confidence.is_some())
+    }
+
+    /// Speaker labels in order of first appearance
+    pub fn speakers(&self) -> Vec<&str> {
+        let mut speakers: Vec<&str> = Vec::new();
+        for speaker in self.segments.iter().filter_map(|s| s.speaker.as_deref()) {
+            if !speakers.contains(&speaker) {
+                speakers.push(speaker);
+            }
+        }
+        speakers
+    }
+
+    /// Name shown for each speaker label: the one given in `names`, or
+    /// "Speaker 1", "Speaker 2"... in order of first appearance
+    pub fn speaker_names(&self, names: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+        self.speakers()
+            .into_iter()
+            .enumerate()
+            .map(|(i, label)| {
+                let name = names.get(label).cloned();
+                (
+                    label.to_string(),
+                    name.unwrap_or_else(|| format!("Speaker {}", i + 1)),
+                )
+            })
+            .collect()
+    }
+
+    /// Text as one paragraph per speaker turn, e.g. "Alice: ..."
+    pub fn speaker_text(&self, names: &BTreeMap<String, String>) -> String {
+        let shown = self.speaker_names(names);
+        let mut turns: Vec<(Option<&str>, Vec<&str>)> = Vec::new();
+        for segment in &self.segments {
+            let speaker = segment.speaker.as_deref();
+            match turns.last_mut() {
+                Some((current, texts)) if *current == speaker => texts.push(&segment.text),
+                _ => turns.push((speaker, vec![&segment.text])),
+            }
+        }
+
+        turns
+            .iter()
+            .map(|(speaker, texts)| {
+                let text = texts.join(" ");
+                match speaker {
+                    Some(label) => format!("{}: {}", shown[*label], text),
+                    None => text,
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
 }
 
+#[derive(Clone)]
 pub struct TranscribeOptions {
     pub wav_data: Vec<u8>,
     pub model: String,
     pub language: Option<String>,
     pub context_bias: Vec<String>,
+    /// What is dictated, in a few words (--prompt), steering Whisper-style
+    /// decoding towards its names and jargon
+    pub prompt: Option<String>,
+}
+
+/// What a backend does with a request, known before any audio is sent
+///
+/// Options it can't honor are flagged up front rather than left to an
+/// opaque 400 error or silently ignored, and the audio is converted to what
+/// it takes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Capabilities {
+    /// Segments with start and end times
+    pub timestamps: bool,
+    /// Speaker labels on segments
+    pub diarization: bool,
+    /// Custom words (--bias) are passed on
+    pub bias: bool,
+    /// Longest audio in one request, in seconds
+    pub max_secs: Option<usize>,
+    /// Audio is resampled to 16 kHz mono before sending
+    pub model_rate: bool,
+    /// Only WAV is taken; other files (-f memo.mp3) are decoded first
+    pub wav_only: bool,
+    /// The model can be chosen (--model)
+    pub model: bool,
+    /// Takes a prompt steering recognition (--prompt)
+    pub prompt: bool,
+}
+
+impl std::fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if self.timestamps {
+            parts.push("timestamps".to_string());
+        }
+        if self.diarization {
+            parts.push("speakers".to_string());
+        }
+        if self.bias {
+            parts.push("custom words".to_string());
+        }
+        if self.prompt {
+            parts.push("prompt".to_string());
+        }
+        if let Some(secs) = self.max_secs {
+            parts.push(format!("{}s per request", secs));
+        }
+        parts.push(
+            match (self.model_rate, self.wav_only) {
+                (true, true) => "16 kHz WAV",
+                (true, false) => "16 kHz WAV or any file",
+                (false, true) => "WAV",
+                (false, false) => "any audio",
+            }
+            .to_string(),
+        );
+        f.write_str(&parts.join(", "))
+    }
 }
 
 pub enum Backend {
-    Mistral { api_key: String },
-    RecApi { api_url: String, api_key: String },
+    Mistral {
+        api_key: String,
+    },
+    OpenAI {
+        api_key: String,
+        model: String,
+        /// Overrides the format picked for the model
+        response_format: Option<String>,
+    },
+    /// ElevenLabs Scribe
+    ElevenLabs {
+        api_key: String,
+        model: String,
+        /// Label each word with its speaker
+        diarize: bool,
+    },
+    /// Azure Speech REST API for short audio
+    Azure {
+        region: String,
+        api_key: String,
+    },
+    /// AWS Transcribe batch jobs, on audio uploaded to S3
+    Aws {
+        credentials: aws::Credentials,
+        region: String,
+        bucket: String,
+        /// Label each segment with its speaker
+        diarize: bool,
+    },
+    /// Google Cloud Speech-to-Text (synchronous recognition)
+    Google {
+        credentials: Credentials,
+        /// Recognition model, e.g. 'latest_long' (Google's default otherwise)
+        model: Option<String>,
+    },
+    RecApi {
+        api_url: String,
+        api_key: String,
+        style: RequestStyle,
+    },
+    /// Any server exposing an OpenAI-style multipart transcription endpoint
+    Compatible {
+        name: String,
+        url: String,
+        api_key: Option<String>,
+        model: Option<String>,
+        response_format: Option<String>,
+        style: RequestStyle,
+    },
+    Local(LocalOptions),
+    /// User program reading the WAV on stdin and printing the transcript
+    Command {
+        name: String,
+        command: String,
+        model: Option<String>,
+    },
+    /// Vosk through its Python binding, fully offline
+    Vosk {
+        python: String,
+        model_dir: PathBuf,
+    },
+    /// Canned answers without any API, for demos and offline development
+    Mock {
+        text: Option<String>,
+        latency: Duration,
+    },
+}
+
+/// How the API key is sent
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthStyle {
+    /// `Authorization: Bearer <key>`
+    Bearer,
+    /// `x-api-key: <key>`
+    XApiKey,
+    /// Requests signed with the key as HMAC secret (rec-api), for gateways
+    /// that check signatures
+    Hmac,
+    None,
+}
+
+/// Auth header and multipart field names expected by a server
+pub struct RequestStyle {
+    pub auth: AuthStyle,
+    pub file_field: String,
+    pub model_field: String,
+    pub language_field: String,
+}
+
+impl RequestStyle {
+    fn new(auth: AuthStyle, entry: Option<&BackendConfig>) -> Self {
+        let field = |value: Option<&String>, default: &str| {
+            value.cloned().unwrap_or_else(|| default.to_string())
+        };
+
+        Self {
+            auth: entry.and_then(|e| e.auth).unwrap_or(auth),
+            file_field: field(entry.and_then(|e| e.file_field.as_ref()), "file"),
+            model_field: field(entry.and_then(|e| e.model_field.as_ref()), "model"),
+            language_field: field(entry.and_then(|e| e.language_field.as_ref()), "language"),
+        }
+    }
+
+    fn authorize(
+        &self,
+        req: reqwest::RequestBuilder,
+        api_key: Option<&str>,
+    ) -> reqwest::RequestBuilder {
+        match (self.auth, api_key) {
+            (AuthStyle::Bearer, Some(key)) => {
+                req.header("authorization", format!("Bearer {}", key))
+            }
+            (AuthStyle::XApiKey, Some(key)) => req.header("x-api-key", key),
+            _ => req,
+        }
+    }
+}
+
+/// How to run whisper.cpp for the local backend
+pub struct LocalOptions {
+    pub binary: String,
+    pub model: PathBuf,
+    pub threads: Option<usize>,
+    pub accelerator: Accelerator,
+    pub gpu_device: Option<u32>,
 }
 
 impl Backend {
+    /// Default cloud backend from environment variables
+    pub fn from_env(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        if config.mock.enabled {
+            Self::named("mock", config)
+        } else if std::env::var("REC_API_KEY").is_ok() && std::env::var("REC_API_URL").is_ok() {
+            Self::named("rec-api", config)
+        } else if std::env::var("MISTRAL_API_KEY").is_ok() {
+            Self::named("mistral", config)
+        } else if std::env::var("OPENAI_API_KEY").is_ok() {
+            Self::named("openai", config)
+        } else if std::env::var("ELEVENLABS_API_KEY").is_ok() {
+            Self::named("elevenlabs", config)
+        } else if std::env::var("AZURE_SPEECH_KEY").is_ok() {
+            Self::named("azure", config)
+        } else if std::env::var("GOOGLE_API_KEY").is_ok()
+            || std::env::var("GOOGLE_APPLICATION_CREDENTIALS").is_ok()
+        {
+            Self::named("google", config)
+        } else {
+            Err("Set REC_API_KEY + REC_API_URL, MISTRAL_API_KEY, OPENAI_API_KEY, ELEVENLABS_API_KEY, AZURE_SPEECH_KEY, GOOGLE_APPLICATION_CREDENTIALS or GOOGLE_API_KEY".into())
+        }
+    }
+
+    /// Built-in backend ('mistral', 'openai', 'elevenlabs', 'azure', 'google', 'aws', 'rec-api', 'local', 'vosk', 'mock') or custom entry from config
+    pub fn named(name: &str, config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let entry = config.backends.get(name);
+        let env = |var: &str| std::env::var(var).map_err(|_| format!("{} not set", var));
+
+        match name {
+            "mistral" => Ok(Backend::Mistral {
+                api_key: env("MISTRAL_API_KEY")?,
+            }),
+            "openai" => Ok(Backend::OpenAI {
+                api_key: env("OPENAI_API_KEY")?,
+                model: entry.and_then(|e| e.model.clone()).unwrap_or_else(|| {
+                    match entry.is_some_and(|e| e.diarize) {
+                        true => OPENAI_DIARIZE_MODEL.to_string(),
+                        false => OPENAI_MODEL.to_string(),
+                    }
+                }),
+                response_format: entry.and_then(|e| e.response_format.clone()),
+            }),
+            "elevenlabs" => Ok(Backend::ElevenLabs {
+                api_key: env("ELEVENLABS_API_KEY")?,
+                model: entry
+                    .and_then(|e| e.model.clone())
+                    .unwrap_or_else(|| ELEVENLABS_MODEL.to_string()),
+                diarize: entry.is_some_and(|e| e.diarize),
+            }),
+            "azure" => Ok(Backend::Azure {
+                region: env("AZURE_SPEECH_REGION")?,
+                api_key: env("AZURE_SPEECH_KEY")?,
+            }),
+            "aws" => Ok(Backend::Aws {
+                credentials: aws::Credentials::from_env()?,
+                region: entry
+                    .and_then(|e| e.region.clone())
+                    .or_else(|| std::env::var("AWS_REGION").ok())
+                    .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok())
+                    .ok_or("No AWS region: set backends.aws.region in config or AWS_REGION")?,
+                bucket: entry.and_then(|e| e.bucket.clone()).ok_or(
+                    "AWS Transcribe reads audio from S3: set backends.aws.bucket in config",
+                )?,
+                diarize: entry.is_some_and(|e| e.diarize),
+            }),
+            // A service account is preferred: API keys can't be limited to one user
+            "google" => Ok(Backend::Google {
+                credentials: match (
+                    std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS"),
+                    std::env::var("GOOGLE_API_KEY"),
+                ) {
+                    (Some(path), _) => Credentials::ServiceAccount(path.into()),
+                    (None, Ok(key)) => Credentials::ApiKey(key),
+                    (None, Err(_)) => {
+                        return Err(
+                            "Set GOOGLE_APPLICATION_CREDENTIALS (service account key file) or GOOGLE_API_KEY"
+                                .into(),
+                        );
+                    }
+                },
+                model: entry.and_then(|e| e.model.clone()),
+            }),
+            "rec-api" => Ok(Backend::RecApi {
+                api_url: env("REC_API_URL")?,
+                api_key: env("REC_API_KEY")?,
+                style: RequestStyle::new(AuthStyle::Bearer, entry),
+            }),
+            "local" => {
+                let model = config.local.model_path().ok_or(
+                    "No local model: run `rec model default <name>` or set local.model in config",
+                )?;
+
+                Ok(Backend::Local(LocalOptions {
+                    binary: config.local.binary.clone(),
+                    model,
+                    threads: config.local.threads,
+                    accelerator: config.local.accelerator.resolve(),
+                    gpu_device: config.local.gpu_device,
+                }))
+            }
+            "vosk" => {
+                let model_dir = config.vosk.model_dir.clone().ok_or(
+                    "No Vosk model: download one from https://alphacephei.com/vosk/models and set vosk.model_dir in config",
+                )?;
+                if !model_dir.is_dir() {
+                    return Err(
+                        format!("Vosk model directory not found: {}", model_dir.display()).into(),
+                    );
+                }
+                Ok(Backend::Vosk {
+                    python: config.vosk.python.clone(),
+                    model_dir,
+                })
+            }
+            "mock" => Ok(Backend::Mock {
+                text: config.mock.text.clone(),
+                latency: Duration::from_millis(config.mock.latency_ms),
+            }),
+            _ => {
+                let entry = entry.ok_or(format!(
+                    "Unknown backend: {} (expected mistral, openai, elevenlabs, azure, google, aws, rec-api, local, vosk, mock or an entry of 'backends' in config)",
+                    name
+                ))?;
+                if let Some(command) = &entry.command {
+                    return Ok(Backend::Command {
+                        name: name.to_string(),
+                        command: command.clone(),
+                        model: entry.model.clone(),
+                    });
+                }
+                let url = entry.url.clone().ok_or(format!(
+                    "Backend '{}' has no url or command in config",
+                    name
+                ))?;
+                let api_key = match &entry.api_key_env {
+                    Some(var) => Some(env(var)?),
+                    None => None,
+                };
+
+                Ok(Backend::Compatible {
+                    name: name.to_string(),
+                    url,
+                    api_key,
+                    model: entry.model.clone(),
+                    response_format: entry
+                        .response_format
+                        .clone()
+                        .or_else(|| entry.diarize.then(|| "diarized_json".to_string())),
+                    style: RequestStyle::new(AuthStyle::Bearer, Some(entry)),
+                })
+            }
+        }
+    }
+
+    /// Whether transcription runs on this machine rather than through a service
+    pub fn is_local(&self) -> bool {
+        matches!(self, Backend::Local(_) | Backend::Vosk { .. })
+    }
+
+    /// Short name for messages
+    pub fn name(&self) -> &str {
+        match self {
+            Backend::Mistral { .. } => "mistral",
+            Backend::OpenAI { .. } => "openai",
+            Backend::ElevenLabs { .. } => "elevenlabs",
+            Backend::Azure { .. } => "azure",
+            Backend::Google { .. } => "google",
+            Backend::Aws { .. } => "aws",
+            Backend::RecApi { .. } => "rec-api",
+            Backend::Compatible { name, .. } | Backend::Command { name, .. } => name,
+            Backend::Local(_) => "local",
+            Backend::Vosk { .. } => "vosk",
+            Backend::Mock { .. } => "mock",
+        }
+    }
+
+    /// The same backend with another model (--model)
+    ///
+    /// Mistral and the rec API get the model with each request instead, and
+    /// backends without a model choice are left as they are. For the local
+    /// backend, the model is a path or the name of a downloaded model.
+    pub fn with_model(mut self, name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        match &mut self {
+            Backend::OpenAI { model, .. } | Backend::ElevenLabs { model, .. } => {
+                *model = name.to_string();
+            }
+            Backend::Google { model, .. }
+            | Backend::Compatible { model, .. }
+            | Backend::Command { model, .. } => *model = Some(name.to_string()),
+            Backend::Local(options) => {
+                options.model = match crate::config::is_model_name(std::path::Path::new(name)) {
+                    true => crate::models::model_path(name)?,
+                    false => name.into(),
+                };
+            }
+            _ => {}
+        }
+        Ok(self)
+    }
+
+    /// What this backend supports and the audio it takes
+    pub fn capabilities(&self) -> Capabilities {
+        match self {
+            Backend::Mistral { .. } | Backend::RecApi { .. } => Capabilities {
+                timestamps: matches!(self, Backend::RecApi { .. }),
+                bias: true,
+                model: true,
+                ..Default::default()
+            },
+            Backend::OpenAI {
+                model,
+                response_format,
+                ..
+            } => {
+                let format = openai_format(model, response_format.as_deref());
+                Capabilities {
+                    timestamps: format == "verbose_json" || format == "diarized_json",
+                    diarization: format == "diarized_json",
+                    // The diarizing model takes no prompt
+                    bias: format != "diarized_json",
+                    model: true,
+                    prompt: format != "diarized_json",
+                    ..Default::default()
+                }
+            }
+            Backend::ElevenLabs { diarize, .. } => Capabilities {
+                timestamps: true,
+                diarization: *diarize,
+                model: true,
+                ..Default::default()
+            },
+            Backend::Azure { .. } => Capabilities {
+                max_secs: Some(AZURE_MAX_SECS),
+                model_rate: true,
+                wav_only: true,
+                ..Default::default()
+            },
+            Backend::Google { .. } => Capabilities {
+                timestamps: true,
+                bias: true,
+                max_secs: Some(GOOGLE_MAX_SECS),
+                model_rate: true,
+                wav_only: true,
+                model: true,
+                ..Default::default()
+            },
+            Backend::Aws { diarize, .. } => Capabilities {
+                timestamps: true,
+                diarization: *diarize,
+                bias: true,
+                wav_only: true,
+                ..Default::default()
+            },
+            Backend::Compatible {
+                response_format, ..
+            } => {
+                let format = response_format.as_deref().unwrap_or_default();
+                Capabilities {
+                    timestamps: format.contains("verbose") || format.contains("diarized"),
+                    diarization: format.contains("diarized"),
+                    bias: !format.contains("diarized"),
+                    model: true,
+                    prompt: !format.contains("diarized"),
+                    ..Default::default()
+                }
+            }
+            // Models take 16 kHz mono, and older whisper.cpp builds refuse
+            // anything else; other files are left for whisper.cpp to decode
+            Backend::Local(_) => Capabilities {
+                timestamps: true,
+                bias: true,
+                model_rate: true,
+                model: true,
+                prompt: true,
+                ..Default::default()
+            },
+            // Whatever the program makes of what it is given
+            Backend::Command { .. } => Capabilities {
+                timestamps: true,
+                diarization: true,
+                bias: true,
+                model: true,
+                prompt: true,
+                ..Default::default()
+            },
+            Backend::Vosk { .. } => Capabilities {
+                timestamps: true,
+                model_rate: true,
+                wav_only: true,
+                ..Default::default()
+            },
+            Backend::Mock { .. } => Capabilities::default(),
+        }
+    }
+
     pub async fn transcribe(
         &self,
-        opts: TranscribeOptions,
-    ) -> Result<String, Box<dyn std::error::Error>> {
+        mut opts: TranscribeOptions,
+    ) -> Result<Transcript, Box<dyn std::error::Error>> {
+        let capabilities = self.capabilities();
+        if capabilities.model_rate {
+            match crate::enhance::to_model_rate(&opts.wav_data) {
+                Ok(wav) => opts.wav_data = wav,
+                Err(e) if capabilities.wav_only => {
+                    return Err(format!("{} needs WAV audio: {}", self.name(), e).into());
+                }
+                Err(_) => {}
+            }
+        }
+
         match self {
             Backend::Mistral { api_key } => transcribe_mistral(&opts, api_key).await,
-            Backend::RecApi { api_url, api_key } => {
-                transcribe_rec_api(&opts, api_url, api_key).await
+            Backend::OpenAI {
+                api_key,
+                model,
+                response_format,
+            } => transcribe_openai(&opts, api_key, model, response_format.as_deref()).await,
+            Backend::ElevenLabs {
+                api_key,
+                model,
+                diarize,
+            } => transcribe_elevenlabs(&opts, api_key, model, *diarize).await,
+            Backend::Azure { region, api_key } => transcribe_azure(&opts, region, api_key).await,
+            Backend::Google { credentials, model } => {
+                transcribe_google(&opts, credentials, model.as_deref()).await
+            }
+            Backend::Aws {
+                credentials,
+                region,
+                bucket,
+                diarize,
+            } => transcribe_aws(&opts, credentials, region, bucket, *diarize).await,
+            Backend::RecApi {
+                api_url,
+                api_key,
+                style,
+            } => transcribe_rec_api(&opts, api_url, api_key, style).await,
+            Backend::Compatible {
+                name,
+                url,
+                api_key,
+                model,
+                response_format,
+                style,
+            } => {
+                transcribe_compatible(
+                    &opts,
+                    name,
+                    url,
+                    api_key.as_deref(),
+                    model.as_deref(),
+                    response_format.as_deref(),
+                    style,
+                )
+                .await
+            }
+            Backend::Local(local) => transcribe_local(&opts, local).await,
+            Backend::Command {
+                name,
+                command,
+                model,
+            } => transcribe_command(&opts, name, command, model.as_deref()).await,
+            Backend::Vosk { python, model_dir } => transcribe_vosk(&opts, python, model_dir).await,
+            Backend::Mock { text, latency } => {
+                tokio::time::sleep(*latency).await;
+                Ok(Transcript::from_text(
+                    text.clone()
+                        .unwrap_or_else(|| describe_audio(&opts.wav_data)),
+                ))
             }
         }
     }
 }
 
+/// Mock transcription saying how much audio was received
+fn describe_audio(wav_data: &[u8]) -> String {
+    match crate::audio::wav_duration(wav_data) {
+        Some(seconds) => format!("Mock transcription of {:.1} seconds of audio.", seconds),
+        None => format!("Mock transcription of {} bytes of audio.", wav_data.len()),
+    }
+}
+
 async fn transcribe_mistral(
     opts: &TranscribeOptions,
     api_key: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
+) -> Result<Transcript, Box<dyn std::error::Error>> {
     let client = reqwest::Client::new();
     let mut form = multipart::Form::new()
         .part(
@@ -49,37 +764,139 @@ async fn transcribe_mistral(
         .text("model", opts.model.clone());
 
     if let Some(lang) = &opts.language {
-        form = form.text("language", lang.clone());
+        form = form.text("language", language::code(lang).to_string());
     }
 
     for term in &opts.context_bias {
         form = form.text("context_bias", term.clone());
     }
 
-    let resp = client
-        .post(MISTRAL_URL)
-        .header("x-api-key", api_key)
-        .multipart(form)
-        .send()
-        .await?;
+    let resp = http::send(
+        "mistral",
+        client
+            .post(MISTRAL_URL)
+            .header("x-api-key", api_key)
+            .multipart(form),
+    )
+    .await?;
 
-    if !resp.status().is_success() {
-        let body = resp.text().await?;
-        return Err(format!("Mistral API error: {}", body).into());
+    if !resp.status.is_success() {
+        return Err(resp.error(format!("Mistral API error: {}", resp.body)));
     }
 
-    let result: TranscriptionResponse = resp.json().await?;
-    Ok(result.text)
+    let result: TranscriptionResponse = serde_json::from_str(&resp.body)?;
+    Ok(result.into())
 }
 
-async fn transcribe_rec_api(
+/// Response format asked of OpenAI, unless configured: `verbose_json` where supported
+fn openai_format<'a>(model: &str, response_format: Option<&'a str>) -> &'a str {
+    response_format.unwrap_or(if model.starts_with("whisper") {
+        "verbose_json"
+    } else if model.contains("diarize") {
+        "diarized_json"
+    } else {
+        "json"
+    })
+}
+
+/// Prompt for Whisper-style decoding: the description of what is dictated,
+/// then the custom words, spelled as they should come out
+fn whisper_prompt(opts: &TranscribeOptions) -> Option<String> {
+    let words = (!opts.context_bias.is_empty()).then(|| opts.context_bias.join(", "));
+    match (opts.prompt.as_deref(), words) {
+        (Some(prompt), Some(words)) => Some(format!("{} {}", prompt.trim(), words)),
+        (Some(prompt), None) => Some(prompt.trim().to_string()),
+        (None, words) => words,
+    }
+}
+
+/// OpenAI transcription: segments and confidences come with `verbose_json`,
+/// which only whisper-1 supports; the gpt-4o models answer `json` with text only
+async fn transcribe_openai(
     opts: &TranscribeOptions,
-    api_url: &str,
     api_key: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
+    model: &str,
+    response_format: Option<&str>,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    let format = openai_format(model, response_format);
+
     let client = reqwest::Client::new();
-    let url = format!("{}/api/transcribe", api_url.trim_end_matches('/'));
+    let mut form = multipart::Form::new()
+        .part(
+            "file",
+            multipart::Part::bytes(opts.wav_data.clone())
+                .file_name("audio.wav")
+                .mime_str("audio/wav")?,
+        )
+        .text("model", model.to_string())
+        .text("response_format", format.to_string());
+
+    if let Some(lang) = &opts.language {
+        form = form.text("language", language::code(lang).to_string());
+    }
+
+    // Required past 30 seconds by the diarizing model, which takes no prompt
+    if format == "diarized_json" {
+        form = form.text("chunking_strategy", "auto");
+    } else if let Some(prompt) = whisper_prompt(opts) {
+        // No vocabulary field, but the prompt steers spelling
+        form = form.text("prompt", prompt);
+    }
+
+    let resp = http::send(
+        "openai",
+        client.post(OPENAI_URL).bearer_auth(api_key).multipart(form),
+    )
+    .await?;
+
+    if !resp.status.is_success() {
+        return Err(resp.error(format!("OpenAI API error: {}", resp.body)));
+    }
+
+    // 'text', 'srt' and 'vtt' are not JSON
+    if !format.contains("json") {
+        return Ok(Transcript::from_text(resp.body.trim().to_string()));
+    }
+    let result: TranscriptionResponse = serde_json::from_str(&resp.body)?;
+    Ok(result.into())
+}
 
+/// ElevenLabs response: the text, and every word, space and sound event
+#[derive(Deserialize)]
+struct ElevenLabsResponse {
+    text: String,
+    #[serde(default)]
+    words: Vec<ElevenLabsWord>,
+}
+
+#[derive(Deserialize)]
+struct ElevenLabsWord {
+    text: String,
+    #[serde(default)]
+    start: f32,
+    #[serde(default)]
+    end: f32,
+    /// 'word', 'spacing' or 'audio_event'
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    speaker_id: Option<String>,
+    #[serde(default)]
+    logprob: Option<f32>,
+}
+
+/// ElevenLabs Scribe transcription
+///
+/// Scribe times every word rather than segments, so words are grouped
+/// into a segment per sentence, split further at speaker changes when
+/// diarizing. Sound events like "(laughter)" are not asked for.
+async fn transcribe_elevenlabs(
+    opts: &TranscribeOptions,
+    api_key: &str,
+    model: &str,
+    diarize: bool,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    let client = reqwest::Client::new();
     let mut form = multipart::Form::new()
         .part(
             "file",
@@ -87,28 +904,1023 @@ async fn transcribe_rec_api(
                 .file_name("audio.wav")
                 .mime_str("audio/wav")?,
         )
-        .text("model", opts.model.clone());
+        .text("model_id", model.to_string())
+        .text("tag_audio_events", "false")
+        .text("diarize", diarize.to_string());
+
+    // ISO 639 code: 'fr' rather than 'fr-CA'
+    if let Some(lang) = &opts.language {
+        form = form.text("language_code", language::code(lang).to_string());
+    }
+
+    let resp = http::send(
+        "elevenlabs",
+        client
+            .post(ELEVENLABS_URL)
+            .header("xi-api-key", api_key)
+            .multipart(form),
+    )
+    .await?;
+
+    if !resp.status.is_success() {
+        return Err(resp.error(format!(
+            "ElevenLabs API error ({}): {}",
+            resp.status, resp.body
+        )));
+    }
+
+    let result: ElevenLabsResponse = serde_json::from_str(&resp.body)?;
+    Ok(Transcript {
+        segments: elevenlabs_segments(&result.words),
+        text: result.text.trim().to_string(),
+    })
+}
+
+/// Words grouped by sentence and speaker, with the mean word confidence
+fn elevenlabs_segments(words: &[ElevenLabsWord]) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut current: Vec<&ElevenLabsWord> = Vec::new();
+
+    let mut flush = |current: &mut Vec<&ElevenLabsWord>| {
+        let spoken: Vec<&&ElevenLabsWord> = current.iter().filter(|w| w.kind == "word").collect();
+        if let (Some(first), Some(last)) = (spoken.first(), spoken.last()) {
+            let logprobs: Vec<f32> = spoken.iter().filter_map(|w| w.logprob).collect();
+            segments.push(Segment {
+                start: first.start,
+                end: last.end,
+                text: current
+                    .iter()
+                    .map(|w| w.text.as_str())
+                    .collect::<String>()
+                    .trim()
+                    .to_string(),
+                confidence: (!logprobs.is_empty())
+                    .then(|| (logprobs.iter().sum::<f32>() / logprobs.len() as f32).exp()),
+                speaker: first.speaker_id.clone(),
+            });
+        }
+        current.clear();
+    };
+
+    for word in words.iter().filter(|w| w.kind != "audio_event") {
+        let turn = word.kind == "word"
+            && current
+                .iter()
+                .rfind(|w| w.kind == "word")
+                .is_some_and(|last| last.speaker_id != word.speaker_id);
+        if turn {
+            flush(&mut current);
+        }
+        // Spacing doesn't start a segment
+        if current.is_empty() && word.kind != "word" {
+            continue;
+        }
+        current.push(word);
+        if word.kind == "word" && word.text.ends_with(['.', '?', '!']) {
+            flush(&mut current);
+        }
+    }
+    flush(&mut current);
+    segments
+}
+
+/// Azure short-audio response (`format=detailed`)
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AzureResponse {
+    recognition_status: String,
+    #[serde(default)]
+    display_text: String,
+    /// In 100-nanosecond ticks
+    #[serde(default)]
+    offset: u64,
+    #[serde(default)]
+    duration: u64,
+    #[serde(default, rename = "NBest")]
+    n_best: Vec<AzureAlternative>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AzureAlternative {
+    confidence: f32,
+}
+
+/// Azure Speech REST API for short audio
+///
+/// It takes 16 kHz mono PCM (converted beforehand) and a full locale ('en-US'),
+/// authenticates with `Ocp-Apim-Subscription-Key`, and answers one result
+/// for the whole audio rather than segments.
+async fn transcribe_azure(
+    opts: &TranscribeOptions,
+    region: &str,
+    api_key: &str,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    let url = format!(
+        "https://{}.stt.speech.microsoft.com/speech/recognition/conversation/cognitiveservices/v1?language={}&format=detailed",
+        region,
+        locale(opts.language.as_deref())
+    );
+
+    let resp = http::send(
+        "azure",
+        reqwest::Client::new()
+            .post(&url)
+            .header("Ocp-Apim-Subscription-Key", api_key)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                "audio/wav; codecs=audio/pcm; samplerate=16000",
+            )
+            .body(opts.wav_data.clone()),
+    )
+    .await?;
+
+    if !resp.status.is_success() {
+        return Err(resp.error(format!("Azure API error ({}): {}", resp.status, resp.body)));
+    }
+
+    let result: AzureResponse = serde_json::from_str(&resp.body)?;
+    match result.recognition_status.as_str() {
+        "Success" => {}
+        // No speech in the audio
+        "NoMatch" | "InitialSilenceTimeout" => return Ok(Transcript::default()),
+        status => return Err(format!("Azure recognition failed: {}", status).into()),
+    }
+
+    const TICKS: f32 = 10_000_000.0;
+    let segment = Segment {
+        start: result.offset as f32 / TICKS,
+        end: (result.offset + result.duration) as f32 / TICKS,
+        text: result.display_text.clone(),
+        confidence: result.n_best.first().map(|best| best.confidence),
+        speaker: None,
+    };
+    Ok(Transcript {
+        text: result.display_text,
+        segments: vec![segment],
+    })
+}
+
+/// Google recognition response
+#[derive(Deserialize)]
+struct GoogleResponse {
+    /// Missing when there was no speech
+    #[serde(default)]
+    results: Vec<GoogleResult>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleResult {
+    #[serde(default)]
+    alternatives: Vec<GoogleAlternative>,
+    /// Offset of the end of the result, like "3.500s"
+    #[serde(default)]
+    result_end_time: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GoogleAlternative {
+    #[serde(default)]
+    transcript: String,
+    #[serde(default)]
+    confidence: Option<f32>,
+}
+
+/// Google Cloud Speech-to-Text, synchronous recognition
+///
+/// Audio goes base64 in a JSON body as 16 kHz LINEAR16, with a full locale
+/// like Azure. Custom words become an inline phrase set, Google's
+/// equivalent of Mistral's context_bias. Each result (roughly a sentence)
+/// becomes a segment, ending at its `resultEndTime`.
+async fn transcribe_google(
+    opts: &TranscribeOptions,
+    credentials: &Credentials,
+    model: Option<&str>,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    use base64::Engine;
+
+    let mut config = serde_json::json!({
+        "encoding": "LINEAR16",
+        "sampleRateHertz": 16000,
+        "languageCode": locale(opts.language.as_deref()),
+        "enableAutomaticPunctuation": true,
+    });
+    if let Some(model) = model {
+        config["model"] = model.into();
+    }
+    if !opts.context_bias.is_empty() {
+        let phrases: Vec<_> = opts
+            .context_bias
+            .iter()
+            .map(|term| serde_json::json!({"value": term}))
+            .collect();
+        config["adaptation"] = serde_json::json!({
+            "phraseSets": [{"phrases": phrases, "boost": GOOGLE_BOOST}],
+        });
+    }
+    let body = serde_json::json!({
+        "config": config,
+        "audio": {"content": base64::engine::general_purpose::STANDARD.encode(&opts.wav_data)},
+    });
+
+    let client = reqwest::Client::new();
+    let req = credentials.authorize(&client, GOOGLE_URL).await?;
+    let resp = http::send("google", req.json(&body)).await?;
+
+    if !resp.status.is_success() {
+        return Err(resp.error(format!("Google API error ({}): {}", resp.status, resp.body)));
+    }
+
+    let result: GoogleResponse = serde_json::from_str(&resp.body)?;
+    let mut segments = Vec::new();
+    let mut start = 0.0;
+    for result in result.results {
+        let end = result
+            .result_end_time
+            .as_deref()
+            .and_then(|t| t.strip_suffix('s'))
+            .and_then(|t| t.parse().ok())
+            .unwrap_or(start);
+        if let Some(best) = result.alternatives.into_iter().next()
+            && !best.transcript.trim().is_empty()
+        {
+            segments.push(Segment {
+                start,
+                end,
+                text: best.transcript.trim().to_string(),
+                confidence: best.confidence,
+                speaker: None,
+            });
+        }
+        start = end;
+    }
+
+    Ok(Transcript {
+        text: segments
+            .iter()
+            .map(|s| s.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+        segments,
+    })
+}
+
+/// AWS Transcribe output file
+#[derive(Deserialize)]
+struct AwsOutput {
+    results: AwsResults,
+}
+
+#[derive(Deserialize)]
+struct AwsResults {
+    transcripts: Vec<AwsText>,
+    #[serde(default)]
+    items: Vec<AwsItem>,
+    /// Roughly a sentence each, in recent outputs
+    #[serde(default)]
+    audio_segments: Vec<AwsSegment>,
+}
+
+#[derive(Deserialize)]
+struct AwsText {
+    transcript: String,
+}
+
+/// Word or punctuation mark; numbers come as strings
+#[derive(Deserialize)]
+struct AwsItem {
+    #[serde(default)]
+    start_time: Option<String>,
+    alternatives: Vec<AwsAlternative>,
+}
+
+#[derive(Deserialize)]
+struct AwsAlternative {
+    confidence: String,
+}
+
+#[derive(Deserialize)]
+struct AwsSegment {
+    transcript: String,
+    start_time: String,
+    end_time: String,
+    /// `spk_0`, `spk_1`... when speakers are labelled
+    #[serde(default)]
+    speaker_label: Option<String>,
+}
+
+/// Seconds between two checks of a transcription job
+const AWS_POLL: Duration = Duration::from_secs(1);
+
+/// AWS Transcribe as a batch job
+///
+/// The audio is uploaded to the configured bucket through a presigned URL,
+/// transcribed by a job polled until it finishes, then both the object and
+/// the job are deleted. Custom words go in a custom vocabulary, which AWS
+/// takes a few minutes to prepare (see `aws_vocabulary`).
+async fn transcribe_aws(
+    opts: &TranscribeOptions,
+    credentials: &aws::Credentials,
+    region: &str,
+    bucket: &str,
+    diarize: bool,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    let client = reqwest::Client::new();
+    let stamp = chrono::Utc::now().format("%Y%m%d-%H%M%S-%f");
+    let job = format!("rec-{}-{}", stamp, std::process::id());
+    let key = format!("rec/{}.wav", job);
+    let language = locale(opts.language.as_deref());
+
+    // Nothing is left behind in the bucket or the job list, even when the
+    // watchdog drops the request midway
+    let cleanup = AwsCleanup {
+        client: client.clone(),
+        credentials: credentials.clone(),
+        region: region.to_string(),
+        bucket: bucket.to_string(),
+        key: key.clone(),
+        job: job.clone(),
+        done: false,
+    };
+
+    let url = aws::presign_s3(credentials, region, bucket, &key, "PUT");
+    let resp = http::send(
+        "aws-s3",
+        client
+            .put(&url)
+            .header(reqwest::header::CONTENT_TYPE, "audio/wav")
+            .body(opts.wav_data.clone()),
+    )
+    .await?;
+    if !resp.status.is_success() {
+        return Err(resp.error(format!(
+            "S3 upload to {} failed ({}): {}",
+            bucket, resp.status, resp.body
+        )));
+    }
+
+    let vocabulary = match opts.context_bias.is_empty() {
+        true => None,
+        false => aws_vocabulary(&client, credentials, region, &language, &opts.context_bias).await,
+    };
+    let result = run_aws_job(
+        &client,
+        credentials,
+        region,
+        &job,
+        &language,
+        bucket,
+        &key,
+        vocabulary,
+        diarize,
+    )
+    .await
+    // Held across the cleanup below, which needs it Send
+    .map_err(|e| e.to_string());
+
+    cleanup.run().await;
+    Ok(result?)
+}
+
+/// Uploaded audio and transcription job to delete once a request ends
+///
+/// Deleted by [`AwsCleanup::run`] when the request finishes, or in the
+/// background when it is dropped before that.
+struct AwsCleanup {
+    client: reqwest::Client,
+    credentials: aws::Credentials,
+    region: String,
+    bucket: String,
+    key: String,
+    job: String,
+    done: bool,
+}
+
+impl AwsCleanup {
+    async fn run(mut self) {
+        self.done = true;
+        self.delete().await;
+    }
+
+    async fn delete(&self) {
+        let url = aws::presign_s3(
+            &self.credentials,
+            &self.region,
+            &self.bucket,
+            &self.key,
+            "DELETE",
+        );
+        if let Err(e) = http::send("aws-s3", self.client.delete(&url)).await {
+            eprintln!(
+                "Warning: Failed to delete s3://{}/{}: {}",
+                self.bucket, self.key, e
+            );
+        }
+        let body = serde_json::json!({"TranscriptionJobName": self.job});
+        let req = aws::json_request(
+            &self.client,
+            &self.credentials,
+            &self.region,
+            "transcribe",
+            "Transcribe.DeleteTranscriptionJob",
+            &body,
+        );
+        http::send("aws", req).await.ok();
+    }
+}
+
+impl Drop for AwsCleanup {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let cleanup = AwsCleanup {
+            client: self.client.clone(),
+            credentials: self.credentials.clone(),
+            region: self.region.clone(),
+            bucket: self.bucket.clone(),
+            key: self.key.clone(),
+            job: self.job.clone(),
+            done: true,
+        };
+        runtime.spawn(async move { cleanup.delete().await });
+    }
+}
+
+/// Start the transcription job, wait for it and read its output
+#[allow(clippy::too_many_arguments)]
+async fn run_aws_job(
+    client: &reqwest::Client,
+    credentials: &aws::Credentials,
+    region: &str,
+    job: &str,
+    language: &str,
+    bucket: &str,
+    key: &str,
+    vocabulary: Option<String>,
+    diarize: bool,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    let mut body = serde_json::json!({
+        "TranscriptionJobName": job,
+        "LanguageCode": language,
+        "MediaFormat": "wav",
+        "Media": {"MediaFileUri": format!("s3://{}/{}", bucket, key)},
+    });
+    if let Some(vocabulary) = vocabulary {
+        body["Settings"]["VocabularyName"] = vocabulary.into();
+    }
+    if diarize {
+        body["Settings"]["ShowSpeakerLabels"] = true.into();
+        body["Settings"]["MaxSpeakerLabels"] = AWS_MAX_SPEAKERS.into();
+    }
+    aws_call(client, credentials, region, "StartTranscriptionJob", &body).await?;
+
+    let body = serde_json::json!({"TranscriptionJobName": job});
+    let output_url = loop {
+        tokio::time::sleep(AWS_POLL).await;
+        let status = aws_call(client, credentials, region, "GetTranscriptionJob", &body).await?;
+        let job = &status["TranscriptionJob"];
+        match job["TranscriptionJobStatus"].as_str() {
+            Some("COMPLETED") => {
+                break job["Transcript"]["TranscriptFileUri"]
+                    .as_str()
+                    .ok_or("AWS job completed without a transcript")?
+                    .to_string();
+            }
+            Some("FAILED") => {
+                return Err(format!(
+                    "AWS transcription failed: {}",
+                    job["FailureReason"].as_str().unwrap_or("no reason given")
+                )
+                .into());
+            }
+            _ => {}
+        }
+    };
+
+    // Presigned by AWS, no signature needed
+    let resp = http::send("aws", client.get(&output_url)).await?;
+    if !resp.status.is_success() {
+        return Err(resp.error(format!(
+            "Failed to fetch the AWS transcript ({}): {}",
+            resp.status, resp.body
+        )));
+    }
+    let output: AwsOutput = serde_json::from_str(&resp.body)?;
+    Ok(aws_transcript(output.results))
+}
+
+/// Segments from `audio_segments`, with the mean confidence of their words
+fn aws_transcript(results: AwsResults) -> Transcript {
+    let words: Vec<(f32, f32)> = results
+        .items
+        .iter()
+        .filter_map(|item| {
+            let start = item.start_time.as_deref()?.parse().ok()?;
+            let confidence = item.alternatives.first()?.confidence.parse().ok()?;
+            Some((start, confidence))
+        })
+        .collect();
+
+    let segments = results
+        .audio_segments
+        .into_iter()
+        .map(|segment| {
+            let start: f32 = segment.start_time.parse().unwrap_or_default();
+            let end: f32 = segment.end_time.parse().unwrap_or(start);
+            let confidences: Vec<f32> = words
+                .iter()
+                .filter(|(time, _)| *time >= start && *time < end)
+                .map(|(_, confidence)| *confidence)
+                .collect();
+            Segment {
+                start,
+                end,
+                text: segment.transcript.trim().to_string(),
+                confidence: (!confidences.is_empty())
+                    .then(|| confidences.iter().sum::<f32>() / confidences.len() as f32),
+                speaker: segment.speaker_label,
+            }
+        })
+        .collect();
+
+    Transcript {
+        text: results
+            .transcripts
+            .into_iter()
+            .map(|t| t.transcript)
+            .collect::<Vec<_>>()
+            .join(" "),
+        segments,
+    }
+}
+
+/// Custom vocabulary holding `words`, if AWS has it ready
+///
+/// Vocabularies are named after their words, so each list is created once.
+/// A new one takes AWS a few minutes to prepare, and dictations until then
+/// go without it rather than waiting.
+async fn aws_vocabulary(
+    client: &reqwest::Client,
+    credentials: &aws::Credentials,
+    region: &str,
+    language: &str,
+    words: &[String],
+) -> Option<String> {
+    // Phrases can't hold spaces or digits
+    let phrases: Vec<String> = words
+        .iter()
+        .map(|word| {
+            word.split_whitespace()
+                .collect::<Vec<_>>()
+                .join("-")
+                .chars()
+                .filter(|c| c.is_alphabetic() || matches!(c, '-' | '.' | '\''))
+                .collect::<String>()
+        })
+        .filter(|phrase| !phrase.is_empty())
+        .collect();
+    if phrases.is_empty() {
+        return None;
+    }
+    let hash = aws::sha256_hex(phrases.join("\n").as_bytes());
+    let name = format!("rec-{}-{}", language, &hash[..16]);
+
+    let body = serde_json::json!({"VocabularyName": name});
+    let state = match aws_call(client, credentials, region, "GetVocabulary", &body).await {
+        Ok(vocabulary) => vocabulary["VocabularyState"].as_str().map(str::to_string),
+        Err(_) => None,
+    };
+    match state.as_deref() {
+        Some("READY") => return Some(name),
+        Some("PENDING") => return None,
+        Some(_) => {
+            eprintln!(
+                "Warning: AWS could not build the custom vocabulary {} (check the custom words)",
+                name
+            );
+            return None;
+        }
+        None => {}
+    }
+
+    let body = serde_json::json!({
+        "VocabularyName": name,
+        "LanguageCode": language,
+        "Phrases": phrases,
+    });
+    match aws_call(client, credentials, region, "CreateVocabulary", &body).await {
+        Ok(_) => eprintln!(
+            "AWS is preparing a custom vocabulary for your custom words: it applies to dictations in a few minutes"
+        ),
+        Err(e) => eprintln!("Warning: Failed to create an AWS custom vocabulary: {}", e),
+    }
+    None
+}
+
+/// Call a Transcribe action and return its JSON answer
+async fn aws_call(
+    client: &reqwest::Client,
+    credentials: &aws::Credentials,
+    region: &str,
+    action: &str,
+    body: &serde_json::Value,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let target = format!("Transcribe.{}", action);
+    let req = aws::json_request(client, credentials, region, "transcribe", &target, body);
+    let resp = http::send("aws", req).await?;
+    if !resp.status.is_success() {
+        return Err(resp.error(format!(
+            "AWS {} failed ({}): {}",
+            action, resp.status, resp.body
+        )));
+    }
+    Ok(serde_json::from_str(&resp.body)?)
+}
+
+/// Multipart form for a server with custom field names
+fn styled_form(
+    opts: &TranscribeOptions,
+    model: &str,
+    style: &RequestStyle,
+) -> Result<multipart::Form, Box<dyn std::error::Error>> {
+    let mut form = multipart::Form::new()
+        .part(
+            style.file_field.clone(),
+            multipart::Part::bytes(opts.wav_data.clone())
+                .file_name("audio.wav")
+                .mime_str("audio/wav")?,
+        )
+        .text(style.model_field.clone(), model.to_string());
 
     if let Some(lang) = &opts.language {
-        form = form.text("language", lang.clone());
+        form = form.text(
+            style.language_field.clone(),
+            language::code(lang).to_string(),
+        );
     }
 
+    Ok(form)
+}
+
+async fn transcribe_rec_api(
+    opts: &TranscribeOptions,
+    api_url: &str,
+    api_key: &str,
+    style: &RequestStyle,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/api/transcribe", api_url.trim_end_matches('/'));
+
+    let mut form = styled_form(opts, &opts.model, style)?;
+
     for term in &opts.context_bias {
         form = form.text("context_bias", term.clone());
     }
 
-    let resp = client
-        .post(&url)
-        .header("authorization", format!("Bearer {}", api_key))
-        .multipart(form)
-        .send()
-        .await?;
+    let req = style
+        .authorize(client.post(&url), Some(api_key))
+        .multipart(form);
+    let resp = match style.auth {
+        AuthStyle::Hmac => http::send_signed("rec-api", req, api_key).await?,
+        _ => http::send("rec-api", req).await?,
+    };
+
+    if !resp.status.is_success() {
+        return Err(resp.error(format!("Rec API error: {}", resp.body)));
+    }
+
+    let result: TranscriptionResponse = serde_json::from_str(&resp.body)?;
+    Ok(result.into())
+}
+
+async fn transcribe_compatible(
+    opts: &TranscribeOptions,
+    name: &str,
+    url: &str,
+    api_key: Option<&str>,
+    model: Option<&str>,
+    response_format: Option<&str>,
+    style: &RequestStyle,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    let client = reqwest::Client::new();
+    let mut form = styled_form(opts, model.unwrap_or(&opts.model), style)?;
+
+    if let Some(format) = response_format {
+        form = form.text("response_format", format.to_string());
+    }
+    if let Some(prompt) = whisper_prompt(opts)
+        && !response_format.is_some_and(|format| format.contains("diarized"))
+    {
+        form = form.text("prompt", prompt);
+    }
+
+    let resp = http::send(
+        name,
+        style.authorize(client.post(url), api_key).multipart(form),
+    )
+    .await?;
+
+    if !resp.status.is_success() {
+        return Err(resp.error(format!("{} API error: {}", name, resp.body)));
+    }
+
+    let result: TranscriptionResponse = serde_json::from_str(&resp.body)?;
+    Ok(result.into())
+}
+
+/// Transcription by a user program
+///
+/// The WAV goes to its stdin, with the language, the entry's model and
+/// custom words in `REC_LANGUAGE`, `REC_MODEL` and `REC_CONTEXT_BIAS` (one
+/// per line), and the --prompt in `REC_PROMPT`, when set. It prints either plain text, or a JSON object
+/// shaped like OpenAI's `verbose_json` (`text`, and optionally `segments`
+/// with `start`, `end`, `text`, `confidence` and `speaker`).
+async fn transcribe_command(
+    opts: &TranscribeOptions,
+    name: &str,
+    command: &str,
+    model: Option<&str>,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut cmd = tokio::process::Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .env("REC_CONTEXT_BIAS", opts.context_bias.join("\n"))
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        // Killed when the watchdog gives up on it
+        .kill_on_drop(true);
+    for (var, value) in [
+        ("REC_LANGUAGE", opts.language.as_deref()),
+        ("REC_MODEL", model),
+        ("REC_PROMPT", opts.prompt.as_deref()),
+    ] {
+        match value {
+            Some(value) => cmd.env(var, value),
+            None => cmd.env_remove(var),
+        };
+    }
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("Failed to run the {} backend: {}", name, e))?;
+
+    // Write stdin while the program runs, so its output can't fill the other pipe
+    if let Some(mut pipe) = child.stdin.take() {
+        let wav_data = opts.wav_data.clone();
+        tokio::spawn(async move {
+            let _ = pipe.write_all(&wav_data).await;
+        });
+    }
+    let output = child.wait_with_output().await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "{} backend exited with {}: {}",
+            name,
+            output.status,
+            stderr.trim().lines().last().unwrap_or_default()
+        )
+        .into());
+    }
 
-    if !resp.status().is_success() {
-        let body = resp.text().await?;
-        return Err(format!("Rec API error: {}", body).into());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = stdout.trim();
+    if stdout.starts_with('{') {
+        let result: TranscriptionResponse = serde_json::from_str(stdout)
+            .map_err(|e| format!("{} backend printed invalid JSON: {}", name, e))?;
+        Ok(result.into())
+    } else {
+        Ok(Transcript::from_text(stdout.to_string()))
     }
+}
+
+/// One utterance recognized by Vosk
+#[derive(Deserialize)]
+struct VoskResult {
+    #[serde(default)]
+    text: String,
+    /// Words with their timing, as asked with SetWords
+    #[serde(default)]
+    result: Vec<VoskWord>,
+}
+
+#[derive(Deserialize)]
+struct VoskWord {
+    start: f32,
+    end: f32,
+    conf: f32,
+}
+
+/// Vosk, offline: the model decides the language, and custom words can't
+/// be passed. Each utterance becomes a segment.
+async fn transcribe_vosk(
+    opts: &TranscribeOptions,
+    python: &str,
+    model_dir: &std::path::Path,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut child = tokio::process::Command::new(python)
+        .arg("-c")
+        .arg(crate::vosk::SCRIPT)
+        .arg(model_dir)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        // Killed when dropped, rather than left running with the model loaded
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", python, e))?;
+
+    // The script reads all of stdin before it prints anything. If it fails
+    // first (no vosk package), writing breaks the pipe and stderr says why
+    let mut stdin = child.stdin.take().ok_or("No stdin")?;
+    stdin.write_all(&opts.wav_data).await.ok();
+    drop(stdin);
+    let output = child.wait_with_output().await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let hint = match stderr.contains("No module named 'vosk'") {
+            true => format!(" (install it with `{} -m pip install vosk`)", python),
+            false => String::new(),
+        };
+        return Err(format!(
+            "Vosk error: {}{}",
+            stderr.trim().lines().last().unwrap_or_default(),
+            hint
+        )
+        .into());
+    }
+
+    let results: Vec<VoskResult> = serde_json::from_slice(&output.stdout)?;
+    let segments: Vec<Segment> = results
+        .into_iter()
+        .filter(|r| !r.text.trim().is_empty())
+        .map(|r| {
+            let confidence = (!r.result.is_empty())
+                .then(|| r.result.iter().map(|w| w.conf).sum::<f32>() / r.result.len() as f32);
+            Segment {
+                start: r.result.first().map(|w| w.start).unwrap_or_default(),
+                end: r.result.last().map(|w| w.end).unwrap_or_default(),
+                text: r.text.trim().to_string(),
+                confidence,
+                speaker: None,
+            }
+        })
+        .collect();
+
+    Ok(Transcript {
+        text: segments
+            .iter()
+            .map(|s| s.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+        segments,
+    })
+}
+
+/// whisper.cpp `--output-json-full` output
+#[derive(Deserialize)]
+struct WhisperOutput {
+    transcription: Vec<WhisperSegment>,
+}
+
+#[derive(Deserialize)]
+struct WhisperSegment {
+    offsets: WhisperOffsets,
+    text: String,
+    #[serde(default)]
+    tokens: Vec<WhisperToken>,
+}
+
+#[derive(Deserialize)]
+struct WhisperOffsets {
+    from: u64,
+    to: u64,
+}
+
+#[derive(Deserialize)]
+struct WhisperToken {
+    text: String,
+    p: f32,
+}
+
+/// Temp file path without extension, unique to this call: transcriptions
+/// can run side by side (interview tracks, --compare, watchdog retries)
+fn temp_base(prefix: &str) -> std::path::PathBuf {
+    static COUNT: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+    let n = COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    std::env::temp_dir().join(format!("{}-{}-{}", prefix, std::process::id(), n))
+}
+
+/// Temp files removed when dropped, including with a transcription the
+/// watchdog gave up on
+struct TempFiles(Vec<PathBuf>);
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
+
+async fn transcribe_local(
+    opts: &TranscribeOptions,
+    local: &LocalOptions,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    let binary = &local.binary;
+
+    // whisper.cpp reads audio from a file, so spool the WAV to disk
+    let base = temp_base("rec");
+    let wav_path = base.with_extension("wav");
+    let json_path = base.with_extension("json");
+    let _spooled = TempFiles(vec![wav_path.clone(), json_path.clone()]);
+    tokio::fs::write(&wav_path, &opts.wav_data).await?;
+
+    let mut cmd = tokio::process::Command::new(binary);
+    cmd.arg("--model")
+        .arg(&local.model)
+        .arg("--file")
+        .arg(&wav_path)
+        .arg("--output-json-full")
+        .arg("--output-file")
+        .arg(&base)
+        .arg("--no-prints");
+
+    if let Some(lang) = &opts.language {
+        cmd.arg("--language").arg(language::code(lang));
+    }
+
+    if let Some(prompt) = whisper_prompt(opts) {
+        cmd.arg("--prompt").arg(prompt);
+    }
+
+    if let Some(threads) = local.threads {
+        cmd.arg("--threads").arg(threads.to_string());
+    }
+
+    // The GPU API is fixed when whisper.cpp is built; we can only pick
+    // whether to use it and which device
+    if local.accelerator == Accelerator::Cpu {
+        cmd.arg("--no-gpu");
+    } else if let Some(device) = local.gpu_device {
+        cmd.arg("--device").arg(device.to_string());
+    }
+
+    // Killed if the transcription is dropped, rather than left writing to its files
+    cmd.kill_on_drop(true);
+    let output = cmd.output().await;
+
+    let output = output.map_err(|e| format!("Failed to run {}: {}", binary, e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("whisper.cpp error: {}", stderr.trim()).into());
+    }
+
+    let json = tokio::fs::read_to_string(&json_path).await?;
+    let result: WhisperOutput = serde_json::from_str(&json)?;
+
+    let segments: Vec<Segment> = result
+        .transcription
+        .into_iter()
+        .map(|s| {
+            // Special tokens ([_BEG_], [_TT_123]...) carry no meaning
+            let probs: Vec<f32> = s
+                .tokens
+                .iter()
+                .filter(|t| !t.text.starts_with("[_"))
+                .map(|t| t.p)
+                .collect();
+            let confidence =
+                (!probs.is_empty()).then(|| probs.iter().sum::<f32>() / probs.len() as f32);
+
+            Segment {
+                start: s.offsets.from as f32 / 1000.0,
+                end: s.offsets.to as f32 / 1000.0,
+                text: s.text.trim().to_string(),
+                confidence,
+                speaker: None,
+            }
+        })
+        .filter(|s| !s.text.is_empty())
+        .collect();
+
+    let text = segments
+        .iter()
+        .map(|s| s.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
 
-    let result: TranscriptionResponse = resp.json().await?;
-    Ok(result.text)
+    Ok(Transcript { text, segments })
 }
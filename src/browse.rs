@@ -0,0 +1,199 @@
+//! History and vocabulary at hand while recording
+//!
+//! With the recording's controls, `h` lists the latest dictations, `/`
+//! searches them and `w` adds a custom word, while the capture goes on. The
+//! recording loop only turns keys into requests: they are carried out by a
+//! task of their own, which sends back the lines to show, so reading history
+//! or saving the config never holds up the loop watching the recording.
+
+use crate::config::{self, Config};
+use tokio::sync::mpsc;
+
+/// Dictations listed by `h`, and at most found by `/`
+const SHOWN: usize = 5;
+
+/// Escape, closing a prompt
+const ESCAPE: u8 = 27;
+
+/// What a key asked for
+pub enum Request {
+    /// The latest dictations
+    Recent,
+    /// Dictations containing the text
+    Search(String),
+    /// A word for the vocabulary
+    AddWord(String),
+}
+
+/// Requests on their way to the task, and the lines it answers with
+pub struct Browser {
+    requests: mpsc::UnboundedSender<Request>,
+    answers: mpsc::UnboundedReceiver<Vec<String>>,
+}
+
+impl Browser {
+    /// Start the task carrying out requests, which ends with the browser
+    pub fn spawn() -> Self {
+        let (requests, mut received) = mpsc::unbounded_channel();
+        let (answer, answers) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(request) = received.recv().await {
+                let lines = tokio::task::spawn_blocking(move || carry_out(request))
+                    .await
+                    .unwrap_or_else(|e| vec![format!("Warning: {}", e)]);
+                if answer.send(lines).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { requests, answers }
+    }
+
+    /// Hand a request to the task
+    pub fn send(&self, request: Request) {
+        self.requests.send(request).ok();
+    }
+
+    /// Wait for the lines answering the next request
+    pub async fn answer(&mut self) -> Vec<String> {
+        match self.answers.recv().await {
+            Some(lines) => lines,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+/// Text typed after `/` or `w`, up to Enter
+pub struct Prompt {
+    label: &'static str,
+    request: fn(String) -> Request,
+    /// Bytes as typed, which may end in the middle of a character
+    typed: Vec<u8>,
+}
+
+/// Where a key leaves a prompt
+pub enum Typed {
+    Editing,
+    Done(Request),
+    Closed,
+}
+
+impl Prompt {
+    /// The prompt a key opens, if any
+    pub fn open(key: u8) -> Option<Self> {
+        let (label, request): (_, fn(String) -> Request) = match key {
+            b'/' => ("Search history", Request::Search),
+            b'w' => ("Add word", Request::AddWord),
+            _ => return None,
+        };
+        Some(Self {
+            label,
+            request,
+            typed: Vec::new(),
+        })
+    }
+
+    /// Status line showing the prompt and what was typed
+    pub fn line(&self) -> String {
+        format!(
+            "{} (Enter to confirm, Esc to close): {}",
+            self.label,
+            String::from_utf8_lossy(&self.typed)
+        )
+    }
+
+    /// Take a key typed while the prompt is open
+    pub fn key(&mut self, byte: u8) -> Typed {
+        match byte {
+            b'\r' | b'\n' => {
+                let text = String::from_utf8_lossy(&self.typed).trim().to_string();
+                match text.is_empty() {
+                    true => Typed::Closed,
+                    false => Typed::Done((self.request)(text)),
+                }
+            }
+            ESCAPE | crate::ptt::CTRL_C => Typed::Closed,
+            // Backspace and Delete take off a whole character
+            8 | 127 => {
+                while let Some(byte) = self.typed.pop() {
+                    if byte & 0xC0 != 0x80 {
+                        break;
+                    }
+                }
+                Typed::Editing
+            }
+            byte if byte >= b' ' => {
+                self.typed.push(byte);
+                Typed::Editing
+            }
+            _ => Typed::Editing,
+        }
+    }
+}
+
+/// Lines answering a request
+fn carry_out(request: Request) -> Vec<String> {
+    let result = match request {
+        Request::Recent => recent(),
+        Request::Search(text) => search(&text),
+        Request::AddWord(word) => add_word(word),
+    };
+    result.unwrap_or_else(|e| vec![format!("Warning: {}", e)])
+}
+
+fn recent() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let history = Config::load_history()?;
+    if history.is_empty() {
+        return Ok(vec!["No dictations yet".to_string()]);
+    }
+    let start = history.len().saturating_sub(SHOWN);
+    Ok(listed(history.iter().enumerate().skip(start)))
+}
+
+fn search(text: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let history = Config::load_history()?;
+    let text = text.to_lowercase();
+    let mut found: Vec<_> = history
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, entry)| entry.corrected.to_lowercase().contains(&text))
+        .take(SHOWN)
+        .collect();
+    if found.is_empty() {
+        return Ok(vec![format!("No dictation contains \"{}\"", text)]);
+    }
+    found.reverse();
+    Ok(listed(found.into_iter()))
+}
+
+/// Entries as `rec history list` shows them
+fn listed<'a>(entries: impl Iterator<Item = (usize, &'a config::HistoryEntry)>) -> Vec<String> {
+    entries
+        .map(|(i, entry)| format!("{:>4}  {}  {}", i + 1, entry.timestamp, entry.corrected))
+        .collect()
+}
+
+/// Add a word like `rec add-word` does without a terminal: a near-duplicate
+/// is only merged when asked to there
+fn add_word(word: String) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut config = Config::load()?;
+    let key = config::word_key(&word);
+    if let Some(entry) = config
+        .custom_words
+        .iter()
+        .find(|w| config::word_key(w) == key)
+    {
+        return Ok(vec![format!("Already in the vocabulary: {}", entry)]);
+    }
+    let line = match config.similar_custom_word(&word) {
+        Some(existing) => format!(
+            "Word added: {} (it looks like '{}': run rec add-word to merge it instead)",
+            word, existing
+        ),
+        None => format!("Word added: {}", word),
+    };
+    config.add_custom_word(word);
+    config.save()?;
+    Ok(vec![line])
+}
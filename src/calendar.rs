@@ -0,0 +1,121 @@
+//! Date, time and calendar event a dictation was made at, so relative
+//! references ("the meeting we just had", "tomorrow") can be resolved
+//!
+//! The current event comes from a user-provided command rather than a
+//! built-in calendar client, so any calendar works: `icalBuddy` on macOS,
+//! `khal` or `gcalcli` elsewhere, or a script reading an ICS file.
+
+use serde::{Deserialize, Serialize};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CalendarConfig {
+    /// Tell Claude the date, time and current event when correcting and filling templates
+    #[serde(default)]
+    pub correction: bool,
+    /// Write them before each dictation in the notes file (-o)
+    #[serde(default)]
+    pub notes_header: bool,
+    /// Shell command printing the title of the current event, if any (first line is used)
+    #[serde(default)]
+    pub event_command: Option<String>,
+    /// Seconds the event command may take before it is given up on
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for CalendarConfig {
+    fn default() -> Self {
+        Self {
+            correction: false,
+            notes_header: false,
+            event_command: None,
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+fn default_timeout_secs() -> u64 {
+    3
+}
+
+/// When a dictation was made
+pub struct Moment {
+    pub time: chrono::DateTime<chrono::Local>,
+    /// Title of the calendar event in progress
+    pub event: Option<String>,
+}
+
+impl Moment {
+    /// Now, with the current event if an event command is configured
+    pub fn now(config: &CalendarConfig) -> Self {
+        Self {
+            time: chrono::Local::now(),
+            event: config
+                .event_command
+                .as_deref()
+                .and_then(|command| current_event(command, config.timeout_secs)),
+        }
+    }
+
+    /// Short form for the notes file heading: `2025-03-14 10:30 · Sprint review`
+    pub fn heading(&self) -> String {
+        let time = self.time.format("%Y-%m-%d %H:%M").to_string();
+        match &self.event {
+            Some(event) => format!("{} · {}", time, event),
+            None => time,
+        }
+    }
+}
+
+/// Long form for Claude: `Friday 14 March 2025, 10:30, during "Sprint review"`
+impl std::fmt::Display for Moment {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.time.format("%A %-d %B %Y, %H:%M"))?;
+        if let Some(event) = &self.event {
+            write!(f, ", during \"{}\"", event)?;
+        }
+        Ok(())
+    }
+}
+
+/// First line printed by the event command, if it succeeds in time
+fn current_event(command: &str, timeout_secs: u64) -> Option<String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| eprintln!("Warning: Failed to run the calendar event command: {}", e))
+        .ok()?;
+
+    let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+            _ => {
+                child.kill().ok();
+                child.wait().ok();
+                eprintln!(
+                    "Warning: The calendar event command took over {}s, going without",
+                    timeout_secs
+                );
+                return None;
+            }
+        }
+    }
+
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+}
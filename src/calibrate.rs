@@ -0,0 +1,132 @@
+//! Input level calibration (`rec calibrate`)
+//!
+//! A few seconds of silence give the microphone's noise floor, a few seconds
+//! of speech its speech level. From the two come a gain bringing speech to a
+//! level backends and detectors handle well, and voice activity thresholds
+//! sitting between the noise and the speech of this microphone in this room.
+
+use crate::audio::{self, rms};
+use crate::config::Config;
+use crate::vad;
+use std::time::Duration;
+
+/// Seconds recorded for each measurement
+const QUIET_SECS: u64 = 3;
+const SPEECH_SECS: u64 = 5;
+
+/// Speech RMS level the gain aims for, about -20 dBFS
+const TARGET_SPEECH: f32 = 0.1;
+
+/// Gain range: attenuating hot inputs a little, boosting quiet ones a lot
+const MIN_GAIN: f32 = 0.5;
+const MAX_GAIN: f32 = 8.0;
+
+/// Speech must be this many times above the noise to calibrate on it
+const MIN_SNR: f32 = 2.0;
+
+/// Bounds of the adaptive detector's ratio
+const MIN_RATIO: f32 = 1.5;
+const MAX_RATIO: f32 = 10.0;
+
+/// Text offered to read aloud while the speech level is measured
+const READ_ALOUD: &str = "The quick brown fox jumps over the lazy dog, then runs back to the barn before the rain starts.";
+
+/// Levels measured on the microphone, before any gain
+pub struct Levels {
+    /// Median frame RMS while quiet
+    pub noise: f32,
+    /// 90th percentile frame RMS while speaking, the voiced frames
+    pub speech: f32,
+}
+
+/// Settings derived from measured levels
+pub struct Recommendation {
+    pub gain: f32,
+    pub vad_threshold: f32,
+    pub vad_ratio: f32,
+    /// Noise floor after gain, the adaptive detector's starting point
+    pub noise_floor: f32,
+}
+
+/// Record silence then speech from `device` and measure both
+pub async fn measure(
+    device: Option<&str>,
+    buffer_ms: Option<u32>,
+) -> Result<Levels, Box<dyn std::error::Error>> {
+    let recorder = audio::Recorder::start(device, buffer_ms)?;
+
+    eprintln!("Stay quiet for {} seconds...", QUIET_SECS);
+    tokio::time::sleep(Duration::from_secs(QUIET_SECS)).await;
+    let quiet = recorder.take();
+
+    eprintln!(
+        "Now speak as you would dictate for {} seconds, e.g. read:\n  \"{}\"",
+        SPEECH_SECS, READ_ALOUD
+    );
+    tokio::time::sleep(Duration::from_secs(SPEECH_SECS)).await;
+    let (sample_rate, channels) = (recorder.sample_rate, recorder.channels);
+    let speech = recorder.stop();
+
+    if audio::is_digital_silence(&quiet) && audio::is_digital_silence(&speech) {
+        return Err(audio::MUTED.into());
+    }
+    let noise = percentile(frame_levels(&quiet, sample_rate, channels), 0.5);
+    let speech = percentile(frame_levels(&speech, sample_rate, channels), 0.9);
+    if speech < noise * MIN_SNR {
+        return Err(format!(
+            "Speech ({:.0} dBFS) was hardly louder than the silence ({:.0} dBFS): check the input device and try again",
+            dbfs(speech),
+            dbfs(noise)
+        )
+        .into());
+    }
+    Ok(Levels { noise, speech })
+}
+
+impl Levels {
+    pub fn recommend(&self) -> Recommendation {
+        let gain = (TARGET_SPEECH / self.speech).clamp(MIN_GAIN, MAX_GAIN);
+        let noise = self.noise * gain;
+        let speech = self.speech * gain;
+        Recommendation {
+            gain: round(gain),
+            // Halfway between the two on a log scale
+            vad_threshold: (noise * speech).sqrt(),
+            vad_ratio: round((speech / noise).sqrt().clamp(MIN_RATIO, MAX_RATIO)),
+            noise_floor: noise,
+        }
+    }
+}
+
+impl Recommendation {
+    /// Store the settings in config
+    pub fn apply(&self, config: &mut Config) {
+        config.input_gain = self.gain;
+        config.vad.threshold = self.vad_threshold;
+        config.vad.ratio = self.vad_ratio;
+        config.vad.noise_floor = Some(self.noise_floor);
+    }
+}
+
+/// RMS of each detector frame
+fn frame_levels(samples: &[f32], sample_rate: u32, channels: u16) -> Vec<f32> {
+    let frame_len = (sample_rate as usize * vad::FRAME_MS / 1000 * channels as usize).max(1);
+    samples.chunks(frame_len).map(rms).collect()
+}
+
+fn percentile(mut levels: Vec<f32>, share: f32) -> f32 {
+    if levels.is_empty() {
+        return 0.0;
+    }
+    levels.sort_by(f32::total_cmp);
+    levels[((levels.len() - 1) as f32 * share) as usize]
+}
+
+/// Level in decibels relative to full scale
+pub fn dbfs(level: f32) -> f32 {
+    20.0 * level.max(1e-6).log10()
+}
+
+fn round(value: f32) -> f32 {
+    (value * 100.0).round() / 100.0
+}
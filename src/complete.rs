@@ -0,0 +1,215 @@
+//! Recent dictations for shell completion (`rec complete-server`)
+//!
+//! Shell plugins (an fzf widget bound to a key) ask a server listening on a
+//! Unix socket in the runtime directory, so that the prompt doesn't wait for
+//! a `rec` to start and parse the whole history on every key press. A
+//! request is one line, the answer one candidate per line, after which the
+//! connection is closed:
+//!
+//! - `recent [N]`: the latest dictations, newest first
+//! - `phrases [N]`: phrases said in more than one dictation, most frequent first
+//! - `search TEXT`: dictations, then phrases, containing TEXT (any case)
+//!
+//! Dictations spanning several lines are answered on one, as a prompt takes.
+
+use crate::config::Config;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Candidates answered when the request gives no count
+const DEFAULT_COUNT: usize = 20;
+
+/// Word counts of the phrases looked for
+const MIN_PHRASE_WORDS: usize = 3;
+const MAX_PHRASE_WORDS: usize = 6;
+
+/// Phrases must come up in at least this many dictations
+const MIN_PHRASE_DICTATIONS: usize = 2;
+
+/// Dictations and frequent phrases of the history, as of its last change
+#[derive(Default)]
+struct Index {
+    modified: Option<SystemTime>,
+    /// Newest first
+    recent: Vec<String>,
+    /// Most frequent first
+    phrases: Vec<String>,
+}
+
+/// Serve completions until interrupted
+#[cfg(unix)]
+pub async fn serve() -> Result<(), Box<dyn std::error::Error>> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let path = socket_path()?;
+    if tokio::net::UnixStream::connect(&path).await.is_ok() {
+        return Err(format!(
+            "rec complete-server is already running on {}",
+            path.display()
+        )
+        .into());
+    }
+    // Left behind by a server that didn't exit cleanly
+    std::fs::remove_file(&path).ok();
+    let listener = tokio::net::UnixListener::bind(&path)?;
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    eprintln!("Completing from history on {}", path.display());
+
+    let index = Arc::new(Mutex::new(Index::default()));
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = accepted?;
+                let index = index.clone();
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut request = String::new();
+                    if BufReader::new(reader).read_line(&mut request).await.is_err() {
+                        return;
+                    }
+                    let answer = {
+                        let mut index = index.lock().unwrap();
+                        index.refresh();
+                        index.answer(request.trim())
+                    };
+                    writer.write_all(answer.as_bytes()).await.ok();
+                });
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    std::fs::remove_file(&path).ok();
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn serve() -> Result<(), Box<dyn std::error::Error>> {
+    Err("rec complete-server needs Unix sockets".into())
+}
+
+/// Socket the server listens on
+fn socket_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = dirs::runtime_dir()
+        .or_else(dirs::cache_dir)
+        .ok_or("Could not find runtime directory")?
+        .join("rec");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir.join("complete.sock"))
+}
+
+impl Index {
+    /// Reload the history if it changed since the last request
+    fn refresh(&mut self) {
+        let modified = Config::history_path()
+            .ok()
+            .and_then(|path| std::fs::metadata(path).ok())
+            .and_then(|meta| meta.modified().ok());
+        if modified.is_some() && modified == self.modified {
+            return;
+        }
+        let history = match Config::load_history() {
+            Ok(history) => history,
+            Err(e) => {
+                eprintln!("Warning: Failed to load history: {}", e);
+                return;
+            }
+        };
+
+        self.modified = modified;
+        self.recent = history
+            .iter()
+            .rev()
+            .map(|entry| one_line(&entry.corrected))
+            .filter(|text| !text.is_empty())
+            .collect();
+        self.phrases = frequent_phrases(&self.recent);
+    }
+
+    /// Candidates for a request line, one per line
+    fn answer(&self, request: &str) -> String {
+        let (command, argument) = request.split_once(' ').unwrap_or((request, ""));
+        let count = || argument.trim().parse().unwrap_or(DEFAULT_COUNT);
+        let candidates: Vec<&String> = match command {
+            "recent" => self.recent.iter().take(count()).collect(),
+            "phrases" => self.phrases.iter().take(count()).collect(),
+            "search" => {
+                let needle = argument.trim().to_lowercase();
+                let mut seen = std::collections::HashSet::new();
+                self.recent
+                    .iter()
+                    .chain(&self.phrases)
+                    .filter(|text| text.to_lowercase().contains(&needle) && seen.insert(*text))
+                    .take(DEFAULT_COUNT)
+                    .collect()
+            }
+            _ => {
+                return format!(
+                    "error: unknown request {:?} (recent, phrases or search)\n",
+                    command
+                );
+            }
+        };
+        candidates
+            .iter()
+            .map(|text| format!("{}\n", text))
+            .collect()
+    }
+}
+
+/// Text with its line breaks and repeated spaces collapsed
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Phrases found in several dictations, most frequent first
+///
+/// Phrases don't cross punctuation, and one that only comes up inside a
+/// longer frequent phrase is left out for it.
+fn frequent_phrases(dictations: &[String]) -> Vec<String> {
+    // Keyed by lowercase words; the form first said (the newest) is kept
+    let mut counts: HashMap<String, (String, usize)> = HashMap::new();
+    for dictation in dictations {
+        let mut said = std::collections::HashSet::new();
+        for clause in dictation.split(['.', ',', ';', ':', '!', '?']) {
+            let words: Vec<&str> = clause.split_whitespace().collect();
+            for len in MIN_PHRASE_WORDS..=MAX_PHRASE_WORDS.min(words.len()) {
+                for window in words.windows(len) {
+                    let phrase = window.join(" ");
+                    let key = phrase.to_lowercase();
+                    if said.insert(key.clone()) {
+                        counts.entry(key).or_insert((phrase, 0)).1 += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut frequent: Vec<(String, String, usize)> = counts
+        .into_iter()
+        .filter(|(_, (_, count))| *count >= MIN_PHRASE_DICTATIONS)
+        .map(|(key, (phrase, count))| (key, phrase, count))
+        .collect();
+    frequent.sort_by(|a, b| {
+        b.2.cmp(&a.2)
+            .then(b.0.len().cmp(&a.0.len()))
+            .then(a.0.cmp(&b.0))
+    });
+
+    let mut kept: Vec<(String, String, usize)> = Vec::new();
+    for (key, phrase, count) in frequent {
+        let padded = format!(" {} ", key);
+        let within_longer = kept
+            .iter()
+            .any(|(longer, _, n)| *n == count && format!(" {} ", longer).contains(&padded));
+        if !within_longer {
+            kept.push((key, phrase, count));
+        }
+    }
+    kept.into_iter().map(|(_, phrase, _)| phrase).collect()
+}
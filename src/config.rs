@@ -1,6 +1,24 @@
 //! Configuration management for rec
 
+use crate::accel::Accelerator;
+use crate::audio::WavConfig;
+use crate::backend::AuthStyle;
+use crate::calendar::CalendarConfig;
+use crate::git::{GitConfig, GitContext};
+use crate::hooks::HooksConfig;
+use crate::http::HttpConfig;
+use crate::instance::Busy;
+use crate::limits::LimitsConfig;
+use crate::plugins::PluginConfig;
+use crate::retrieval::RetrievalConfig;
+use crate::scrub::ScrubConfig;
+use crate::speak::SpeakConfig;
+use crate::sync::SyncConfig;
+use crate::vad::VadConfig;
+use crate::vosk::VoskConfig;
+use crate::watchdog::WatchdogConfig;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 
@@ -11,19 +29,493 @@ pub struct HistoryEntry {
     pub corrected: String,
     pub model: String,
     pub custom_words: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feedback: Option<Feedback>,
+    /// Recording session the dictation belongs to (--session)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    /// Key-value pairs given with --meta, e.g. "ticket": "PROJ-123"
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub meta: BTreeMap<String, String>,
+    /// Git repository and branch the dictation was made in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<GitContext>,
+}
+
+/// User judgement on a correction
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Feedback {
+    Good,
+    Bad,
+}
+
+impl HistoryEntry {
+    /// Create an entry timestamped now
+    pub fn new(original: &str, corrected: &str, model: &str, custom_words: &[String]) -> Self {
+        Self {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            original: original.to_string(),
+            corrected: corrected.to_string(),
+            model: model.to_string(),
+            custom_words: custom_words.to_vec(),
+            explanation: None,
+            feedback: None,
+            session: None,
+            meta: BTreeMap::new(),
+            git: None,
+        }
+    }
+}
+
+/// The word of a custom word entry, without its description ("Tauri: a Rust framework")
+pub fn word_key(entry: &str) -> &str {
+    entry.split(':').next().unwrap_or(entry).trim()
+}
+
+/// Letters and digits of a word, lowercased: "Postgre SQL" is "postgresql"
+fn fold(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// How a custom word is written, keyed by its exact casing (e.g. "PostgreSQL")
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct WordRule {
+    /// Plural used instead of adding -s (e.g. "PostgreSQL instances")
+    #[serde(default)]
+    pub plural: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub custom_words: Vec<String>,
+    /// Exact spelling and plural of custom words, enforced after correction
+    #[serde(default)]
+    pub word_rules: BTreeMap<String, WordRule>,
+    /// Pinned spellings by what was heard (e.g. "Kuberneti": "Kubernetes"),
+    /// applied last, whatever the backend or Claude wrote
+    #[serde(default)]
+    pub word_overrides: BTreeMap<String, String>,
+    /// Other spellings of custom words, merged into them by `rec add-word`
+    /// (e.g. "PostgreSQL": ["Postgres SQL"]) and written as the word
+    #[serde(default)]
+    pub word_aliases: BTreeMap<String, Vec<String>>,
+    /// How filler words ("um", "euh"...) are removed: 'off', 'list' or 'prompt'
+    #[serde(default)]
+    pub fillers: Fillers,
+    /// Filler words removed with `fillers: list`
+    #[serde(default = "default_filler_words")]
+    pub filler_words: Vec<String>,
     pub claude_model: String,
+    /// Base URL, extra headers and timeouts of requests to Claude
+    #[serde(default)]
+    pub claude_http: HttpConfig,
+    /// Secrets masked in text sent to Claude
+    #[serde(default)]
+    pub scrub: ScrubConfig,
+    /// Dictation templates by name, with `{slot}` placeholders
+    #[serde(default)]
+    pub templates: BTreeMap<String, String>,
+    /// Local whisper.cpp transcription
+    #[serde(default)]
+    pub local: LocalConfig,
+    /// Vosk model and Python interpreter for `--backend vosk`
+    #[serde(default)]
+    pub vosk: VoskConfig,
+    /// Inserted between the old clipboard contents and the new text with --clip-append
+    #[serde(default = "default_clip_separator")]
+    pub clip_separator: String,
+    /// Similarity (0-1) above which a dictation counts as a repeat of the previous one; 0 disables the check
+    #[serde(default = "default_duplicate_threshold")]
+    pub duplicate_threshold: f32,
+    /// Segments below this confidence (0-1) are highlighted in the output
+    #[serde(default = "default_confidence_threshold")]
+    pub confidence_threshold: f32,
+    /// Seconds to wait for correction before outputting the uncorrected text
+    #[serde(default)]
+    pub correction_deadline: Option<f32>,
+    /// Sampling temperature for correction (0-1, API default when unset)
+    #[serde(default)]
+    pub correction_temperature: Option<f32>,
+    /// Nucleus sampling (top_p) for correction, API default when unset
+    #[serde(default)]
+    pub correction_top_p: Option<f32>,
+    /// Extra instructions for correction, sent as the system prompt
+    #[serde(default)]
+    pub correction_prompt: Option<String>,
+    /// What is usually dictated (project, names, jargon), given as the
+    /// prompt to backends taking one, e.g. "Standup notes for the Atlas
+    /// billing service"
+    #[serde(default)]
+    pub transcription_prompt: Option<String>,
+    /// Backend settings by name: overrides for built-in backends, or custom
+    /// OpenAI-compatible servers selected with --backend <name>
+    #[serde(default)]
+    pub backends: BTreeMap<String, BackendConfig>,
+    /// Named sets of per-dictation settings, selected with --profile
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Profile used when --profile is not given
+    #[serde(default)]
+    pub default_profile: Option<String>,
+    /// Profile picked by focused application when --profile is not given,
+    /// e.g. "kitty": "code" (matched against the app's lowercased window class)
+    #[serde(default)]
+    pub app_profiles: BTreeMap<String, String>,
+    /// Default the spoken language to the system locale (or keyboard layout with `rec serve`)
+    #[serde(default = "default_auto_language")]
+    pub auto_language: bool,
+    /// Access control for `rec serve`
+    #[serde(default)]
+    pub server: ServerConfig,
+    /// Factor recorded audio is multiplied by (set by `rec calibrate`)
+    #[serde(default = "default_input_gain")]
+    pub input_gain: f32,
+    /// Voice activity detection, to drop silence before transcription
+    #[serde(default)]
+    pub vad: VadConfig,
+    /// Fake backend and corrector, for demos and offline development
+    #[serde(default)]
+    pub mock: MockConfig,
+    /// Scripts run before upload and after transcription or correction
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    /// WebAssembly text post-processors, run in order
+    #[serde(default)]
+    pub plugins: Vec<PluginConfig>,
+    /// Names for diarization labels (e.g. "A": "Alice"), for every session
+    #[serde(default)]
+    pub speakers: BTreeMap<String, String>,
+    /// Memory and disk caps that stop a long recording before they run out
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// Where the git repository and branch are shown besides history
+    #[serde(default)]
+    pub git: GitConfig,
+    /// Where the date, time and current calendar event are given as context
+    #[serde(default)]
+    pub calendar: CalendarConfig,
+    /// Remote for `rec sync`
+    #[serde(default)]
+    pub sync: SyncConfig,
+    /// Speech synthesizer for --speak
+    #[serde(default)]
+    pub speak: SpeakConfig,
+    /// What a new recording does while another rec is recording: 'refuse' or 'stop' it
+    #[serde(default)]
+    pub when_busy: Busy,
+    /// Stop requests this soon after a recording started are ignored, in milliseconds
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+    /// Shorter recordings are discarded instead of transcribed, in milliseconds
+    #[serde(default)]
+    pub min_recording_ms: u64,
+    /// Deadline, retries and fallback backend for transcription requests
+    #[serde(default)]
+    pub watchdog: WatchdogConfig,
+    /// Notes directory searched for names during correction
+    #[serde(default)]
+    pub retrieval: RetrievalConfig,
+    /// Backends tried in order (e.g. ["groq", "mistral", "local"]): the first unless --backend
+    /// is given, the next whenever one is rate limited, fails on the server or times out
+    #[serde(default)]
+    pub failover: Vec<String>,
+    /// Microphone buffer length in milliseconds; longer survives flaky USB audio at the cost of latency
+    #[serde(default)]
+    pub capture_buffer_ms: Option<u32>,
+    /// Input device used when --device isn't given: a name or index from `rec devices`, 'pulse:<source>' or 'pipewire:<target>'
+    #[serde(default)]
+    pub default_device: Option<String>,
+    /// cpal host used when --audio-host isn't given, e.g. 'jack' (the platform's default otherwise)
+    #[serde(default)]
+    pub audio_host: Option<String>,
+    /// Format of the audio kept with sessions: 'pcm16', 'pcm24' or 'float', and dithering
+    #[serde(default)]
+    pub kept_audio: WavConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MockConfig {
+    /// Use the mock backend (unless --backend is given) and the mock corrector
+    #[serde(default)]
+    pub enabled: bool,
+    /// Canned transcription; when unset the transcript describes the audio
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Replacements the mock corrector applies, e.g. "cube ernetes": "Kubernetes"
+    #[serde(default)]
+    pub corrections: BTreeMap<String, String>,
+    /// Simulated time each call takes, in milliseconds
+    #[serde(default)]
+    pub latency_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ServerConfig {
+    /// Refuse to listen on anything but loopback addresses
+    #[serde(default)]
+    pub localhost_only: bool,
+    /// Accepted bearer tokens; without any, the server is open and only listens on loopback
+    #[serde(default)]
+    pub tokens: Vec<ServerToken>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServerToken {
+    /// Who the token was given to, for your own reference
+    pub name: String,
+    pub token: String,
+    /// What the token allows (default: transcribe only)
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<Scope>,
+}
+
+/// How filler words are removed from dictations
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Fillers {
+    /// Kept as transcribed
+    #[default]
+    Off,
+    /// The words of `filler_words`, wherever they are whole words
+    List,
+    /// Claude removes them while correcting, telling fillers from meaning
+    /// (the list applies when not correcting)
+    Prompt,
+}
+
+/// Part of the server API a token gives access to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Transcribe,
+    History,
+    Metrics,
+    /// Recording from the server's microphone (gRPC `Start` and `Stop`)
+    Record,
+}
+
+fn default_scopes() -> Vec<Scope> {
+    vec![Scope::Transcribe]
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BackendConfig {
+    /// Transcription endpoint (required for custom backends, unless `command` is set)
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Program getting the WAV on stdin and printing the transcript, run with `sh -c`
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Environment variable holding the API key
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// How the API key is sent: 'bearer', 'x-api-key', 'hmac' (rec-api) or 'none'
+    #[serde(default)]
+    pub auth: Option<AuthStyle>,
+    /// Multipart field for the audio file (default 'file')
+    #[serde(default)]
+    pub file_field: Option<String>,
+    /// Multipart field for the model (default 'model')
+    #[serde(default)]
+    pub model_field: Option<String>,
+    /// Multipart field for the language (default 'language')
+    #[serde(default)]
+    pub language_field: Option<String>,
+    /// Model sent to this backend
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Sent as 'response_format' (e.g. 'verbose_json' for segment confidences)
+    #[serde(default)]
+    pub response_format: Option<String>,
+    /// Region of the service (aws; AWS_REGION otherwise)
+    #[serde(default)]
+    pub region: Option<String>,
+    /// S3 bucket the audio is uploaded to for transcription (aws)
+    #[serde(default)]
+    pub bucket: Option<String>,
+    /// Ask the backend to tell speakers apart (elevenlabs, openai, aws, and
+    /// OpenAI-compatible servers answering `diarized_json`; see --diarize)
+    #[serde(default)]
+    pub diarize: bool,
+    /// Base URL, extra headers and timeouts of this backend's requests
+    #[serde(flatten)]
+    pub http: HttpConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Profile {
+    /// Language spoken (sent to the transcription backend)
+    #[serde(default)]
+    pub speech_language: Option<String>,
+    /// Language of the final text; translated with Claude when it differs
+    #[serde(default)]
+    pub output_language: Option<String>,
+    /// Correction instructions replacing `correction_prompt`
+    #[serde(default)]
+    pub correction_prompt: Option<String>,
+    /// How filler words are removed, replacing `fillers`
+    #[serde(default)]
+    pub fillers: Option<Fillers>,
+}
+
+fn default_auto_language() -> bool {
+    true
+}
+
+fn default_confidence_threshold() -> f32 {
+    0.6
+}
+
+fn default_input_gain() -> f32 {
+    1.0
+}
+
+fn default_duplicate_threshold() -> f32 {
+    0.9
+}
+
+/// Long enough to absorb a double press, short enough for any deliberate one
+fn default_debounce_ms() -> u64 {
+    300
+}
+
+/// Hesitations that never carry meaning, in English and French
+fn default_filler_words() -> Vec<String> {
+    ["um", "umm", "uh", "uhh", "uhm", "er", "erm", "euh", "hmm"]
+        .map(String::from)
+        .to_vec()
+}
+
+fn default_clip_separator() -> String {
+    " ".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LocalConfig {
+    /// whisper.cpp CLI executable
+    pub binary: String,
+    /// GGML model file, or the name of a model managed with `rec model` (e.g. 'base.en')
+    pub model: Option<PathBuf>,
+    /// Quantized variant of the model to use (e.g. 'q5_1' picks ggml-base-q5_1.bin)
+    #[serde(default)]
+    pub quantization: Option<String>,
+    /// CPU threads (whisper.cpp default when unset)
+    #[serde(default)]
+    pub threads: Option<usize>,
+    #[serde(default)]
+    pub accelerator: Accelerator,
+    /// GPU index when several are available
+    #[serde(default)]
+    pub gpu_device: Option<u32>,
+}
+
+impl LocalConfig {
+    /// Model file to load, accounting for the configured quantization
+    pub fn model_path(&self) -> Option<PathBuf> {
+        let model = self.model.as_ref()?;
+        let model = &match is_model_name(model) {
+            true => crate::models::model_path(&model.to_string_lossy()).ok()?,
+            false => model.clone(),
+        };
+        let Some(quant) = &self.quantization else {
+            return Some(model.clone());
+        };
+
+        let stem = model.file_stem()?.to_string_lossy();
+        let ext = model
+            .extension()
+            .map(|e| e.to_string_lossy())
+            .unwrap_or_default();
+        let name = if ext.is_empty() {
+            format!("{}-{}", stem, quant)
+        } else {
+            format!("{}-{}.{}", stem, quant, ext)
+        };
+        Some(model.with_file_name(name))
+    }
+}
+
+/// Whether `local.model` names a managed model ('base.en') rather than a file
+pub fn is_model_name(model: &std::path::Path) -> bool {
+    let bare = model
+        .parent()
+        .is_some_and(|parent| parent.as_os_str().is_empty());
+    let file = matches!(
+        model.extension().and_then(|ext| ext.to_str()),
+        Some("bin" | "gguf")
+    );
+    bare && !file
+}
+
+impl Default for LocalConfig {
+    fn default() -> Self {
+        Self {
+            binary: "whisper-cli".to_string(),
+            model: None,
+            quantization: None,
+            threads: None,
+            accelerator: Accelerator::Auto,
+            gpu_device: None,
+        }
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             custom_words: vec![],
+            word_rules: BTreeMap::new(),
+            word_overrides: BTreeMap::new(),
+            word_aliases: BTreeMap::new(),
+            fillers: Fillers::default(),
+            filler_words: default_filler_words(),
             claude_model: "claude-haiku-4-5".to_string(),
+            claude_http: HttpConfig::default(),
+            scrub: ScrubConfig::default(),
+            templates: BTreeMap::new(),
+            local: LocalConfig::default(),
+            vosk: VoskConfig::default(),
+            clip_separator: default_clip_separator(),
+            duplicate_threshold: default_duplicate_threshold(),
+            confidence_threshold: default_confidence_threshold(),
+            correction_deadline: None,
+            correction_temperature: None,
+            correction_top_p: None,
+            correction_prompt: None,
+            transcription_prompt: None,
+            backends: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            default_profile: None,
+            app_profiles: BTreeMap::new(),
+            auto_language: default_auto_language(),
+            server: ServerConfig::default(),
+            input_gain: default_input_gain(),
+            vad: VadConfig::default(),
+            mock: MockConfig::default(),
+            hooks: HooksConfig::default(),
+            plugins: vec![],
+            speakers: BTreeMap::new(),
+            limits: LimitsConfig::default(),
+            git: GitConfig::default(),
+            calendar: CalendarConfig::default(),
+            sync: SyncConfig::default(),
+            speak: SpeakConfig::default(),
+            when_busy: Busy::default(),
+            debounce_ms: default_debounce_ms(),
+            min_recording_ms: 0,
+            watchdog: WatchdogConfig::default(),
+            retrieval: RetrievalConfig::default(),
+            failover: Vec::new(),
+            capture_buffer_ms: None,
+            default_device: None,
+            audio_host: None,
+            kept_audio: WavConfig::default(),
         }
     }
 }
@@ -39,6 +531,11 @@ impl Config {
         Ok(config_dir.join("config.json"))
     }
 
+    /// Whether a config file has been written yet
+    pub fn exists() -> bool {
+        Self::config_path().is_ok_and(|path| path.exists())
+    }
+
     /// Load config from disk, creating with defaults if it doesn't exist
     pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
         let path = Self::config_path()?;
@@ -77,6 +574,30 @@ impl Config {
         Ok(())
     }
 
+    /// Resolve a profile by name, falling back to the default profile
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile, Box<dyn std::error::Error>> {
+        match name.or(self.default_profile.as_deref()) {
+            Some(name) => self
+                .profiles
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("Unknown profile: {}", name).into()),
+            None => Ok(Profile::default()),
+        }
+    }
+
+    /// Name of the profile mapped to the focused application, if any
+    pub fn app_profile(&self) -> Option<String> {
+        if self.app_profiles.is_empty() {
+            return None;
+        }
+        let app = crate::focus::focused_app()?;
+        self.app_profiles
+            .iter()
+            .find(|(name, _)| app.contains(&name.to_lowercase()))
+            .map(|(_, profile)| profile.clone())
+    }
+
     /// Add a custom word to the list (deduplicated)
     pub fn add_custom_word(&mut self, word: String) {
         if !self.custom_words.contains(&word) {
@@ -84,8 +605,66 @@ impl Config {
         }
     }
 
+    /// Custom word that `word` is probably another spelling of: the same
+    /// letters in another casing or spacing, one typo away, or an alias of it
+    pub fn similar_custom_word(&self, word: &str) -> Option<String> {
+        let word = word_key(word);
+        let letters = fold(word);
+        self.custom_words
+            .iter()
+            .map(|w| word_key(w))
+            .find_map(|known| {
+                let known_letters = fold(known);
+                let typos = crate::text::levenshtein(
+                    &letters.chars().collect::<Vec<_>>(),
+                    &known_letters.chars().collect::<Vec<_>>(),
+                );
+                // Short words a letter apart are usually different words (API, APK)
+                let near =
+                    typos <= 1 && letters.chars().count().min(known_letters.chars().count()) >= 5;
+                let alias = self
+                    .word_aliases
+                    .get(known)
+                    .is_some_and(|aliases| aliases.iter().any(|a| a.eq_ignore_ascii_case(word)));
+                (known != word && (letters == known_letters || near || alias))
+                    .then(|| known.to_string())
+            })
+    }
+
+    /// Record `alias` as another spelling of the custom word `word`
+    pub fn add_word_alias(&mut self, word: &str, alias: &str) {
+        let aliases = self
+            .word_aliases
+            .entry(word_key(word).to_string())
+            .or_default();
+        if !aliases.iter().any(|a| a == alias) {
+            aliases.push(alias.to_string());
+        }
+    }
+
+    /// Make `word` the spelling of the custom word `existing`, which becomes
+    /// an alias along with its own, keeping its description and rules
+    pub fn respell_custom_word(&mut self, existing: &str, word: &str) {
+        let (existing, word) = (word_key(existing), word_key(word));
+        for entry in self.custom_words.iter_mut() {
+            if word_key(entry) == existing {
+                let description = entry.find(':').map_or("", |i| &entry[i..]);
+                *entry = format!("{}{}", word, description);
+            }
+        }
+        if let Some(rule) = self.word_rules.remove(existing) {
+            self.word_rules.insert(word.to_string(), rule);
+        }
+        let mut aliases = self.word_aliases.remove(existing).unwrap_or_default();
+        aliases.retain(|a| a != word);
+        aliases.push(existing.to_string());
+        for alias in aliases {
+            self.add_word_alias(word, &alias);
+        }
+    }
+
     /// Get the history file path
-    fn history_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    pub fn history_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
         let config_dir = dirs::config_dir()
             .ok_or("Could not find config directory")?
             .join("rec");
@@ -121,28 +700,17 @@ impl Config {
     }
 
     /// Add entry to history
-    pub fn add_to_history(
-        original: &str,
-        corrected: &str,
-        model: &str,
-        custom_words: &[String],
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn add_to_history(entry: HistoryEntry) -> Result<(), Box<dyn std::error::Error>> {
         let mut history = Self::load_history()?;
+        history.push(entry);
+        Self::save_history(&history)
+    }
 
-        let timestamp = chrono::Utc::now().to_rfc3339();
-
-        history.push(HistoryEntry {
-            timestamp,
-            original: original.to_string(),
-            corrected: corrected.to_string(),
-            model: model.to_string(),
-            custom_words: custom_words.to_vec(),
-        });
-
+    /// Overwrite history on disk
+    pub fn save_history(history: &[HistoryEntry]) -> Result<(), Box<dyn std::error::Error>> {
         let path = Self::history_path()?;
-        let content = serde_json::to_string_pretty(&history)?;
+        let content = serde_json::to_string_pretty(history)?;
         fs::write(path, content)?;
-
         Ok(())
     }
 }
@@ -1,7 +1,36 @@
 //! Claude API correction for transcriptions
 
-use crate::config::HistoryEntry;
+use crate::config::{Feedback, HistoryEntry, MockConfig};
+use crate::http;
+use crate::retrieval::Notes;
 use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Maximum length of Claude's answers
+const MAX_TOKENS: u32 = 1024;
+
+/// Limit an answer cut off at `MAX_TOKENS` may be retried up to, four
+/// times higher each time
+const MAX_TOKENS_RETRY: u32 = 8192;
+
+/// Transcriptions longer than this (in characters) are corrected in chunks
+///
+/// At around 3 characters per token, a chunk's correction fits in
+/// `MAX_TOKENS` with room left for the explanation.
+const CHUNK_CHARS: usize = MAX_TOKENS as usize * 2;
+
+/// Characters of the previous chunk shown as context when correcting the next
+const CONTEXT_CHARS: usize = 300;
+
+/// Number of chunks corrected at the same time
+const CHUNK_CONCURRENCY: usize = 4;
+
+/// Characters a streamed correction may run past 1.5 times the length of
+/// the transcription before it is stopped, e.g. when Claude answers a
+/// dictated question instead of correcting it
+const RUNAWAY_CHARS: usize = 200;
 
 #[derive(Serialize)]
 struct Message {
@@ -9,20 +38,22 @@ struct Message {
     content: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 struct ToolProperty {
     r#type: String,
     description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    items: Option<serde_json::Value>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 struct ToolInputSchema {
     r#type: String,
     properties: std::collections::HashMap<String, ToolProperty>,
     required: Vec<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 struct Tool {
     name: String,
     description: String,
@@ -33,30 +64,109 @@ struct Tool {
 struct ApiRequest {
     model: String,
     max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
     messages: Vec<Message>,
     tools: Vec<Tool>,
     tool_choice: serde_json::Value,
+    stream: bool,
+}
+
+/// Server-sent event of a streamed answer, the ones that matter here
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    ContentBlockDelta {
+        delta: BlockDelta,
+    },
+    MessageDelta {
+        delta: MessageDelta,
+    },
+    Error {
+        error: StreamError,
+    },
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Deserialize)]
-#[serde(tag = "type")]
-#[allow(dead_code)]
-enum ContentBlock {
-    #[serde(rename = "tool_use")]
-    ToolUse {
-        id: String,
-        name: String,
-        input: serde_json::Value,
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockDelta {
+    InputJsonDelta {
+        partial_json: String,
     },
-    #[serde(rename = "text")]
-    Text { text: String },
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Deserialize)]
-struct ApiResponse {
-    content: Vec<ContentBlock>,
+struct MessageDelta {
+    #[serde(default)]
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StreamError {
+    message: String,
+}
+
+/// A streamed answer, put together as its events arrive
+#[derive(Default)]
+struct Answer {
+    /// Start of an event line not fully received yet
+    pending: Vec<u8>,
+    /// Tool input JSON so far
+    input: String,
+    stop_reason: Option<String>,
+    error: Option<String>,
+}
+
+impl Answer {
+    /// Take a piece of the response body, returning whether the tool input grew
+    fn push(&mut self, bytes: &[u8]) -> bool {
+        self.pending.extend_from_slice(bytes);
+        let mut grew = false;
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim_end().strip_prefix("data:") else {
+                continue;
+            };
+            match serde_json::from_str(data.trim()) {
+                Ok(StreamEvent::ContentBlockDelta {
+                    delta: BlockDelta::InputJsonDelta { partial_json },
+                }) => {
+                    self.input.push_str(&partial_json);
+                    grew = true;
+                }
+                Ok(StreamEvent::MessageDelta { delta }) => self.stop_reason = delta.stop_reason,
+                Ok(StreamEvent::Error { error }) => self.error = Some(error.message),
+                _ => {}
+            }
+        }
+        grew
+    }
+}
+
+/// Check on the tool input JSON so far, stopping the answer with an error
+type OnInput<'a> = &'a (dyn Fn(&str) -> Result<(), String> + Sync);
+
+/// Claude's answer was cut off at this many tokens
+#[derive(Debug)]
+struct Truncated(u32);
+
+impl std::fmt::Display for Truncated {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Claude response truncated at {} tokens", self.0)
+    }
 }
 
+impl std::error::Error for Truncated {}
+
 #[derive(Deserialize)]
 struct CorrectionResult {
     #[serde(default)]
@@ -65,19 +175,370 @@ struct CorrectionResult {
     explanation: Option<String>,
 }
 
+/// Receives the corrected text so far
+pub type OnPartial = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Sampling parameters and extra instructions for a Claude call
+#[derive(Clone, Default)]
+pub struct Tuning {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    /// Sent as the system prompt
+    pub system: Option<String>,
+    /// Repository and branch the user is working in, given as context
+    pub project: Option<String>,
+    /// Names from the user's notes, offered when they sound like the text
+    pub notes: Option<Arc<Notes>>,
+    /// Text of the segments the backend was unsure of, the only part notes
+    /// are looked up for; `None` when it gave no confidence scores
+    pub uncertain: Option<String>,
+    /// Date, time and calendar event of the dictation, given as context
+    pub moment: Option<String>,
+    /// Text dictated just before, that this dictation continues (`rec continue`)
+    pub preceding: Option<String>,
+    /// Called with the corrected text so far as Claude's answer streams in
+    pub on_partial: Option<OnPartial>,
+    /// Filler words to remove along with others like them (`fillers: prompt`)
+    pub fillers: Vec<String>,
+}
+
 pub struct CorrectionOutput {
     pub corrected: Option<String>,
     pub explanation: Option<String>,
 }
 
+/// Stand-in for Claude when `mock.enabled` is set
+///
+/// Waits the configured latency, then applies the configured replacements;
+/// any other text comes back unchanged.
+pub async fn mock_correction(text: &str, mock: &MockConfig) -> CorrectionOutput {
+    tokio::time::sleep(std::time::Duration::from_millis(mock.latency_ms)).await;
+
+    let mut corrected = text.to_string();
+    let mut fixed = Vec::new();
+    for (original, replacement) in &mock.corrections {
+        if corrected.contains(original.as_str()) {
+            corrected = corrected.replace(original.as_str(), replacement);
+            fixed.push(format!("'{}' -> '{}'", original, replacement));
+        }
+    }
+
+    CorrectionOutput {
+        corrected: (corrected != text).then_some(corrected),
+        explanation: (!fixed.is_empty()).then(|| format!("Mock: {}", fixed.join(", "))),
+    }
+}
+
 /// Correct transcription using Claude API
+///
+/// Long transcriptions are split into chunks at sentence boundaries, which
+/// are corrected concurrently and then harmonized in a final pass, so the
+/// answer never has to fit the whole text.
 pub async fn correct_transcription(
     text: &str,
     custom_words: &[String],
     model: &str,
     api_key: &str,
     history: &[HistoryEntry],
+    tuning: &Tuning,
 ) -> Result<CorrectionOutput, Box<dyn std::error::Error>> {
+    // The dictation continued is context for the first chunk
+    let preceding = tuning.preceding.as_deref().map(tail);
+    let chunks = split_chunks(text, CHUNK_CHARS);
+    if chunks.len() <= 1 {
+        return Ok(correct_window(
+            text,
+            preceding,
+            custom_words,
+            model,
+            api_key,
+            history,
+            tuning,
+        )
+        .await?);
+    }
+
+    // Chunks stream side by side, so only a text corrected whole shows its progress
+    let tuning = &Tuning {
+        on_partial: None,
+        ..tuning.clone()
+    };
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(CHUNK_CONCURRENCY));
+    let custom_words: Arc<[String]> = custom_words.into();
+    let history: Arc<[HistoryEntry]> = history.into();
+    let mut tasks = tokio::task::JoinSet::new();
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let semaphore = semaphore.clone();
+        let custom_words = custom_words.clone();
+        let history = history.clone();
+        let chunk = chunk.clone();
+        let context = match i.checked_sub(1) {
+            Some(prev) => Some(tail(&chunks[prev]).to_string()),
+            None => preceding.map(str::to_string),
+        };
+        let model = model.to_string();
+        let api_key = api_key.to_string();
+        let tuning = tuning.clone();
+
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let output = correct_window(
+                &chunk,
+                context.as_deref(),
+                &custom_words,
+                &model,
+                &api_key,
+                &history,
+                &tuning,
+            )
+            .await;
+            (i, output)
+        });
+    }
+
+    let mut outputs: Vec<CorrectionOutput> = chunks
+        .iter()
+        .map(|_| CorrectionOutput {
+            corrected: None,
+            explanation: None,
+        })
+        .collect();
+
+    while let Some(result) = tasks.join_next().await {
+        let (i, output) = result?;
+        match output {
+            Ok(output) => outputs[i] = output,
+            Err(e) => eprintln!(
+                "Correction of part {} failed, keeping it as is: {}",
+                i + 1,
+                e
+            ),
+        }
+    }
+
+    let joined = join_outputs(&chunks, outputs);
+    let Some(mut text) = joined.corrected else {
+        return Ok(joined);
+    };
+    let mut explanations: Vec<String> = joined.explanation.into_iter().collect();
+
+    match harmonize(&text, model, api_key, tuning).await {
+        Ok(fixes) => {
+            for (from, to) in fixes {
+                if !from.is_empty() && text.contains(&from) {
+                    text = text.replace(&from, &to);
+                    explanations.push(format!("\"{}\" → \"{}\" for consistency", from, to));
+                }
+            }
+        }
+        Err(e) => eprintln!(
+            "Coherence pass failed, keeping corrected parts as is: {}",
+            e
+        ),
+    }
+
+    Ok(CorrectionOutput {
+        corrected: Some(text),
+        explanation: (!explanations.is_empty()).then(|| explanations.join("; ")),
+    })
+}
+
+type Window<'a> = Pin<Box<dyn Future<Output = Result<CorrectionOutput, String>> + Send + 'a>>;
+
+/// Correct a window of text, splitting it in smaller windows if the answer
+/// was cut off even at `MAX_TOKENS_RETRY`
+///
+/// Each smaller window sees the end of the previous one as context so
+/// corrections stay consistent across the split.
+fn correct_window<'a>(
+    text: &'a str,
+    context: Option<&'a str>,
+    custom_words: &'a [String],
+    model: &'a str,
+    api_key: &'a str,
+    history: &'a [HistoryEntry],
+    tuning: &'a Tuning,
+) -> Window<'a> {
+    Box::pin(async move {
+        let result = correct_chunk(text, context, custom_words, model, api_key, history, tuning)
+            .await
+            .map_err(|e| (e.is::<Truncated>(), e.to_string()));
+
+        let error = match result {
+            Ok(output) => return Ok(output),
+            Err((false, error)) => return Err(error),
+            Err((true, error)) => error,
+        };
+
+        let windows = split_chunks(text, text.len() / 2 + 1);
+        if windows.len() < 2 {
+            return Err(error);
+        }
+
+        let mut outputs = Vec::new();
+        for (i, window) in windows.iter().enumerate() {
+            let context = match i {
+                0 => context,
+                _ => Some(tail(&windows[i - 1])),
+            };
+            outputs.push(
+                correct_window(
+                    window,
+                    context,
+                    custom_words,
+                    model,
+                    api_key,
+                    history,
+                    tuning,
+                )
+                .await?,
+            );
+        }
+
+        Ok(join_outputs(&windows, outputs))
+    })
+}
+
+/// Reassemble the corrections of consecutive pieces of text
+///
+/// Pieces are joined with the whitespace that separated them in the
+/// original (a paragraph break stays a paragraph break).
+fn join_outputs(parts: &[String], outputs: Vec<CorrectionOutput>) -> CorrectionOutput {
+    let changed = outputs.iter().any(|o| o.corrected.is_some());
+    let mut joined = String::new();
+    let mut explanations = Vec::new();
+
+    for (part, output) in parts.iter().zip(outputs) {
+        if !joined.is_empty() {
+            let separator = &part[..part.len() - part.trim_start().len()];
+            joined.push_str(if separator.is_empty() { " " } else { separator });
+        }
+        let text = output.corrected.unwrap_or_else(|| part.clone());
+        joined.push_str(text.trim());
+        explanations.extend(output.explanation);
+    }
+
+    CorrectionOutput {
+        corrected: changed.then_some(joined),
+        explanation: (!explanations.is_empty()).then(|| explanations.join("; ")),
+    }
+}
+
+/// End of a piece of text, shown as context when correcting the next one
+fn tail(text: &str) -> &str {
+    let text = text.trim_end();
+    let mut start = text.len().saturating_sub(CONTEXT_CHARS);
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    match text[start..].find(char::is_whitespace) {
+        Some(space) if start > 0 => text[start + space..].trim_start(),
+        _ => &text[start..],
+    }
+}
+
+/// Split text into chunks of at most `max_chars`, at sentence boundaries
+///
+/// A single sentence longer than `max_chars` becomes a chunk of its own.
+fn split_chunks(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for sentence in crate::text::sentences(text) {
+        if !current.is_empty() && current.len() + sentence.len() > max_chars {
+            chunks.push(std::mem::take(&mut current));
+        }
+        current.push_str(sentence);
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+/// Find inconsistencies between independently corrected chunks
+///
+/// Returns exact replacements rather than the whole text, so the answer
+/// stays short however long the transcription is.
+async fn harmonize(
+    text: &str,
+    model: &str,
+    api_key: &str,
+    tuning: &Tuning,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let prompt = format!(
+        r#"This voice transcription was corrected in several parts independently, then joined.
+
+Transcription:
+{}
+
+Use the 'report_fixes' tool to list ONLY fixes needed because the parts were corrected separately:
+- the same term or name spelled differently in different places
+- a sentence broken or duplicated where two parts meet
+
+Each fix is an exact substring of the transcription and its replacement. Do not rephrase anything else.
+If nothing needs fixing, report an empty list."#,
+        text
+    );
+
+    let mut properties = std::collections::HashMap::new();
+    properties.insert(
+        "fixes".to_string(),
+        ToolProperty {
+            r#type: "array".to_string(),
+            description:
+                "Fixes to apply, each with the exact 'original' text and its 'replacement'"
+                    .to_string(),
+            items: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "original": {"type": "string"},
+                    "replacement": {"type": "string"}
+                },
+                "required": ["original", "replacement"]
+            })),
+        },
+    );
+
+    let tool = Tool {
+        name: "report_fixes".to_string(),
+        description: "Report consistency fixes for the joined transcription".to_string(),
+        input_schema: ToolInputSchema {
+            r#type: "object".to_string(),
+            properties,
+            required: vec!["fixes".to_string()],
+        },
+    };
+
+    let result = call_tool(model, api_key, prompt, tool, tuning).await?;
+
+    let fixes = result
+        .get("fixes")
+        .and_then(|v| v.as_array())
+        .map(|fixes| {
+            fixes
+                .iter()
+                .filter_map(|fix| {
+                    let original = fix.get("original")?.as_str()?;
+                    let replacement = fix.get("replacement")?.as_str()?;
+                    Some((original.to_string(), replacement.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(fixes)
+}
+
+/// Prompt asking Claude to correct a single piece of transcription
+fn correction_prompt(
+    text: &str,
+    preceding: Option<&str>,
+    custom_words: &[String],
+    history: &[HistoryEntry],
+    tuning: &Tuning,
+) -> String {
     let custom_words_list = if custom_words.is_empty() {
         "(no custom words configured)".to_string()
     } else {
@@ -88,22 +549,75 @@ pub async fn correct_transcription(
             .join("\n")
     };
 
-    let context = if history.is_empty() {
-        String::new()
-    } else {
-        let recent = history.iter().rev().take(5).rev();
-        let mut ctx = String::from("\nContext (previous corrections):\n");
-        for entry in recent {
-            ctx.push_str(&format!(
+    let is_bad = |entry: &&HistoryEntry| entry.feedback == Some(Feedback::Bad);
+    let good: Vec<_> = history
+        .iter()
+        .filter(|e| !is_bad(e) && e.original != e.corrected)
+        .collect();
+    let bad: Vec<_> = history.iter().filter(is_bad).collect();
+
+    let mut context = String::new();
+    if !good.is_empty() {
+        context.push_str("\nContext (previous corrections):\n");
+        for entry in good.iter().rev().take(5).rev() {
+            context.push_str(&format!(
                 "- Original: \"{}\"\n  Corrected: \"{}\"\n",
                 entry.original, entry.corrected
             ));
         }
-        ctx.push('\n');
-        ctx
+        context.push('\n');
+    }
+    if !bad.is_empty() {
+        context.push_str(
+            "\nRejected corrections (the user marked these as wrong, do NOT repeat them):\n",
+        );
+        for entry in bad.iter().rev().take(5).rev() {
+            context.push_str(&format!(
+                "- Original: \"{}\"\n  Wrong correction: \"{}\"\n",
+                entry.original, entry.corrected
+            ));
+        }
+        context.push('\n');
+    }
+    if let Some(project) = &tuning.project {
+        context.push_str(&format!(
+            "\nThe user is working in the git repository {}; names from it are likely terms.\n\n",
+            project
+        ));
+    }
+    if let Some(moment) = &tuning.moment {
+        context.push_str(&format!(
+            "\nThe user is dictating on {}; event titles and names in it are likely terms.\n\n",
+            moment
+        ));
+    }
+    let notes = tuning
+        .notes
+        .as_ref()
+        .map(|notes| notes.matches(tuning.uncertain.as_deref().unwrap_or(text)))
+        .unwrap_or_default();
+    if !notes.is_empty() {
+        context.push_str(&format!(
+            "\nNames from the user's notes that sound like words of the transcription (use ONLY if phonetically similar):\n{}\n\n",
+            notes.join("\n")
+        ));
+    }
+    if let Some(preceding) = preceding {
+        context.push_str(&format!(
+            "\nText just before this transcription (for context only, do NOT include it in your answer):\n{}\n\n",
+            preceding
+        ));
+    }
+
+    let fillers_rule = match tuning.fillers.is_empty() {
+        true => String::new(),
+        false => format!(
+            ", except:\n9. Remove hesitations and filler words such as {}, and words like \"like\" or \"you know\" only where they are fillers, with the commas setting them off",
+            tuning.fillers.join(", ")
+        ),
     };
 
-    let prompt = format!(
+    format!(
         r#"You are a voice transcription corrector. Your job is to fix ONLY obvious transcription errors based on phonetic similarity.
 
 Custom technical terms (use ONLY if phonetically similar):
@@ -116,7 +630,7 @@ Custom technical terms (use ONLY if phonetically similar):
 5. DO NOT replace words with technical terms unless they are phonetically very similar
 6. When in doubt, prefer keeping the original text unchanged
 7. Preserve the original meaning, punctuation, and sentence structure
-8. Don't translate, don't add or remove content
+8. Don't translate, don't add or remove content{}
 
 IMPORTANT: Be conservative. Only make corrections you are confident about based on phonetics, not on what might make sense given the context.
 
@@ -126,9 +640,160 @@ Original transcription:
 Use the 'report_correction' tool:
 - If correction is needed: provide 'corrected' with the corrected text and 'explanation' with a brief reason
 - If no correction is needed: call the tool with empty strings for both fields"#,
-        custom_words_list, context, text
+        custom_words_list, context, fillers_rule, text
+    )
+}
+
+/// The prompts `correct_transcription` would send, without calling the API
+///
+/// Covers the system prompt and one correction prompt per chunk; the final
+/// harmonization pass depends on Claude's answers and is not included.
+pub fn preview_prompts(
+    text: &str,
+    custom_words: &[String],
+    history: &[HistoryEntry],
+    tuning: &Tuning,
+) -> String {
+    let mut out = String::new();
+    if let Some(system) = &tuning.system {
+        out.push_str(&format!("=== System ===\n{}\n\n", system));
+    }
+
+    let preceding = tuning.preceding.as_deref().map(tail);
+    let chunks = split_chunks(text, CHUNK_CHARS);
+    if chunks.len() <= 1 {
+        out.push_str(&correction_prompt(
+            text,
+            preceding,
+            custom_words,
+            history,
+            tuning,
+        ));
+        out.push('\n');
+        return out;
+    }
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let context = match i.checked_sub(1) {
+            Some(prev) => Some(tail(&chunks[prev])),
+            None => preceding,
+        };
+        out.push_str(&format!("=== Chunk {}/{} ===\n", i + 1, chunks.len()));
+        out.push_str(&correction_prompt(
+            chunk,
+            context,
+            custom_words,
+            history,
+            tuning,
+        ));
+        out.push_str("\n\n");
+    }
+    out
+}
+
+/// Group a long transcript into chapters
+///
+/// `parts` are consecutive pieces of the transcript with their start time in
+/// seconds. Returns the index of the part each chapter starts at, with its
+/// title, in order.
+pub async fn chapters(
+    parts: &[(f32, &str)],
+    model: &str,
+    api_key: &str,
+) -> Result<Vec<(usize, String)>, Box<dyn std::error::Error>> {
+    let numbered: Vec<String> = parts
+        .iter()
+        .enumerate()
+        .map(|(i, (start, text))| format!("[{}] ({}) {}", i, timestamp(*start), text.trim()))
+        .collect();
+
+    let prompt = format!(
+        r#"This is a transcript of a long recording (a meeting, lecture or talk), in numbered parts with their start time.
+
+{}
+
+Use the 'report_chapters' tool to divide it into chapters, one per topic, in order:
+- 'start' is the number of the part where the chapter begins; the first chapter starts at 0
+- 'title' is a short heading (a few words) in the language of the transcript
+Aim for chapters of a few minutes each; don't create a chapter for every part."#,
+        numbered.join("\n")
     );
 
+    let mut properties = std::collections::HashMap::new();
+    properties.insert(
+        "chapters".to_string(),
+        ToolProperty {
+            r#type: "array".to_string(),
+            description: "Chapters in order, each with its 'start' part number and 'title'"
+                .to_string(),
+            items: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "start": {"type": "integer"},
+                    "title": {"type": "string"}
+                },
+                "required": ["start", "title"]
+            })),
+        },
+    );
+
+    let tool = Tool {
+        name: "report_chapters".to_string(),
+        description: "Report the chapters of the transcript".to_string(),
+        input_schema: ToolInputSchema {
+            r#type: "object".to_string(),
+            properties,
+            required: vec!["chapters".to_string()],
+        },
+    };
+
+    let result = call_tool(model, api_key, prompt, tool, &Tuning::default()).await?;
+
+    let mut chapters: Vec<(usize, String)> = result
+        .get("chapters")
+        .and_then(|v| v.as_array())
+        .map(|chapters| {
+            chapters
+                .iter()
+                .filter_map(|chapter| {
+                    let start = chapter.get("start")?.as_u64()? as usize;
+                    let title = chapter.get("title")?.as_str()?.trim();
+                    (start < parts.len() && !title.is_empty()).then(|| (start, title.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    chapters.sort_by_key(|(start, _)| *start);
+    chapters.dedup_by_key(|(start, _)| *start);
+    Ok(chapters)
+}
+
+/// Time as m:ss, or h:mm:ss past an hour
+pub fn timestamp(seconds: f32) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    let (h, m, s) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
+
+/// Correct a single piece of transcription
+///
+/// `preceding` is the text just before it, shown to Claude for context only.
+async fn correct_chunk(
+    text: &str,
+    preceding: Option<&str>,
+    custom_words: &[String],
+    model: &str,
+    api_key: &str,
+    history: &[HistoryEntry],
+    tuning: &Tuning,
+) -> Result<CorrectionOutput, Box<dyn std::error::Error>> {
+    let prompt = correction_prompt(text, preceding, custom_words, history, tuning);
+
     // Define the correction tool schema
     let mut properties = std::collections::HashMap::new();
     properties.insert(
@@ -138,6 +803,7 @@ Use the 'report_correction' tool:
             description:
                 "The corrected transcription text, or empty string if no correction needed"
                     .to_string(),
+            items: None,
         },
     );
     properties.insert(
@@ -146,6 +812,7 @@ Use the 'report_correction' tool:
             r#type: "string".to_string(),
             description: "Brief explanation of changes made, or empty string if no changes"
                 .to_string(),
+            items: None,
         },
     );
 
@@ -159,57 +826,363 @@ Use the 'report_correction' tool:
         },
     };
 
+    let on_input = |input: &str| {
+        let Some(corrected) = partial_string(input, "corrected") else {
+            return Ok(());
+        };
+        if corrected.chars().count() > text.chars().count() * 3 / 2 + RUNAWAY_CHARS {
+            return Err("Stopped a correction running far longer than the transcription".into());
+        }
+        if let Some(on_partial) = &tuning.on_partial {
+            on_partial(&corrected);
+        }
+        Ok(())
+    };
+    let tool_input = stream_tool(model, api_key, prompt, tool, tuning, &on_input).await?;
+
+    // Parse the tool input as CorrectionResult
+    let correction: CorrectionResult = serde_json::from_value(tool_input)
+        .map_err(|e| format!("Failed to parse tool input: {}", e))?;
+
+    // If correction fields are empty, return None
+    let corrected = correction.corrected.filter(|s| !s.is_empty());
+    let explanation = correction.explanation.filter(|s| !s.is_empty());
+
+    Ok(CorrectionOutput {
+        corrected,
+        explanation,
+    })
+}
+
+/// Send a single-message prompt to Claude, forcing it to answer with `tool`
+async fn call_tool(
+    model: &str,
+    api_key: &str,
+    prompt: String,
+    tool: Tool,
+    tuning: &Tuning,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    stream_tool(model, api_key, prompt, tool, tuning, &|_| Ok(())).await
+}
+
+/// `call_tool`, passing the tool input to `on_input` as it streams in
+///
+/// An error from `on_input` stops the answer there, as Claude is clearly
+/// going astray, without waiting for the rest. An answer cut off by its
+/// token limit is asked again with a higher one rather than returned short.
+async fn stream_tool(
+    model: &str,
+    api_key: &str,
+    prompt: String,
+    tool: Tool,
+    tuning: &Tuning,
+    on_input: OnInput<'_>,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let mut max_tokens = MAX_TOKENS;
+    loop {
+        let result =
+            stream_tool_once(model, api_key, &prompt, &tool, tuning, on_input, max_tokens).await;
+        match result {
+            Err(e) if e.is::<Truncated>() && max_tokens < MAX_TOKENS_RETRY => {
+                max_tokens = (max_tokens * 4).min(MAX_TOKENS_RETRY);
+                crate::progress::stage(&format!("Cut off, retrying with {} tokens", max_tokens));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// One request of `stream_tool`, with this token limit
+async fn stream_tool_once(
+    model: &str,
+    api_key: &str,
+    prompt: &str,
+    tool: &Tool,
+    tuning: &Tuning,
+    on_input: OnInput<'_>,
+    max_tokens: u32,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let tool_choice = serde_json::json!({"type": "tool", "name": tool.name});
     let request = ApiRequest {
         model: model.to_string(),
-        max_tokens: 1024,
+        max_tokens,
+        system: tuning.system.clone(),
+        temperature: tuning.temperature,
+        top_p: tuning.top_p,
         messages: vec![Message {
             role: "user".to_string(),
-            content: prompt,
+            content: prompt.to_string(),
         }],
-        tools: vec![tool],
-        tool_choice: serde_json::json!({"type": "tool", "name": "report_correction"}),
+        tools: vec![tool.clone()],
+        tool_choice,
+        stream: true,
     };
 
+    let mut answer = Answer::default();
+    let mut stopped = None;
     let client = reqwest::Client::new();
-    let resp = client
-        .post("https://api.anthropic.com/v1/messages")
-        .header("x-api-key", api_key)
-        .header("anthropic-version", "2023-06-01")
-        .header("content-type", "application/json")
-        .json(&request)
-        .send()
-        .await?;
+    let resp = http::send_streaming(
+        "claude",
+        client
+            .post("https://api.anthropic.com/v1/messages")
+            .header("x-api-key", api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("content-type", "application/json")
+            .json(&request),
+        |bytes| {
+            if !answer.push(bytes) {
+                return true;
+            }
+            stopped = on_input(&answer.input).err();
+            stopped.is_none()
+        },
+    )
+    .await?;
+
+    if !resp.status.is_success() {
+        return Err(format!("Claude API error: {}", resp.body).into());
+    }
+    if let Some(reason) = stopped {
+        return Err(reason.into());
+    }
+    if let Some(error) = answer.error {
+        return Err(format!("Claude API error: {}", error).into());
+    }
+    if answer.stop_reason.as_deref() == Some("max_tokens") {
+        return Err(Box::new(Truncated(max_tokens)));
+    }
+    if answer.input.is_empty() {
+        return Err("No tool_use in Claude response".into());
+    }
+
+    serde_json::from_str(&answer.input).map_err(|e| {
+        format!(
+            "Failed to parse API response: {}\nTool input: {}",
+            e, answer.input
+        )
+        .into()
+    })
+}
+
+/// Value of a string field in JSON cut off anywhere, as far as it goes
+///
+/// Lets a streamed tool input be shown before it is complete.
+fn partial_string(json: &str, field: &str) -> Option<String> {
+    let key = format!("\"{}\"", field);
+    let rest = json[json.find(&key)? + key.len()..].trim_start();
+    let rest = rest.strip_prefix(':')?.trim_start().strip_prefix('"')?;
+
+    let mut value = String::new();
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('r') => value.push('\r'),
+                Some('u') => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    match u32::from_str_radix(&code, 16).ok().and_then(char::from_u32) {
+                        Some(c) => value.push(c),
+                        // Cut off mid-escape, or half a surrogate pair
+                        None => break,
+                    }
+                }
+                Some(c) => value.push(c),
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+    Some(value)
+}
+
+/// Slot names (`{name}`) used in a template, in order of first appearance
+pub fn template_slots(template: &str) -> Vec<String> {
+    let mut slots: Vec<String> = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('}') else { break };
+        let name = rest[..end].trim();
+        if !name.is_empty() && !slots.iter().any(|s| s == name) {
+            slots.push(name.to_string());
+        }
+        rest = &rest[end + 1..];
+    }
+
+    slots
+}
 
-    if !resp.status().is_success() {
-        let body = resp.text().await?;
-        return Err(format!("Claude API error: {}", body).into());
+/// Map a dictation onto the slots of a template and return the filled template
+pub async fn fill_template(
+    text: &str,
+    template: &str,
+    moment: Option<&str>,
+    model: &str,
+    api_key: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let slots = template_slots(template);
+    if slots.is_empty() {
+        return Err(format!("Template has no {{slots}}: {}", template).into());
     }
 
-    let body_text = resp.text().await?;
+    // Dictations say "today" or "the meeting we just had", templates want dates and titles
+    let when = moment
+        .map(|moment| {
+            format!(
+                "\nThe dictation was made on {}: resolve relative dates and references to events against it.\n",
+                moment
+            )
+        })
+        .unwrap_or_default();
+
+    let prompt = format!(
+        r#"You fill in templates from voice dictations.
+
+Template:
+{}
 
-    let result: ApiResponse = serde_json::from_str(&body_text)
-        .map_err(|e| format!("Failed to parse API response: {}\nBody: {}", e, body_text))?;
+Dictation:
+{}
+{}
+Use the 'fill_template' tool to extract the value of each slot from the dictation.
+Keep the speaker's wording, only trim filler and fix the grammar so each value reads naturally in the template.
+If the dictation says nothing about a slot, use an empty string."#,
+        template, text, when
+    );
 
-    // Find the tool_use content block
-    let tool_input = result
-        .content
+    let properties = slots
         .iter()
-        .find_map(|block| match block {
-            ContentBlock::ToolUse { input, .. } => Some(input),
-            _ => None,
+        .map(|slot| {
+            (
+                slot.clone(),
+                ToolProperty {
+                    r#type: "string".to_string(),
+                    description: format!("Value for the {{{}}} slot", slot),
+                    items: None,
+                },
+            )
         })
-        .ok_or("No tool_use in Claude response")?;
+        .collect();
 
-    // Parse the tool input as CorrectionResult
-    let correction: CorrectionResult = serde_json::from_value(tool_input.clone())
-        .map_err(|e| format!("Failed to parse tool input: {}", e))?;
+    let tool = Tool {
+        name: "fill_template".to_string(),
+        description: "Report the value of each template slot".to_string(),
+        input_schema: ToolInputSchema {
+            r#type: "object".to_string(),
+            properties,
+            required: slots.clone(),
+        },
+    };
 
-    // If correction fields are empty, return None
-    let corrected = correction.corrected.filter(|s| !s.is_empty());
-    let explanation = correction.explanation.filter(|s| !s.is_empty());
+    let values = call_tool(model, api_key, prompt, tool, &Tuning::default()).await?;
 
-    Ok(CorrectionOutput {
-        corrected,
-        explanation,
-    })
+    let mut filled = template.to_string();
+    for slot in &slots {
+        let value = values.get(slot).and_then(|v| v.as_str()).unwrap_or("");
+        filled = filled.replace(&format!("{{{}}}", slot), value.trim());
+    }
+
+    Ok(filled)
+}
+
+/// Answer a spoken question, in plain text that reads well aloud
+pub async fn answer(
+    question: &str,
+    model: &str,
+    api_key: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let prompt = format!(
+        r#"You are a voice assistant. Answer the user's spoken question below.
+It comes from speech recognition, so read past small transcription errors.
+Answer in the language of the question, concisely, in plain text without Markdown,
+since the answer may be read aloud.
+
+Question:
+{}
+
+Use the 'report_answer' tool."#,
+        question
+    );
+
+    let mut properties = std::collections::HashMap::new();
+    properties.insert(
+        "answer".to_string(),
+        ToolProperty {
+            r#type: "string".to_string(),
+            description: "The answer to the question".to_string(),
+            items: None,
+        },
+    );
+
+    let tool = Tool {
+        name: "report_answer".to_string(),
+        description: "Report the answer to the question".to_string(),
+        input_schema: ToolInputSchema {
+            r#type: "object".to_string(),
+            properties,
+            required: vec!["answer".to_string()],
+        },
+    };
+
+    let result = call_tool(model, api_key, prompt, tool, &Tuning::default()).await?;
+
+    result
+        .get("answer")
+        .and_then(|v| v.as_str())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| "Empty answer in Claude response".into())
+}
+
+/// Translate a transcription into another language
+pub async fn translate(
+    text: &str,
+    language: &str,
+    model: &str,
+    api_key: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let prompt = format!(
+        r#"Translate this voice transcription into the language with code or name "{}".
+Keep the tone, technical terms, code identifiers and product names as they are.
+If it is already in that language, return it unchanged.
+
+Transcription:
+{}
+
+Use the 'report_translation' tool."#,
+        language, text
+    );
+
+    let mut properties = std::collections::HashMap::new();
+    properties.insert(
+        "translation".to_string(),
+        ToolProperty {
+            r#type: "string".to_string(),
+            description: "The translated text".to_string(),
+            items: None,
+        },
+    );
+
+    let tool = Tool {
+        name: "report_translation".to_string(),
+        description: "Report the translated transcription".to_string(),
+        input_schema: ToolInputSchema {
+            r#type: "object".to_string(),
+            properties,
+            required: vec!["translation".to_string()],
+        },
+    };
+
+    let result = call_tool(model, api_key, prompt, tool, &Tuning::default()).await?;
+
+    result
+        .get("translation")
+        .and_then(|v| v.as_str())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| "Empty translation in Claude response".into())
 }
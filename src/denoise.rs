@@ -0,0 +1,63 @@
+//! Background noise suppression for recordings (`--denoise`)
+//!
+//! RNNoise, a small recurrent network trained to tell speech from fans, hum
+//! and chatter, runs in-process through nnnoiseless, its Rust port, which
+//! ships its model. It works on 48 kHz audio, so each channel is resampled
+//! to 48 kHz and back.
+
+use crate::enhance;
+use nnnoiseless::DenoiseState;
+
+/// Sample rate RNNoise works at
+const RATE: u32 = 48000;
+
+/// Samples RNNoise takes at a time (10 ms)
+const FRAME: usize = DenoiseState::FRAME_SIZE;
+
+/// Suppress background noise in interleaved samples between -1 and 1
+pub fn denoise(samples: &[f32], sample_rate: u32, channels: u16) -> Vec<f32> {
+    let channels = channels.max(1) as usize;
+    let denoised: Vec<Vec<f32>> = (0..channels)
+        .map(|channel| {
+            let samples: Vec<f32> = samples
+                .iter()
+                .skip(channel)
+                .step_by(channels)
+                .copied()
+                .collect();
+            let samples = enhance::to_rate(&samples, sample_rate, RATE);
+            enhance::to_rate(&denoise_channel(&samples), RATE, sample_rate)
+        })
+        .collect();
+
+    // Resampling there and back may leave a sample more or less
+    (0..samples.len() / channels)
+        .flat_map(|i| {
+            denoised
+                .iter()
+                .map(move |channel| channel.get(i).copied().unwrap_or(0.0))
+        })
+        .collect()
+}
+
+/// One channel of 48 kHz audio through RNNoise
+///
+/// Each frame comes out one frame late, so the input gets a frame of
+/// silence at the end and the first frame out is dropped.
+fn denoise_channel(samples: &[f32]) -> Vec<f32> {
+    // RNNoise expects the range of 16-bit samples
+    let mut input: Vec<f32> = samples.iter().map(|s| s * 32768.0).collect();
+    input.resize((samples.len().div_ceil(FRAME) + 1) * FRAME, 0.0);
+
+    let mut state = DenoiseState::new();
+    let mut frame = [0.0; FRAME];
+    let mut output = Vec::with_capacity(input.len());
+    for chunk in input.chunks_exact(FRAME) {
+        state.process_frame(&mut frame, chunk);
+        output.extend(frame.iter().map(|s| s / 32768.0));
+    }
+
+    output.drain(..FRAME);
+    output.truncate(samples.len());
+    output
+}
@@ -0,0 +1,257 @@
+//! Cleanup for phone-quality recordings (--enhance)
+//!
+//! Narrowband audio (8 kHz calls, voicemails) transcribes poorly as is: the
+//! hum below the voice band and the hiss across it throw models off. This
+//! keeps the telephone band, upsamples to the 16 kHz models expect and gates
+//! out the steady background noise.
+
+use crate::audio::encode_wav;
+use realfft::RealFftPlanner;
+use realfft::num_complex::Complex;
+
+/// Sample rate speech models are trained on
+const TARGET_RATE: u32 = 16000;
+
+/// Telephone voice band, in Hz
+const LOW_CUT: f32 = 300.0;
+const HIGH_CUT: f32 = 3400.0;
+
+/// FFT frame length for noise gating (32 ms at 16 kHz), with 50% overlap
+const FRAME: usize = 512;
+const HOP: usize = FRAME / 2;
+
+/// Share of the quietest frames used to estimate the background noise
+const NOISE_QUANTILE: f32 = 0.1;
+
+/// How far above the noise level a bin must be to pass untouched
+const GATE_FACTOR: f32 = 2.0;
+
+/// Gain left on gated bins; removing them entirely sounds watery
+const GATE_FLOOR: f32 = 0.1;
+
+/// Peak level the result is normalized to
+const PEAK: f32 = 0.9;
+
+/// Band-pass, upsample and denoise a WAV file, returning 16 kHz mono WAV
+pub fn enhance(wav_data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let (samples, rate) =
+        decode_mono(wav_data).map_err(|e| format!("--enhance needs a WAV file: {}", e))?;
+
+    // Band-pass before resampling so nothing aliases, and after to remove
+    // the images linear interpolation leaves above the band
+    let samples = band_pass(&samples, rate);
+    let samples = resample(&samples, rate, TARGET_RATE);
+    let samples = band_pass(&samples, TARGET_RATE);
+    let mut samples = spectral_gate(&samples)?;
+
+    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    if peak > 0.0 {
+        let gain = PEAK / peak;
+        samples.iter_mut().for_each(|s| *s *= gain);
+    }
+
+    encode_wav(&samples, TARGET_RATE, 1)
+}
+
+/// Downmix and resample a WAV file to the 16 kHz mono speech models expect
+///
+/// Higher rates are low-passed first so nothing folds back into the voice.
+pub fn to_model_rate(wav_data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let (samples, rate) = decode_mono(wav_data)?;
+    encode_wav(&to_rate(&samples, rate, TARGET_RATE), TARGET_RATE, 1)
+}
+
+/// Resample one channel, low-passing it first when the rate goes down so
+/// nothing folds back into the voice
+pub fn to_rate(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from <= to {
+        return resample(samples, from, to);
+    }
+
+    let cutoff = to as f32 * 0.45;
+    let mut samples = samples.to_vec();
+    for mut filter in [
+        Biquad::low_pass(cutoff, from),
+        Biquad::low_pass(cutoff, from),
+    ] {
+        samples.iter_mut().for_each(|s| *s = filter.process(*s));
+    }
+    resample(&samples, from, to)
+}
+
+/// Samples of a WAV file between -1 and 1, mixed down to mono
+fn decode_mono(wav_data: &[u8]) -> Result<(Vec<f32>, u32), Box<dyn std::error::Error>> {
+    let reader = hound::WavReader::new(std::io::Cursor::new(wav_data))
+        .map_err(|e| format!("Expected a WAV file: {}", e))?;
+    let spec = reader.spec();
+
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    let channels = spec.channels.max(1) as usize;
+    let mono = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    Ok((mono, spec.sample_rate))
+}
+
+/// Two cascaded high-pass and low-pass biquads (24 dB/octave each side)
+fn band_pass(samples: &[f32], rate: u32) -> Vec<f32> {
+    let high = HIGH_CUT.min(rate as f32 * 0.45);
+    let filters = [
+        Biquad::high_pass(LOW_CUT, rate),
+        Biquad::high_pass(LOW_CUT, rate),
+        Biquad::low_pass(high, rate),
+        Biquad::low_pass(high, rate),
+    ];
+
+    let mut out = samples.to_vec();
+    for mut filter in filters {
+        out.iter_mut().for_each(|s| *s = filter.process(*s));
+    }
+    out
+}
+
+/// Linear interpolation to another sample rate
+fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let len = (samples.len() as u64 * to as u64 / from as u64) as usize;
+    let step = from as f64 / to as f64;
+    (0..len)
+        .map(|i| {
+            let pos = i as f64 * step;
+            let index = pos as usize;
+            let frac = (pos - index as f64) as f32;
+            let a = samples[index.min(samples.len() - 1)];
+            let b = samples[(index + 1).min(samples.len() - 1)];
+            a + (b - a) * frac
+        })
+        .collect()
+}
+
+/// Attenuate frequency bins that don't rise clearly above the background
+///
+/// The noise spectrum is the average of the quietest frames, which in a
+/// call are the pauses between sentences.
+fn spectral_gate(samples: &[f32]) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+    if samples.len() < FRAME {
+        return Ok(samples.to_vec());
+    }
+
+    let mut planner = RealFftPlanner::<f32>::new();
+    let forward = planner.plan_fft_forward(FRAME);
+    let inverse = planner.plan_fft_inverse(FRAME);
+
+    // sqrt-Hann for analysis and synthesis sums to one at 50% overlap
+    let window: Vec<f32> = (0..FRAME)
+        .map(|i| (std::f32::consts::PI * i as f32 / FRAME as f32).sin())
+        .collect();
+
+    let mut spectra: Vec<Vec<Complex<f32>>> = Vec::new();
+    let mut input = forward.make_input_vec();
+    for start in (0..=samples.len() - FRAME).step_by(HOP) {
+        for (i, value) in input.iter_mut().enumerate() {
+            *value = samples[start + i] * window[i];
+        }
+        let mut spectrum = forward.make_output_vec();
+        forward.process(&mut input, &mut spectrum)?;
+        spectra.push(spectrum);
+    }
+
+    let energy = |spectrum: &Vec<Complex<f32>>| spectrum.iter().map(|c| c.norm_sqr()).sum::<f32>();
+    let mut order: Vec<usize> = (0..spectra.len()).collect();
+    order.sort_by(|&a, &b| energy(&spectra[a]).total_cmp(&energy(&spectra[b])));
+    let quiet = &order[..((spectra.len() as f32 * NOISE_QUANTILE) as usize).max(1)];
+
+    let bins = FRAME / 2 + 1;
+    let mut noise = vec![0.0f32; bins];
+    for &frame in quiet {
+        for (level, c) in noise.iter_mut().zip(&spectra[frame]) {
+            *level += c.norm() / quiet.len() as f32;
+        }
+    }
+
+    let mut out = vec![0.0f32; samples.len()];
+    let mut frame_out = inverse.make_output_vec();
+    for (n, spectrum) in spectra.iter_mut().enumerate() {
+        for (c, level) in spectrum.iter_mut().zip(&noise) {
+            let magnitude = c.norm();
+            if magnitude > 0.0 {
+                let gain = ((magnitude - level * GATE_FACTOR) / magnitude).clamp(GATE_FLOOR, 1.0);
+                *c *= gain;
+            }
+        }
+        // The inverse transform needs purely real DC and Nyquist bins
+        spectrum[0].im = 0.0;
+        spectrum[bins - 1].im = 0.0;
+        inverse.process(spectrum, &mut frame_out)?;
+
+        let start = n * HOP;
+        for (i, value) in frame_out.iter().enumerate() {
+            out[start + i] += value * window[i] / FRAME as f32;
+        }
+    }
+    Ok(out)
+}
+
+/// Second-order IIR filter (RBJ audio EQ cookbook)
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl Biquad {
+    fn high_pass(cutoff: f32, rate: u32) -> Self {
+        let (cos, alpha) = Self::coefficients(cutoff, rate);
+        Self::new(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    fn low_pass(cutoff: f32, rate: u32) -> Self {
+        let (cos, alpha) = Self::coefficients(cutoff, rate);
+        Self::new(
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// cos(w0) and alpha for a Butterworth (Q = 1/sqrt(2)) response
+    fn coefficients(cutoff: f32, rate: u32) -> (f32, f32) {
+        let w0 = 2.0 * std::f32::consts::PI * cutoff / rate as f32;
+        (w0.cos(), w0.sin() / std::f32::consts::SQRT_2)
+    }
+
+    fn new(b: [f32; 3], a: [f32; 3]) -> Self {
+        Self {
+            b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            a: [a[1] / a[0], a[2] / a[0]],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
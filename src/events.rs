@@ -0,0 +1,32 @@
+//! Newline-delimited JSON progress events on stdout (--jsonl)
+
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Switch stdout to JSONL events for the rest of the run
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Write one event line, e.g. `{"event":"level","rms":0.02}`
+pub fn emit(event: &str, data: serde_json::Value) {
+    if !enabled() {
+        return;
+    }
+
+    let mut object = serde_json::Map::new();
+    object.insert("event".to_string(), event.into());
+    if let serde_json::Value::Object(fields) = data {
+        object.extend(fields);
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", serde_json::Value::Object(object)).ok();
+    stdout.flush().ok();
+}
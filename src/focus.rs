@@ -0,0 +1,66 @@
+//! Application in the foreground, to pick a profile for it (`app_profiles`)
+
+use std::process::Command;
+
+/// Name of the focused application, lowercased (e.g. 'kitty', 'thunderbird')
+///
+/// Uses the window class on X11, sway and Hyprland, and the process name on
+/// macOS. None when it can't be told, e.g. on other Wayland compositors.
+pub fn focused_app() -> Option<String> {
+    let app = if cfg!(target_os = "macos") {
+        run(
+            "osascript",
+            &[
+                "-e",
+                "tell application \"System Events\" to get name of first application process whose frontmost is true",
+            ],
+        )
+    } else if std::env::var_os("SWAYSOCK").is_some() {
+        let tree: serde_json::Value =
+            serde_json::from_str(&run("swaymsg", &["-t", "get_tree"])?).ok()?;
+        sway_focused(&tree)
+    } else if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        let window: serde_json::Value =
+            serde_json::from_str(&run("hyprctl", &["activewindow", "-j"])?).ok()?;
+        window["class"].as_str().map(str::to_string)
+    } else {
+        x11_focused()
+    }?;
+
+    let app = app.trim().to_lowercase();
+    (!app.is_empty()).then_some(app)
+}
+
+/// app_id (Wayland) or window class (Xwayland) of the focused sway window
+fn sway_focused(node: &serde_json::Value) -> Option<String> {
+    if node["focused"].as_bool() == Some(true) {
+        return node["app_id"]
+            .as_str()
+            .or_else(|| node["window_properties"]["class"].as_str())
+            .map(str::to_string);
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[key].as_array())
+        .flatten()
+        .find_map(sway_focused)
+}
+
+/// Class of the active X11 window, from `xprop`
+fn x11_focused() -> Option<String> {
+    // _NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007
+    let active = run("xprop", &["-root", "_NET_ACTIVE_WINDOW"])?;
+    let id = active.split_whitespace().last()?;
+
+    // WM_CLASS(STRING) = "Navigator", "firefox": the second part is the class
+    let class = run("xprop", &["-id", id, "WM_CLASS"])?;
+    class.rsplit('"').nth(1).map(str::to_string)
+}
+
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
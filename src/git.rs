@@ -0,0 +1,60 @@
+//! Git repository a dictation was made in, so it can be traced back to
+//! what was being worked on
+
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GitConfig {
+    /// Tell Claude the repository and branch when correcting
+    #[serde(default)]
+    pub correction: bool,
+    /// Write the repository and branch before each dictation in the notes file (-o)
+    #[serde(default)]
+    pub notes_header: bool,
+}
+
+/// Repository and branch of the current directory
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct GitContext {
+    /// Name of the repository's top-level directory
+    pub repo: String,
+    /// None on a detached HEAD
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+}
+
+impl std::fmt::Display for GitContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.branch {
+            Some(branch) => write!(f, "{} ({})", self.repo, branch),
+            None => write!(f, "{}", self.repo),
+        }
+    }
+}
+
+/// Detect the repository of the current directory, if any
+///
+/// Returns None outside a repository or when git isn't installed.
+pub fn detect() -> Option<GitContext> {
+    let toplevel = git(&["rev-parse", "--show-toplevel"])?;
+    let repo = std::path::Path::new(&toplevel)
+        .file_name()?
+        .to_string_lossy()
+        .to_string();
+    // Empty on a detached HEAD; unlike rev-parse, works before the first commit
+    let branch = git(&["branch", "--show-current"]).filter(|b| !b.is_empty());
+    Some(GitContext { repo, branch })
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
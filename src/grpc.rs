@@ -0,0 +1,330 @@
+//! gRPC API of the server (`rec serve --grpc`)
+//!
+//! The same dictation as the HTTP API, with typed messages for clients such
+//! as GUIs and editor plugins, plus starting and stopping a recording from
+//! the server's microphone. The service is described in proto/rec.proto;
+//! these messages mirror it and build.rs generates the server.
+
+use crate::audio;
+use crate::backend::Transcript;
+use crate::config::{Config, Scope};
+use crate::instance;
+use crate::limits;
+use crate::server::{self, Server};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+use tokio::sync::{Mutex, oneshot};
+use tonic::{Request, Response, Status};
+
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/rec.Rec.rs"));
+}
+use generated::rec_server::{Rec, RecServer};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StartRequest {
+    #[prost(string, tag = "1")]
+    pub device: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StartReply {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StopRequest {
+    #[prost(bool, tag = "1")]
+    pub cancel: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TranscribeRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    pub audio: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Transcription {
+    #[prost(string, tag = "1")]
+    pub text: String,
+    #[prost(message, repeated, tag = "2")]
+    pub segments: Vec<Segment>,
+    #[prost(btree_map = "string, string", tag = "3")]
+    pub meta: BTreeMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Segment {
+    #[prost(float, tag = "1")]
+    pub start: f32,
+    #[prost(float, tag = "2")]
+    pub end: f32,
+    #[prost(string, tag = "3")]
+    pub text: String,
+    #[prost(float, optional, tag = "4")]
+    pub confidence: Option<f32>,
+    #[prost(string, optional, tag = "5")]
+    pub speaker: Option<String>,
+    #[prost(bool, tag = "6")]
+    pub low_confidence: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HistoryRequest {
+    #[prost(uint32, tag = "1")]
+    pub n: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HistoryReply {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: Vec<HistoryEntry>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HistoryEntry {
+    #[prost(string, tag = "1")]
+    pub timestamp: String,
+    #[prost(string, tag = "2")]
+    pub original: String,
+    #[prost(string, tag = "3")]
+    pub corrected: String,
+    #[prost(string, tag = "4")]
+    pub model: String,
+    #[prost(string, optional, tag = "5")]
+    pub session: Option<String>,
+    #[prost(btree_map = "string, string", tag = "6")]
+    pub meta: BTreeMap<String, String>,
+}
+
+/// Audio captured by a recording's thread: samples, sample rate, channels
+type Captured = (Vec<f32>, u32, u16);
+
+/// Recording started with `Start`
+///
+/// The recorder lives on a thread of its own, as audio streams can't move
+/// between the threads serving requests. Like a recording from the command
+/// line, it stops at the memory limits, and when another `rec` asks it to
+/// (`rec cancel`, `"when_busy": "stop"`).
+struct Recording {
+    /// Dropping it stops the capture
+    stop: std::sync::mpsc::Sender<instance::Request>,
+    /// The audio, or None when the recording was cancelled
+    captured: oneshot::Receiver<Option<Captured>>,
+    /// Task holding the lock that keeps other `rec`s off the microphone,
+    /// passing on what they ask
+    watch: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        self.watch.abort();
+    }
+}
+
+struct Service {
+    server: Arc<Server>,
+    recording: Mutex<Option<Recording>>,
+}
+
+/// Serve the gRPC API on `listener` until the process is stopped
+pub async fn serve(
+    server: Arc<Server>,
+    listener: tokio::net::TcpListener,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let service = RecServer::new(Service {
+        server,
+        recording: Mutex::new(None),
+    })
+    .max_decoding_message_size(server::MAX_UPLOAD);
+
+    tonic::transport::Server::builder()
+        .add_service(service)
+        .serve_with_incoming(tonic::transport::server::TcpIncoming::from(listener))
+        .await?;
+    Ok(())
+}
+
+impl Service {
+    /// Check the `authorization` metadata like the HTTP bearer token
+    fn authorize<T>(&self, request: &Request<T>, scope: Scope) -> Result<(), Status> {
+        let presented = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .unwrap_or("");
+        server::check_token(&self.server, presented, scope).map_err(
+            |(status, message)| match status {
+                axum::http::StatusCode::FORBIDDEN => Status::permission_denied(message),
+                _ => Status::unauthenticated(message),
+            },
+        )
+    }
+
+    async fn dictate(&self, wav_data: Vec<u8>) -> Result<Transcription, Status> {
+        match server::dictate(&self.server, wav_data, true).await {
+            Ok(transcript) => Ok(self.transcription(&transcript)),
+            Err(e) if e == audio::MUTED => Err(Status::failed_precondition(e)),
+            Err(e) => Err(Status::internal(e)),
+        }
+    }
+
+    fn transcription(&self, transcript: &Transcript) -> Transcription {
+        let threshold = self.server.pipeline.config.confidence_threshold;
+        Transcription {
+            text: transcript.text.clone(),
+            segments: transcript
+                .segments
+                .iter()
+                .map(|segment| Segment {
+                    start: segment.start,
+                    end: segment.end,
+                    text: segment.text.clone(),
+                    confidence: segment.confidence,
+                    speaker: segment.speaker.clone(),
+                    low_confidence: segment.confidence.is_some_and(|c| c < threshold),
+                })
+                .collect(),
+            meta: self.server.pipeline.meta.clone(),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Rec for Service {
+    async fn start(&self, request: Request<StartRequest>) -> Result<Response<StartReply>, Status> {
+        self.authorize(&request, Scope::Record)?;
+        let mut recording = self.recording.lock().await;
+        if recording.is_some() {
+            return Err(Status::failed_precondition("Already recording"));
+        }
+
+        let lock = instance::acquire(instance::Busy::Refuse, Duration::ZERO)
+            .map_err(|e| Status::failed_precondition(e.to_string()))?
+            .ok_or_else(|| Status::failed_precondition("Another rec is already recording"))?;
+        let device = Some(request.into_inner().device)
+            .filter(|device| !device.is_empty())
+            .or_else(|| self.server.device.clone());
+        let buffer_ms = self.server.pipeline.config.capture_buffer_ms;
+        let limits = self.server.pipeline.config.limits.clone();
+
+        let (started_tx, started) = oneshot::channel();
+        let (stop, stop_rx) = std::sync::mpsc::channel();
+        let (captured_tx, captured) = oneshot::channel();
+        std::thread::spawn(move || {
+            let recorder = match audio::Recorder::start(device.as_deref(), buffer_ms) {
+                Ok(recorder) => recorder,
+                Err(e) => {
+                    let _ = started_tx.send(Err(e.to_string()));
+                    return;
+                }
+            };
+            let _ = started_tx.send(Ok(()));
+            let request = loop {
+                match stop_rx.recv_timeout(crate::LIMITS_INTERVAL) {
+                    Ok(request) => break request,
+                    Err(RecvTimeoutError::Timeout) => {
+                        let buffered = recorder.buffered_bytes();
+                        if let Some(reason) = limits::capture_exceeded(&limits, buffered) {
+                            eprintln!("Warning: {}", reason);
+                            break instance::Request::Stop;
+                        }
+                    }
+                    // The recording was dropped
+                    Err(RecvTimeoutError::Disconnected) => break instance::Request::Cancel,
+                }
+            };
+            let (rate, channels) = (recorder.sample_rate, recorder.channels);
+            let samples = recorder.stop();
+            let captured =
+                (request == instance::Request::Stop).then_some((samples, rate, channels));
+            let _ = captured_tx.send(captured);
+        });
+
+        match started.await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(Status::unavailable(e)),
+            Err(_) => return Err(Status::internal("Recording thread failed")),
+        }
+        let requests = stop.clone();
+        let watch = tokio::spawn(async move {
+            let mut lock = lock;
+            let _ = requests.send(lock.requested().await);
+        });
+        *recording = Some(Recording {
+            stop,
+            captured,
+            watch,
+        });
+        Ok(Response::new(StartReply {}))
+    }
+
+    async fn stop(&self, request: Request<StopRequest>) -> Result<Response<Transcription>, Status> {
+        self.authorize(&request, Scope::Record)?;
+        let Some(mut recording) = self.recording.lock().await.take() else {
+            return Err(Status::failed_precondition("Not recording"));
+        };
+        let cancel = request.into_inner().cancel;
+        let request = match cancel {
+            true => instance::Request::Cancel,
+            false => instance::Request::Stop,
+        };
+        // Fails when a limit or another `rec` already stopped the capture
+        let _ = recording.stop.send(request);
+        let captured = (&mut recording.captured)
+            .await
+            .map_err(|_| Status::internal("Recording thread failed"))?;
+        if cancel {
+            return Ok(Response::new(Transcription::default()));
+        }
+        let Some((samples, rate, channels)) = captured else {
+            return Err(Status::cancelled("Recording cancelled by rec cancel"));
+        };
+
+        if samples.is_empty() {
+            return Err(Status::failed_precondition("No audio"));
+        }
+        let wav_data = audio::encode_wav(&samples, rate, channels)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(self.dictate(wav_data).await?))
+    }
+
+    async fn transcribe(
+        &self,
+        request: Request<TranscribeRequest>,
+    ) -> Result<Response<Transcription>, Status> {
+        self.authorize(&request, Scope::Transcribe)?;
+        let audio = request.into_inner().audio;
+        if audio.is_empty() {
+            return Err(Status::invalid_argument("No audio"));
+        }
+        Ok(Response::new(self.dictate(audio).await?))
+    }
+
+    async fn history(
+        &self,
+        request: Request<HistoryRequest>,
+    ) -> Result<Response<HistoryReply>, Status> {
+        self.authorize(&request, Scope::History)?;
+        let n = match request.into_inner().n {
+            0 => 10,
+            n => n as usize,
+        };
+        let history = Config::load_history().map_err(|e| Status::internal(e.to_string()))?;
+        let start = history.len().saturating_sub(n);
+        let entries = history[start..]
+            .iter()
+            .map(|entry| HistoryEntry {
+                timestamp: entry.timestamp.clone(),
+                original: entry.original.clone(),
+                corrected: entry.corrected.clone(),
+                model: entry.model.clone(),
+                session: entry.session.clone(),
+                meta: entry.meta.clone(),
+            })
+            .collect();
+        Ok(Response::new(HistoryReply { entries }))
+    }
+}
@@ -0,0 +1,183 @@
+//! User scripts run at fixed points of the pipeline (`hooks` in config)
+//!
+//! Hooks run through `sh -c` in a scratch directory, with a minimal
+//! environment (no API keys) and a time limit. A failing hook is reported
+//! and skipped, so a broken script never loses a dictation.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+/// Environment variables passed through to hooks
+const KEPT_ENV: &[&str] = &["PATH", "HOME", "USER", "LANG", "LC_ALL", "TMPDIR"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HooksConfig {
+    /// Runs before upload with the WAV path in $REC_AUDIO; may rewrite the file
+    #[serde(default)]
+    pub pre_upload: Option<String>,
+    /// Gets the transcription on stdin; non-empty output replaces it
+    #[serde(default)]
+    pub post_transcribe: Option<String>,
+    /// Gets the corrected text on stdin (with --correct); non-empty output replaces it
+    #[serde(default)]
+    pub post_correct: Option<String>,
+    /// Seconds a hook may run before it is killed
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            pre_upload: None,
+            post_transcribe: None,
+            post_correct: None,
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+/// Run the `pre_upload` hook on a WAV buffer
+pub async fn audio(hooks: &HooksConfig, wav_data: Vec<u8>) -> Vec<u8> {
+    let Some(command) = &hooks.pre_upload else {
+        return wav_data;
+    };
+
+    let dir = match scratch_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("Warning: pre_upload hook skipped: {}", e);
+            return wav_data;
+        }
+    };
+    let path = dir.join("audio.wav");
+
+    let result = async {
+        tokio::fs::write(&path, &wav_data)
+            .await
+            .map_err(|e| e.to_string())?;
+        run(command, "pre_upload", &dir, None, hooks.timeout_secs).await?;
+        tokio::fs::read(&path).await.map_err(|e| e.to_string())
+    }
+    .await;
+    tokio::fs::remove_dir_all(&dir).await.ok();
+
+    match result {
+        Ok(modified) if !modified.is_empty() => modified,
+        Ok(_) => {
+            eprintln!("Warning: pre_upload hook left an empty file, using the original audio");
+            wav_data
+        }
+        Err(e) => {
+            eprintln!("Warning: pre_upload hook failed: {}", e);
+            wav_data
+        }
+    }
+}
+
+/// Run a text hook (`post_transcribe` or `post_correct`) if configured
+pub async fn text(hooks: &HooksConfig, name: &str, text: String) -> String {
+    let command = match name {
+        "post_transcribe" => &hooks.post_transcribe,
+        "post_correct" => &hooks.post_correct,
+        _ => &None,
+    };
+    let Some(command) = command else {
+        return text;
+    };
+
+    let result = match scratch_dir() {
+        Ok(dir) => {
+            let output = run(command, name, &dir, Some(&text), hooks.timeout_secs).await;
+            tokio::fs::remove_dir_all(&dir).await.ok();
+            output
+        }
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(output) => {
+            let output = String::from_utf8_lossy(&output);
+            let output = output.trim_end_matches(['\n', '\r']);
+            if output.trim().is_empty() {
+                text
+            } else {
+                output.to_string()
+            }
+        }
+        Err(e) => {
+            eprintln!("Warning: {} hook failed: {}", name, e);
+            text
+        }
+    }
+}
+
+/// Fresh directory a hook runs in
+fn scratch_dir() -> Result<std::path::PathBuf, String> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "rec-hook-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+/// Run a hook command and return its stdout
+async fn run(
+    command: &str,
+    name: &str,
+    dir: &Path,
+    stdin: Option<&str>,
+    timeout_secs: u64,
+) -> Result<Vec<u8>, String> {
+    let mut cmd = tokio::process::Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .current_dir(dir)
+        .env_clear()
+        .envs(
+            KEPT_ENV
+                .iter()
+                .filter_map(|var| Some((var, std::env::var_os(var)?))),
+        )
+        .env("REC_HOOK", name)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true);
+    match stdin {
+        Some(_) => cmd.stdin(Stdio::piped()),
+        None => cmd
+            .env("REC_AUDIO", dir.join("audio.wav"))
+            .stdin(Stdio::null()),
+    };
+
+    let mut child = cmd.spawn().map_err(|e| e.to_string())?;
+
+    // Write stdin while the hook runs, so large texts can't fill both pipes
+    if let (Some(mut pipe), Some(text)) = (child.stdin.take(), stdin) {
+        let text = text.to_string();
+        tokio::spawn(async move {
+            let _ = pipe.write_all(text.as_bytes()).await;
+        });
+    }
+
+    let output = tokio::time::timeout(Duration::from_secs(timeout_secs), child.wait_with_output())
+        .await
+        .map_err(|_| format!("timed out after {}s", timeout_secs))?
+        .map_err(|e| e.to_string())?;
+
+    if !output.status.success() {
+        return Err(format!("exited with {}", output.status));
+    }
+    Ok(output.stdout)
+}
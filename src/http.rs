@@ -0,0 +1,403 @@
+//! Shared HTTP plumbing for backends and correction
+
+use http_body_util::BodyExt;
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// How requests to a service are sent, e.g. through a gateway
+///
+/// Set in a `backends` entry for transcription, and in `claude_http` for
+/// correction.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HttpConfig {
+    /// Replaces the scheme, host and port of the service's URLs, and is put
+    /// before their path, e.g. 'https://gateway.internal/openai'
+    ///
+    /// Ignored for AWS requests, whose signature covers the host and path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// Headers added to every request, e.g. {"X-Gateway-Team": "docs"}
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Seconds to wait for the connection to open
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_secs: Option<f32>,
+    /// Seconds to wait for each read of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_timeout_secs: Option<f32>,
+}
+
+/// Settings by label ('openai', 'claude'...), set once at startup
+static SETTINGS: OnceLock<BTreeMap<String, HttpConfig>> = OnceLock::new();
+
+/// Directory where exchanges are recorded with --debug-http
+static DEBUG_DIR: OnceLock<PathBuf> = OnceLock::new();
+static DEBUG_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+/// Headers whose values are never written to debug files
+const SECRET_HEADERS: &[&str] = &[
+    "authorization",
+    "x-api-key",
+    "xi-api-key",
+    "api-key",
+    "ocp-apim-subscription-key",
+    "x-amz-security-token",
+];
+
+/// Query parameters whose values are never written to debug files
+const SECRET_PARAMS: &[&str] = &["key", "X-Amz-Signature", "X-Amz-Security-Token"];
+
+pub struct Response {
+    pub status: StatusCode,
+    pub body: String,
+    /// How long the service asks to wait before trying again (`Retry-After`)
+    pub retry_after: Option<Duration>,
+}
+
+impl Response {
+    /// Error for this response, e.g. `resp.error(format!("Mistral API error: {}", resp.body))`
+    pub fn error(&self, message: String) -> Box<dyn std::error::Error> {
+        Box::new(ApiError {
+            status: self.status,
+            retry_after: self.retry_after,
+            message,
+        })
+    }
+}
+
+/// Error answer from an API, keeping its status to tell passing failures
+/// from ones that would happen again
+pub struct ApiError {
+    pub status: StatusCode,
+    pub retry_after: Option<Duration>,
+    message: String,
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+// Shown like the plain message errors are when main returns one
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Whether an error is worth sending elsewhere: a rate limit, a server
+/// error, or a request that never got an answer
+///
+/// Errors about the request itself (a bad key, unsupported audio) would
+/// just happen again.
+pub fn is_transient(error: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(e) = error.downcast_ref::<ApiError>() {
+        return e.status == StatusCode::TOO_MANY_REQUESTS || e.status.is_server_error();
+    }
+    if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+        return e.is_timeout() || e.is_connect() || e.is_request();
+    }
+    false
+}
+
+/// Wait the service asked for with the error, if any
+pub fn retry_after(error: &(dyn std::error::Error + 'static)) -> Option<Duration> {
+    error.downcast_ref::<ApiError>()?.retry_after
+}
+
+/// Record every HTTP exchange of this run into `dir`
+pub fn enable_debug(dir: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(&dir)?;
+    DEBUG_DIR.set(dir).ok();
+    Ok(())
+}
+
+/// Send requests labelled like a key of `settings` as it says
+pub fn configure(settings: BTreeMap<String, HttpConfig>) {
+    SETTINGS.set(settings).ok();
+}
+
+/// Whether a request carries an AWS SigV4 signature, in its `Authorization`
+/// header or presigned in its URL
+fn is_signed(req: &reqwest::Request) -> bool {
+    let header = req
+        .headers()
+        .get(reqwest::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    header.is_some_and(|value| value.starts_with("AWS4-HMAC-SHA256"))
+        || req
+            .url()
+            .query_pairs()
+            .any(|(name, _)| name == "X-Amz-Signature")
+}
+
+/// Build a request, applying the settings of its label
+fn prepare(
+    label: &str,
+    req: reqwest::RequestBuilder,
+) -> Result<(reqwest::Client, reqwest::Request), Box<dyn std::error::Error>> {
+    let (client, req) = req.build_split();
+    let mut req = req?;
+    let Some(settings) = SETTINGS.get().and_then(|settings| settings.get(label)) else {
+        return Ok((client, req));
+    };
+
+    // A SigV4 signature covers the host and path, so signed requests keep their URL
+    if let Some(base) = settings.base_url.as_ref().filter(|_| !is_signed(&req)) {
+        let base = reqwest::Url::parse(base)
+            .map_err(|e| format!("Invalid base_url for {}: {}", label, e))?;
+        let mut url = base.clone();
+        url.set_path(&format!(
+            "{}{}",
+            base.path().trim_end_matches('/'),
+            req.url().path()
+        ));
+        url.set_query(req.url().query());
+        *req.url_mut() = url;
+    }
+    for (name, value) in &settings.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| format!("Invalid header name {} for {}: {}", name, label, e))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| format!("Invalid value of header {} for {}: {}", name, label, e))?;
+        req.headers_mut().insert(name, value);
+    }
+
+    // Timeouts belong to the client, so a client with them replaces the caller's
+    if settings.connect_timeout_secs.is_none() && settings.read_timeout_secs.is_none() {
+        return Ok((client, req));
+    }
+    let mut builder = reqwest::Client::builder();
+    if let Some(secs) = settings.connect_timeout_secs {
+        builder = builder.connect_timeout(Duration::from_secs_f32(secs));
+    }
+    if let Some(secs) = settings.read_timeout_secs {
+        builder = builder.read_timeout(Duration::from_secs_f32(secs));
+    }
+    Ok((builder.build()?, req))
+}
+
+/// Send a request and read the whole response body
+///
+/// `label` names the service in debug files (e.g. 'mistral') and picks its
+/// settings.
+pub async fn send(
+    label: &str,
+    req: reqwest::RequestBuilder,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let (client, req) = prepare(label, req)?;
+    execute(label, client, req).await
+}
+
+/// Send a request signed with `secret`, for gateways checking signatures
+///
+/// The body is read whole to be hashed, then `X-Rec-Timestamp` (Unix
+/// seconds), `X-Rec-Content-SHA256` (hex SHA-256 of the body) and
+/// `X-Rec-Signature` (hex HMAC-SHA256 of "<timestamp>\n<body hash>" with
+/// `secret`) are added. Signing comes last, after the settings of `label`
+/// such as `base_url`.
+pub async fn send_signed(
+    label: &str,
+    req: reqwest::RequestBuilder,
+    secret: &str,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let (client, mut req) = prepare(label, req)?;
+
+    let body = match req.body_mut().take() {
+        Some(body) => body.collect().await?.to_bytes(),
+        None => Default::default(),
+    };
+    let timestamp = chrono::Utc::now().timestamp().to_string();
+    let body_hash = hex(&Sha256::digest(&body));
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let signature =
+        hex(hmac::sign(&key, format!("{}\n{}", timestamp, body_hash).as_bytes()).as_ref());
+
+    let headers = req.headers_mut();
+    headers.insert("x-rec-timestamp", HeaderValue::from_str(&timestamp)?);
+    headers.insert("x-rec-content-sha256", HeaderValue::from_str(&body_hash)?);
+    headers.insert("x-rec-signature", HeaderValue::from_str(&signature)?);
+    *req.body_mut() = Some(body.into());
+
+    execute(label, client, req).await
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+async fn execute(
+    label: &str,
+    client: reqwest::Client,
+    req: reqwest::Request,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let request_info = DEBUG_DIR.get().map(|_| describe_request(label, &req));
+
+    let resp = client.execute(req).await?;
+    let status = resp.status();
+    let headers = resp.headers().clone();
+    let body = resp.text().await?;
+
+    record(label, request_info, status, &headers, &body)?;
+    Ok(Response {
+        status,
+        body,
+        retry_after: parse_retry_after(&headers),
+    })
+}
+
+/// Send a request and hand over the response body as it arrives
+///
+/// `on_chunk` gets each piece of the body and returns whether to keep
+/// reading. The body read until then is returned, and recorded with
+/// --debug-http, either way.
+pub async fn send_streaming(
+    label: &str,
+    req: reqwest::RequestBuilder,
+    mut on_chunk: impl FnMut(&[u8]) -> bool,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let (client, req) = prepare(label, req)?;
+
+    let request_info = DEBUG_DIR.get().map(|_| describe_request(label, &req));
+
+    let mut resp = client.execute(req).await?;
+    let status = resp.status();
+    let headers = resp.headers().clone();
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if !on_chunk(&chunk) {
+            break;
+        }
+    }
+    let body = String::from_utf8_lossy(&body).into_owned();
+
+    record(label, request_info, status, &headers, &body)?;
+    Ok(Response {
+        status,
+        body,
+        retry_after: parse_retry_after(&headers),
+    })
+}
+
+/// `Retry-After`, in seconds or as a date
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (at.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+}
+
+/// Write an exchange to the --debug-http directory, if enabled
+fn record(
+    label: &str,
+    request_info: Option<serde_json::Value>,
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (Some(dir), Some(request_info)) = (DEBUG_DIR.get(), request_info) else {
+        return Ok(());
+    };
+    let seq = DEBUG_SEQ.fetch_add(1, Ordering::Relaxed) + 1;
+    let record = serde_json::json!({
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "request": request_info,
+        "response": {
+            "status": status.as_u16(),
+            "headers": headers_json(label, headers),
+            "body": body,
+        },
+    });
+    let path = dir.join(format!("{:03}-{}.json", seq, label));
+    if let Err(e) = std::fs::write(&path, serde_json::to_string_pretty(&record)?) {
+        eprintln!("Warning: Failed to write {}: {}", path.display(), e);
+    }
+    Ok(())
+}
+
+fn describe_request(label: &str, req: &reqwest::Request) -> serde_json::Value {
+    // Multipart bodies hold the audio, and are streamed unless signed; only
+    // JSON bodies are kept
+    let multipart = req
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .is_some_and(|t| t.as_bytes().starts_with(b"multipart/"));
+    let body = req
+        .body()
+        .and_then(|b| b.as_bytes())
+        .filter(|_| !multipart)
+        .map(|b| String::from_utf8_lossy(b).to_string());
+
+    serde_json::json!({
+        "method": req.method().as_str(),
+        "url": redact_url(req.url()),
+        "headers": headers_json(label, req.headers()),
+        "body": body,
+    })
+}
+
+/// Headers for debug files, secret ones and configured ones (often gateway
+/// tokens) redacted
+fn headers_json(label: &str, headers: &HeaderMap) -> serde_json::Value {
+    let configured = SETTINGS
+        .get()
+        .and_then(|settings| settings.get(label))
+        .map(|settings| &settings.headers);
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let secret = SECRET_HEADERS.contains(&name.as_str())
+                || configured.is_some_and(|headers| {
+                    headers
+                        .keys()
+                        .any(|key| key.eq_ignore_ascii_case(name.as_str()))
+                });
+            let value = if secret {
+                "[redacted]".to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).to_string()
+            };
+            (name.to_string(), serde_json::Value::String(value))
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+fn redact_url(url: &reqwest::Url) -> String {
+    let mut url = url.clone();
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| {
+            let v = if SECRET_PARAMS.contains(&k.as_ref()) {
+                "[redacted]".into()
+            } else {
+                v
+            };
+            (k.to_string(), v.to_string())
+        })
+        .collect();
+    if !pairs.is_empty() {
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.to_string()
+}
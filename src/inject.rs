@@ -0,0 +1,76 @@
+//! Typing the transcript into the terminal or focused window
+
+use std::process::Command;
+
+/// Insert text at the cursor of the controlling terminal
+///
+/// Uses TIOCSTI when the kernel allows it (many recent Linux kernels disable
+/// it), then falls back to wtype on Wayland or xdotool on X11. The text is
+/// put on one line first, so it waits at the prompt rather than running.
+pub fn inject(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let text = &one_line(text);
+    #[cfg(unix)]
+    {
+        match tiocsti(text) {
+            Ok(()) => return Ok(()),
+            Err(e)
+                if std::env::var_os("WAYLAND_DISPLAY").is_none()
+                    && std::env::var_os("DISPLAY").is_none() =>
+            {
+                return Err(format!("Could not inject into terminal: {}", e).into());
+            }
+            Err(_) => {}
+        }
+    }
+
+    let (program, args): (&str, Vec<&str>) = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        ("wtype", vec!["--", text])
+    } else {
+        ("xdotool", vec!["type", "--clearmodifiers", "--", text])
+    };
+
+    let status = Command::new(program).args(&args).status().map_err(|e| {
+        format!(
+            "Could not inject text: TIOCSTI unavailable and {} failed: {}",
+            program, e
+        )
+    })?;
+
+    if !status.success() {
+        return Err(format!("{} exited with {}", program, status).into());
+    }
+
+    Ok(())
+}
+
+/// Text with line breaks and tabs turned into spaces and other control
+/// characters dropped, so none of it acts as a key at a shell prompt
+fn one_line(text: &str) -> String {
+    let text: String = text
+        .chars()
+        .filter_map(|c| match c {
+            '\n' | '\r' | '\t' => Some(' '),
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect();
+    text.trim().to_string()
+}
+
+#[cfg(unix)]
+fn tiocsti(text: &str) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let tty = std::fs::OpenOptions::new().write(true).open("/dev/tty")?;
+    let fd = tty.as_raw_fd();
+
+    for byte in text.as_bytes() {
+        // SAFETY: fd is an open terminal and byte points to a valid char
+        let ret = unsafe { libc::ioctl(fd, libc::TIOCSTI, byte as *const u8) };
+        if ret != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
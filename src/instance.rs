@@ -0,0 +1,193 @@
+//! One microphone recording at a time
+//!
+//! A recording `rec` holds a lock in the runtime directory. Another `rec`
+//! started meanwhile would fight it over the microphone and the clipboard,
+//! so it either refuses with a message or, with `"when_busy": "stop"`, asks
+//! the running one to stop (SIGUSR2) as if Enter was pressed, which makes a
+//! single hotkey start and stop dictation. `rec cancel` asks it to stop
+//! without sending anything, for a second hotkey.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// What a `rec` does when another one is already recording
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Busy {
+    /// Exit with an error naming the running recording
+    #[default]
+    Refuse,
+    /// Stop the running recording, which then finishes as usual
+    Stop,
+}
+
+/// What another `rec` asked the recording to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    /// Stop and transcribe, as if Enter was pressed
+    Stop,
+    /// Stop and discard the audio (`rec cancel`)
+    Cancel,
+}
+
+/// Held for as long as this process records
+pub struct Lock {
+    _file: File,
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Lock {
+    /// Wait until another `rec` asks something of this recording
+    pub async fn requested(&mut self) -> Request {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            signal.recv().await;
+            // The request is left in a file before the signal is sent
+            let path = runtime_dir().map(|dir| dir.join(REQUEST_FILE));
+            let request = path
+                .as_ref()
+                .ok()
+                .and_then(|p| std::fs::read_to_string(p).ok());
+            if let Ok(path) = &path {
+                std::fs::remove_file(path).ok();
+            }
+            return match request.as_deref() {
+                Some("cancel") => Request::Cancel,
+                _ => Request::Stop,
+            };
+        }
+        std::future::pending().await
+    }
+}
+
+/// Lock held while recording, holding the recording pid
+const LOCK_FILE: &str = "recording.lock";
+
+/// Request to the recording, read when it gets SIGUSR2
+const REQUEST_FILE: &str = "recording.request";
+
+fn runtime_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = dirs::runtime_dir()
+        .or_else(dirs::cache_dir)
+        .ok_or("Could not find runtime directory")?
+        .join("rec");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn open_lock() -> Result<File, Box<dyn std::error::Error>> {
+    Ok(File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(runtime_dir()?.join(LOCK_FILE))?)
+}
+
+/// Take the recording lock, or deal with the `rec` holding it
+///
+/// Returns None when the running recording was asked to stop, or when it
+/// started less than `debounce` ago (a double press or key bounce would stop
+/// it right away), in which case this process has nothing left to do.
+pub fn acquire(busy: Busy, debounce: Duration) -> Result<Option<Lock>, Box<dyn std::error::Error>> {
+    let mut file = open_lock()?;
+
+    match file.try_lock() {
+        Ok(()) => {
+            // Listen before anyone can read our pid: SIGUSR2 kills by default
+            #[cfg(unix)]
+            let signal =
+                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined2()).ok();
+            file.set_len(0)?;
+            write!(file, "{}", std::process::id())?;
+            file.flush()?;
+            Ok(Some(Lock {
+                _file: file,
+                #[cfg(unix)]
+                signal,
+            }))
+        }
+        Err(std::fs::TryLockError::WouldBlock) => {
+            let pid = holder(&mut file)?;
+            let name = pid.map(|p| format!(" (pid {})", p)).unwrap_or_default();
+            let age = file.metadata()?.modified()?.elapsed().unwrap_or_default();
+
+            match busy {
+                Busy::Stop if age < debounce => {
+                    eprintln!(
+                        "Ignored: the recording in progress{} started {} ms ago",
+                        name,
+                        age.as_millis()
+                    );
+                    Ok(None)
+                }
+                Busy::Stop => {
+                    send(pid, Request::Stop)?;
+                    eprintln!("Stopped the recording in progress{}", name);
+                    Ok(None)
+                }
+                Busy::Refuse => Err(format!(
+                    "Another rec is already recording{}: stop it first, or set \"when_busy\": \"stop\" in config to have this command stop it",
+                    name
+                )
+                .into()),
+            }
+        }
+        Err(std::fs::TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+/// Discard the recording in progress (`rec cancel`)
+pub fn cancel() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = open_lock()?;
+    match file.try_lock() {
+        Ok(()) => Err("No recording in progress".into()),
+        Err(std::fs::TryLockError::WouldBlock) => {
+            let pid = holder(&mut file)?;
+            send(pid, Request::Cancel)?;
+            eprintln!(
+                "Cancelled the recording in progress{}",
+                pid.map(|p| format!(" (pid {})", p)).unwrap_or_default()
+            );
+            Ok(())
+        }
+        Err(std::fs::TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+/// Pid written by the `rec` holding the lock
+fn holder(file: &mut File) -> Result<Option<i32>, Box<dyn std::error::Error>> {
+    let mut pid = String::new();
+    file.rewind()?;
+    file.read_to_string(&mut pid)?;
+    Ok(pid.trim().parse().ok())
+}
+
+/// Leave a request for the recording `rec` and signal it
+fn send(pid: Option<i32>, request: Request) -> Result<(), Box<dyn std::error::Error>> {
+    let pid = pid.ok_or("The recording in progress has not started yet")?;
+    let content = match request {
+        Request::Stop => "stop",
+        Request::Cancel => "cancel",
+    };
+    std::fs::write(runtime_dir()?.join(REQUEST_FILE), content)?;
+    if !signal(pid) {
+        return Err(format!("Could not reach the recording in progress (pid {})", pid).into());
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn signal(pid: i32) -> bool {
+    // SAFETY: kill has no memory safety requirements
+    unsafe { libc::kill(pid, libc::SIGUSR2) == 0 }
+}
+
+#[cfg(not(unix))]
+fn signal(_pid: i32) -> bool {
+    false
+}
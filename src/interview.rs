@@ -0,0 +1,119 @@
+//! Two microphones, one per person (`rec interview`)
+//!
+//! Each device is recorded to its own track and transcribed on its own, so
+//! who said what comes from which microphone picked it up rather than from
+//! diarization. The two transcripts are then interleaved by segment start
+//! time, as speakers A and B.
+
+use crate::backend::{Segment, Transcript};
+use crate::{Pipeline, audio, events, instance, marks, status, status_up};
+
+/// Speaker label of each track
+const LABELS: [&str; 2] = ["A", "B"];
+
+/// Record both devices until Enter, then transcribe each track
+///
+/// Returns the merged transcript with a speaker per segment, or `None` when
+/// the recording was cancelled.
+pub async fn record(
+    pipeline: &Pipeline,
+    devices: [&str; 2],
+    lock: &mut instance::Lock,
+) -> Result<Option<Transcript>, Box<dyn std::error::Error>> {
+    status("Loading...");
+    let buffer_ms = pipeline.config.capture_buffer_ms;
+    let recorders = [
+        audio::Recorder::start(Some(devices[0]), buffer_ms)?,
+        audio::Recorder::start(Some(devices[1]), buffer_ms)?,
+    ];
+    for device in devices {
+        crate::warn_if_muted(Some(device));
+    }
+
+    status("Recording...");
+    crate::recording_started(pipeline, &recorders[0]);
+    crate::show_input(pipeline, &recorders[1]);
+    let mut recording_marks = marks::Marks::listen();
+    // Both tracks grow at about the same pace, so the first one stands for both
+    let request = crate::wait_for_stop(
+        &recorders[0],
+        &mut recording_marks,
+        lock,
+        &pipeline.config.limits,
+        None,
+        crate::enter_pressed(),
+    )
+    .await?;
+    if request == instance::Request::Cancel {
+        status_up("Cancelled\n");
+        return Ok(None);
+    }
+
+    let mut tracks = Vec::new();
+    for (recorder, device) in recorders.into_iter().zip(devices) {
+        let sample_rate = recorder.sample_rate;
+        let channels = recorder.channels;
+        let samples = recorder.stop();
+        if audio::is_digital_silence(&samples) {
+            eprintln!("Warning: Nothing recorded from {}", device);
+        }
+        let samples = pipeline.clean(samples, sample_rate, channels);
+        let duration = samples.len() as f32 / sample_rate as f32 / channels as f32;
+        // Silence is not trimmed: it would shift the track's times
+        tracks.push((
+            audio::encode_wav(&samples, sample_rate, channels)?,
+            duration,
+        ));
+    }
+    let duration = tracks[0].1.max(tracks[1].1);
+    events::emit(
+        "recording_stopped",
+        serde_json::json!({ "duration": duration }),
+    );
+    status_up(&format!("{:.1}s transcribing...", duration));
+
+    let [(wav_a, secs_a), (wav_b, secs_b)]: [(Vec<u8>, f32); 2] =
+        tracks.try_into().map_err(|_| "Expected two tracks")?;
+    // A local model already takes the whole CPU (and one copy in memory per
+    // run), so the tracks go one after the other there
+    let (a, b) = match pipeline.backend.is_local() {
+        true => (
+            pipeline.transcribe(wav_a).await?,
+            pipeline.transcribe(wav_b).await?,
+        ),
+        false => tokio::try_join!(pipeline.transcribe(wav_a), pipeline.transcribe(wav_b))?,
+    };
+    Ok(Some(merge([(a, secs_a), (b, secs_b)])))
+}
+
+/// Segments of both tracks in order of start time, labelled by track
+///
+/// A track transcribed without segments counts as one segment spanning it.
+fn merge(tracks: [(Transcript, f32); 2]) -> Transcript {
+    let mut segments: Vec<Segment> = Vec::new();
+    for ((transcript, duration), label) in tracks.into_iter().zip(LABELS) {
+        let track = match transcript.segments.is_empty() {
+            true if transcript.text.trim().is_empty() => vec![],
+            true => vec![Segment {
+                start: 0.0,
+                end: duration,
+                text: transcript.text.trim().to_string(),
+                confidence: None,
+                speaker: None,
+            }],
+            false => transcript.segments,
+        };
+        segments.extend(track.into_iter().map(|segment| Segment {
+            speaker: Some(label.to_string()),
+            ..segment
+        }));
+    }
+    segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    let text = segments
+        .iter()
+        .map(|s| s.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Transcript { text, segments }
+}
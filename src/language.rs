@@ -0,0 +1,270 @@
+//! Languages as users name them, and as each backend expects them
+//!
+//! `--language`, `--to` and profiles take a code ('fr'), a locale ('fr-FR',
+//! 'fr_FR') or a name ('french', 'Français'). They are checked before
+//! recording, so that a typo is caught with a suggestion rather than coming
+//! back as a provider's 400, and turned into a code with the region when one
+//! was given. Whisper-style APIs get the bare code, Azure, Google and AWS a
+//! locale.
+
+use crate::text::levenshtein;
+
+/// Languages Whisper knows, which the other backends' lists mostly cover:
+/// ISO 639-1 code (Cantonese has none) and English name
+const LANGUAGES: &[(&str, &str)] = &[
+    ("af", "afrikaans"),
+    ("am", "amharic"),
+    ("ar", "arabic"),
+    ("as", "assamese"),
+    ("az", "azerbaijani"),
+    ("ba", "bashkir"),
+    ("be", "belarusian"),
+    ("bg", "bulgarian"),
+    ("bn", "bengali"),
+    ("bo", "tibetan"),
+    ("br", "breton"),
+    ("bs", "bosnian"),
+    ("ca", "catalan"),
+    ("cs", "czech"),
+    ("cy", "welsh"),
+    ("da", "danish"),
+    ("de", "german"),
+    ("el", "greek"),
+    ("en", "english"),
+    ("es", "spanish"),
+    ("et", "estonian"),
+    ("eu", "basque"),
+    ("fa", "persian"),
+    ("fi", "finnish"),
+    ("fo", "faroese"),
+    ("fr", "french"),
+    ("gl", "galician"),
+    ("gu", "gujarati"),
+    ("ha", "hausa"),
+    ("haw", "hawaiian"),
+    ("he", "hebrew"),
+    ("hi", "hindi"),
+    ("hr", "croatian"),
+    ("ht", "haitian creole"),
+    ("hu", "hungarian"),
+    ("hy", "armenian"),
+    ("id", "indonesian"),
+    ("is", "icelandic"),
+    ("it", "italian"),
+    ("ja", "japanese"),
+    ("jv", "javanese"),
+    ("ka", "georgian"),
+    ("kk", "kazakh"),
+    ("km", "khmer"),
+    ("kn", "kannada"),
+    ("ko", "korean"),
+    ("la", "latin"),
+    ("lb", "luxembourgish"),
+    ("ln", "lingala"),
+    ("lo", "lao"),
+    ("lt", "lithuanian"),
+    ("lv", "latvian"),
+    ("mg", "malagasy"),
+    ("mi", "maori"),
+    ("mk", "macedonian"),
+    ("ml", "malayalam"),
+    ("mn", "mongolian"),
+    ("mr", "marathi"),
+    ("ms", "malay"),
+    ("mt", "maltese"),
+    ("my", "burmese"),
+    ("ne", "nepali"),
+    ("nl", "dutch"),
+    ("nn", "nynorsk"),
+    ("no", "norwegian"),
+    ("oc", "occitan"),
+    ("pa", "punjabi"),
+    ("pl", "polish"),
+    ("ps", "pashto"),
+    ("pt", "portuguese"),
+    ("ro", "romanian"),
+    ("ru", "russian"),
+    ("sa", "sanskrit"),
+    ("sd", "sindhi"),
+    ("si", "sinhala"),
+    ("sk", "slovak"),
+    ("sl", "slovenian"),
+    ("sn", "shona"),
+    ("so", "somali"),
+    ("sq", "albanian"),
+    ("sr", "serbian"),
+    ("su", "sundanese"),
+    ("sv", "swedish"),
+    ("sw", "swahili"),
+    ("ta", "tamil"),
+    ("te", "telugu"),
+    ("tg", "tajik"),
+    ("th", "thai"),
+    ("tk", "turkmen"),
+    ("tl", "tagalog"),
+    ("tr", "turkish"),
+    ("tt", "tatar"),
+    ("uk", "ukrainian"),
+    ("ur", "urdu"),
+    ("uz", "uzbek"),
+    ("vi", "vietnamese"),
+    ("yi", "yiddish"),
+    ("yo", "yoruba"),
+    ("yue", "cantonese"),
+    ("zh", "chinese"),
+];
+
+/// Other names: native ones of the most dictated languages, and synonyms
+const ALIASES: &[(&str, &str)] = &[
+    ("français", "fr"),
+    ("francais", "fr"),
+    ("deutsch", "de"),
+    ("español", "es"),
+    ("espanol", "es"),
+    ("castilian", "es"),
+    ("italiano", "it"),
+    ("português", "pt"),
+    ("portugues", "pt"),
+    ("nederlands", "nl"),
+    ("flemish", "nl"),
+    ("polski", "pl"),
+    ("svenska", "sv"),
+    ("norsk", "no"),
+    ("bokmål", "no"),
+    ("dansk", "da"),
+    ("suomi", "fi"),
+    ("türkçe", "tr"),
+    ("mandarin", "zh"),
+    ("farsi", "fa"),
+    ("filipino", "tl"),
+    ("myanmar", "my"),
+    ("jw", "jv"),
+];
+
+/// Code of a language as typed, with the region or script when one was
+/// given: 'French' is 'fr', 'fr_fr' is 'fr-FR'
+pub fn parse(input: &str) -> Result<String, String> {
+    let trimmed = input.trim();
+    let (name, subtag) = match trimmed.split_once(['-', '_']) {
+        // Names with a space or dash, such as 'haitian creole', come whole
+        Some((name, subtag)) if find(name).is_some() => (name, Some(subtag)),
+        _ => (trimmed, None),
+    };
+    let Some(code) = find(name) else {
+        return Err(unknown(input));
+    };
+
+    match subtag {
+        None => Ok(code.to_string()),
+        // Region ('FR'), UN M.49 area ('419') or script ('Hant')
+        Some(region) if region.len() == 2 && region.chars().all(|c| c.is_ascii_alphabetic()) => {
+            Ok(format!("{}-{}", code, region.to_uppercase()))
+        }
+        Some(area) if area.len() == 3 && area.chars().all(|c| c.is_ascii_digit()) => {
+            Ok(format!("{}-{}", code, area))
+        }
+        Some(script) if script.len() == 4 && script.chars().all(|c| c.is_ascii_alphabetic()) => {
+            let mut script = script.to_lowercase();
+            script[..1].make_ascii_uppercase();
+            Ok(format!("{}-{}", code, script))
+        }
+        Some(other) => Err(format!(
+            "Invalid region {:?} in language {:?}: expected e.g. 'fr-FR' or 'es-419'",
+            other, input
+        )),
+    }
+}
+
+/// [`parse`] a language that may not be set, in place
+pub fn normalize(language: &mut Option<String>) -> Result<(), String> {
+    if let Some(value) = language {
+        *value = parse(value)?;
+    }
+    Ok(())
+}
+
+/// Bare code, what Whisper-style APIs take: 'fr-FR' is 'fr'
+pub fn code(language: &str) -> &str {
+    language.split(['-', '_']).next().unwrap_or(language)
+}
+
+/// Locale Azure, Google and AWS expect for a language: 'fr' becomes 'fr-FR'
+pub fn locale(language: Option<&str>) -> String {
+    let Some(language) = language else {
+        return "en-US".to_string();
+    };
+    if language.contains('-') {
+        return language.to_string();
+    }
+    // Where the language's own code isn't its most spoken region
+    let region = match language {
+        "en" => "US",
+        "pt" => "BR",
+        "zh" => "CN",
+        "ja" => "JP",
+        "ko" => "KR",
+        "sv" => "SE",
+        "da" => "DK",
+        "uk" => "UA",
+        "cs" => "CZ",
+        "el" => "GR",
+        "he" => "IL",
+        "hi" => "IN",
+        "vi" => "VN",
+        "ar" => "SA",
+        other => return format!("{}-{}", other, other.to_uppercase()),
+    };
+    format!("{}-{}", language, region)
+}
+
+/// Code of a language code or name, in any case
+fn find(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(code, english)| *code == name || *english == name)
+        .map(|(code, _)| *code)
+        .or_else(|| {
+            ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == name)
+                .map(|(_, code)| *code)
+        })
+}
+
+/// Error for an unknown language, suggesting the closest known one
+fn unknown(input: &str) -> String {
+    let typed: Vec<char> = input.trim().to_lowercase().chars().collect();
+    let closest = LANGUAGES
+        .iter()
+        .flat_map(|&(code, english)| [(code, english, code), (code, english, english)])
+        .chain(
+            ALIASES
+                .iter()
+                .filter_map(|&(alias, code)| Some((code, english_name(code)?, alias))),
+        )
+        .map(|(code, english, candidate)| {
+            let candidate: Vec<char> = candidate.chars().collect();
+            (levenshtein(&typed, &candidate), code, english)
+        })
+        .min_by_key(|(distance, ..)| *distance);
+
+    match closest {
+        // A third of the letters wrong is still a typo; more is another word
+        Some((distance, code, english)) if distance <= (typed.len() / 3).max(1) => format!(
+            "Unknown language {:?}: did you mean {} ('{}')?",
+            input, english, code
+        ),
+        _ => format!(
+            "Unknown language {:?}: use a code such as 'en' or 'fr-FR', or a name such as 'french'",
+            input
+        ),
+    }
+}
+
+fn english_name(code: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(known, _)| *known == code)
+        .map(|(_, english)| *english)
+}
@@ -0,0 +1,116 @@
+//! Safeguards for long recordings (`limits` in config)
+//!
+//! Captured audio is held in memory until the recording stops, and kept
+//! audio is written to disk. Rather than let a forgotten recording run the
+//! machine out of memory or fill the disk, capture stops (and transcribes
+//! what it has) and audio is not written, each with a message saying why.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LimitsConfig {
+    /// Captured audio held in memory before the recording is stopped, in MB
+    #[serde(default = "default_max_recording_mb")]
+    pub max_recording_mb: u64,
+    /// Available memory below which the recording is stopped, in MB
+    #[serde(default = "default_min_free_memory_mb")]
+    pub min_free_memory_mb: u64,
+    /// Free disk space that writing audio must leave, in MB
+    #[serde(default = "default_min_free_disk_mb")]
+    pub min_free_disk_mb: u64,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_recording_mb: default_max_recording_mb(),
+            min_free_memory_mb: default_min_free_memory_mb(),
+            min_free_disk_mb: default_min_free_disk_mb(),
+        }
+    }
+}
+
+/// About an hour and a half of 48 kHz stereo
+fn default_max_recording_mb() -> u64 {
+    2048
+}
+
+fn default_min_free_memory_mb() -> u64 {
+    256
+}
+
+fn default_min_free_disk_mb() -> u64 {
+    500
+}
+
+const MB: u64 = 1 << 20;
+
+/// Why a recording holding `buffered` bytes of audio must stop now, if it must
+pub fn capture_exceeded(limits: &LimitsConfig, buffered: usize) -> Option<String> {
+    if limits.max_recording_mb > 0 && buffered as u64 >= limits.max_recording_mb * MB {
+        return Some(format!(
+            "Recording stopped: reached max_recording_mb ({} MB of audio)",
+            limits.max_recording_mb
+        ));
+    }
+
+    if let Some(available) = available_memory()
+        && available < limits.min_free_memory_mb * MB
+    {
+        return Some(format!(
+            "Recording stopped: only {} MB of memory left (min_free_memory_mb is {})",
+            available / MB,
+            limits.min_free_memory_mb
+        ));
+    }
+    None
+}
+
+/// Check that writing `len` bytes in `dir` leaves `min_free_disk_mb` free
+pub fn check_disk(
+    limits: &LimitsConfig,
+    dir: &Path,
+    len: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(free) = free_disk(dir) else {
+        return Ok(());
+    };
+    if free < len as u64 + limits.min_free_disk_mb * MB {
+        return Err(format!(
+            "only {} MB free in {} (min_free_disk_mb is {})",
+            free / MB,
+            dir.display(),
+            limits.min_free_disk_mb
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Memory available to new allocations without swapping, where known
+fn available_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Space available to unprivileged users on the filesystem holding `dir`
+#[cfg(unix)]
+fn free_disk(dir: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: path is NUL-terminated and stat is a valid statvfs to fill
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_disk(_dir: &Path) -> Option<u64> {
+    None
+}
@@ -0,0 +1,95 @@
+//! Live transcript of the server's session (`GET /live`)
+//!
+//! Every dictation the server finishes is pushed to the browsers watching
+//! the page over server-sent events, so a meeting can be followed on a
+//! second screen or by a colleague on the LAN. A browser opening the page
+//! late first gets what was said so far: the session's earlier dictations
+//! with `--session`, or those of this run otherwise.
+
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures_util::Stream;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+/// Dictations a slow browser may fall behind by before missing some
+const BACKLOG: usize = 64;
+
+/// One dictation of the transcript
+#[derive(Clone, Serialize)]
+pub struct Line {
+    /// RFC 3339, as in history
+    pub timestamp: String,
+    pub text: String,
+}
+
+/// Transcript so far, and the browsers following it
+pub struct Feed {
+    lines: Mutex<Vec<Line>>,
+    sender: broadcast::Sender<Line>,
+}
+
+impl Feed {
+    /// Start from the dictations already in `session`, if any
+    pub fn new(session: Option<&str>) -> Self {
+        let lines = match session.map(crate::session::entries) {
+            Some(Ok(entries)) => entries
+                .into_iter()
+                .map(|entry| Line {
+                    timestamp: entry.timestamp,
+                    text: entry.corrected.trim().to_string(),
+                })
+                .collect(),
+            Some(Err(e)) => {
+                crate::systemd::warning(format!("Failed to read the session: {}", e));
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+        Self {
+            lines: Mutex::new(lines),
+            sender: broadcast::channel(BACKLOG).0,
+        }
+    }
+
+    /// Add a finished dictation and send it to every open page
+    pub fn push(&self, text: &str) {
+        let line = Line {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            text: text.trim().to_string(),
+        };
+        self.lines.lock().unwrap().push(line.clone());
+        // No receivers is not an error: nobody is watching
+        let _ = self.sender.send(line);
+    }
+
+    /// Event stream of the transcript so far, then of each new dictation
+    pub fn events(&self) -> Sse<impl Stream<Item = Result<Event, Infallible>> + use<>> {
+        // Subscribe under the lock so no dictation falls between the two
+        let lines = self.lines.lock().unwrap();
+        let receiver = self.sender.subscribe();
+        let past: Vec<Line> = lines.clone();
+        drop(lines);
+
+        let past = futures_util::stream::iter(past.into_iter().map(event));
+        let new = futures_util::stream::unfold(receiver, |mut receiver| async move {
+            let next = match receiver.recv().await {
+                Ok(line) => event(line),
+                // The page reloads to catch up on what it missed
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    Ok(Event::default().event("reload").data(""))
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            };
+            Some((next, receiver))
+        });
+        Sse::new(futures_util::StreamExt::chain(past, new)).keep_alive(KeepAlive::default())
+    }
+}
+
+fn event(line: Line) -> Result<Event, Infallible> {
+    Ok(Event::default()
+        .json_data(&line)
+        .unwrap_or_else(|_| Event::default().data("")))
+}
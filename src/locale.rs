@@ -0,0 +1,88 @@
+//! Spoken language guessed from the system locale or keyboard layout
+
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// Keyboard layouts whose language differs from their code
+const LAYOUT_LANGUAGES: &[(&str, &str)] = &[
+    ("us", "en"),
+    ("gb", "en"),
+    ("latam", "es"),
+    ("br", "pt"),
+    ("ca", "fr"),
+    ("se", "sv"),
+    ("dk", "da"),
+    ("cz", "cs"),
+    ("ua", "uk"),
+    ("gr", "el"),
+    ("jp", "ja"),
+    ("kr", "ko"),
+    ("cn", "zh"),
+];
+
+/// Keyboard layouts that are also language codes
+const LAYOUT_SAME: &[&str] = &[
+    "fr", "de", "es", "it", "pt", "nl", "no", "fi", "pl", "ru", "tr", "hu", "ro",
+];
+
+/// Language of the system locale (e.g. 'fr' for fr_FR.UTF-8)
+pub fn system_language() -> Option<String> {
+    for var in ["LC_ALL", "LC_MESSAGES", "LANG"] {
+        if let Ok(value) = std::env::var(var)
+            && let Some(language) = from_locale(&value)
+        {
+            return Some(language);
+        }
+    }
+
+    if cfg!(target_os = "macos") {
+        let output = Command::new("defaults")
+            .args(["read", "-g", "AppleLocale"])
+            .output()
+            .ok()?;
+        return from_locale(String::from_utf8_lossy(&output.stdout).trim());
+    }
+
+    None
+}
+
+/// Language of the active keyboard layout (X11 only)
+///
+/// Looked up once per process, as `rec serve` asks on every dictation.
+pub fn keyboard_language() -> Option<String> {
+    static LANGUAGE: OnceLock<Option<String>> = OnceLock::new();
+    LANGUAGE.get_or_init(query_keyboard).clone()
+}
+
+/// Language of the keyboard layout `setxkbmap` reports
+fn query_keyboard() -> Option<String> {
+    let output = Command::new("setxkbmap").arg("-query").output().ok()?;
+    let query = String::from_utf8_lossy(&output.stdout);
+    let layout = query
+        .lines()
+        .find_map(|line| line.strip_prefix("layout:"))?
+        .trim()
+        .split(',')
+        .next()?;
+
+    LAYOUT_LANGUAGES
+        .iter()
+        .find(|(code, _)| *code == layout)
+        .map(|(_, language)| language.to_string())
+        .or_else(|| LAYOUT_SAME.contains(&layout).then(|| layout.to_string()))
+}
+
+/// Language code of a locale name like 'fr_FR.UTF-8', 'pt-BR' or 'de'
+fn from_locale(locale: &str) -> Option<String> {
+    let language = locale
+        .split(['.', '@'])
+        .next()?
+        .split(['_', '-'])
+        .next()?
+        .to_lowercase();
+
+    let valid = (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_lowercase())
+        && language != "c";
+    valid.then_some(language)
+}
@@ -1,16 +1,64 @@
 //! rec - Quick speech-to-text for devs
 
+mod accel;
+mod audio;
+mod aws;
 mod backend;
+mod browse;
+mod calendar;
+mod calibrate;
+mod complete;
 mod config;
 mod correction;
+mod denoise;
+mod download;
+mod enhance;
+mod events;
+mod focus;
+mod git;
+mod google;
+mod grpc;
+mod hooks;
+mod http;
+mod inject;
+mod instance;
+mod interview;
+mod language;
+mod limits;
+mod live;
+mod locale;
+mod marks;
+mod media;
+mod metrics;
+mod models;
+mod plugins;
+mod porcelain;
+mod progress;
+mod ptt;
+mod retrieval;
+mod scrub;
+mod server;
+mod session;
+mod slots;
+mod speak;
+mod sync;
+mod systemd;
+mod text;
+mod upload;
+mod vad;
+mod vosk;
+mod watchdog;
 
 use arboard::Clipboard;
-use backend::Backend;
+use backend::{Backend, Segment, Transcript};
 use clap::{Parser, Subcommand};
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use hound::{WavSpec, WavWriter};
-use std::io::{self, BufWriter, Write};
-use std::sync::{Arc, Mutex};
+use plugins::{PluginStage, Plugins};
+use progress::{stage, status, status_up};
+use std::future::Future;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::time::Duration;
 
 const MODEL_V1: &str = "voxtral-mini-2507";
 const MODEL_V2: &str = "voxtral-mini-2602";
@@ -23,29 +71,174 @@ struct Args {
 
     /// Audio file to transcribe (instead of recording)
     #[arg(short, long, global = true)]
-    file: Option<std::path::PathBuf>,
+    file: Option<PathBuf>,
 
     /// Copy result to clipboard
     #[arg(short, long, global = true)]
     clip: bool,
 
+    /// Append result to the current clipboard contents
+    #[arg(long, global = true)]
+    clip_append: bool,
+
+    /// Split the result on this separator or the spoken word "separator", copying one part at a time ('rec next' copies the next)
+    #[arg(
+        long,
+        value_name = "SEPARATOR",
+        allow_hyphen_values = true,
+        global = true
+    )]
+    split_on: Option<String>,
+
+    /// Type result at the shell prompt (TIOCSTI, or wtype/xdotool fallback)
+    #[arg(long, global = true)]
+    inject_tty: bool,
+
+    /// Append result to a file
+    #[arg(short, long, global = true)]
+    output: Option<PathBuf>,
+
+    /// Input device: a device name, 'pulse:<source>' or 'pipewire:<target>'
+    #[arg(long, global = true)]
+    device: Option<String>,
+
+    /// Audio host devices are opened through, e.g. 'alsa' or 'jack' (see `rec devices`)
+    #[arg(long, global = true)]
+    audio_host: Option<String>,
+
+    /// Say which input and audio host are recorded from
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Remove speaker output from the mic signal (PulseAudio/PipeWire)
+    #[arg(long, global = true)]
+    echo_cancel: bool,
+
+    /// Add timestamped chapter headings to recordings longer than 3 minutes
+    #[arg(long, global = true)]
+    chapters: bool,
+
+    /// Clean up phone-quality audio (band-pass, upsampling to 16 kHz, noise gating)
+    #[arg(long, global = true)]
+    enhance: bool,
+
+    /// Suppress background noise (fans, hum, office chatter) with RNNoise
+    #[arg(long, global = true)]
+    denoise: bool,
+
+    /// Group this dictation with others under a named session, keeping its audio
+    #[arg(long, global = true)]
+    session: Option<String>,
+
+    /// Tag the dictation in history and JSON output (e.g. 'ticket=PROJ-123'), repeatable
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_meta, global = true)]
+    meta: Vec<(String, String)>,
+
+    /// Output a transcript every N seconds while recording (e.g. '30s', '2m')
+    #[arg(long, value_parser = parse_duration, global = true)]
+    segment: Option<Duration>,
+
+    /// Push-to-talk: record only while this key is held (a character, or 'space')
+    #[arg(long, value_name = "KEY", value_parser = ptt::parse_key, global = true, conflicts_with = "segment")]
+    ptt: Option<u8>,
+
     /// Correct transcription using Claude API
     #[arg(long, global = true)]
     correct: bool,
 
+    /// Transcription backend: mistral, openai, elevenlabs, azure, google, aws, rec-api, local, vosk or a name from 'backends' in config
+    #[arg(long, global = true)]
+    backend: Option<String>,
+
+    /// Transcribe locally with whisper.cpp first, then refine with the cloud backend
+    #[arg(long, global = true)]
+    hybrid: bool,
+
+    /// CPU threads for the local backend
+    #[arg(long, global = true)]
+    threads: Option<usize>,
+
+    /// Accelerator for the local backend (auto, cpu, cuda, metal, vulkan)
+    #[arg(long, global = true)]
+    accel: Option<accel::Accelerator>,
+
+    /// Drop silence before transcription (none, energy, adaptive, webrtc, silero)
+    #[arg(long, global = true)]
+    vad: Option<vad::VadEngine>,
+
+    /// Fill a template from config with the dictation (e.g. 'standup')
+    #[arg(short, long, global = true)]
+    template: Option<String>,
+
+    /// Output the uncorrected text if correction takes longer than this (e.g. '4s')
+    #[arg(long, value_parser = parse_duration, global = true)]
+    deadline: Option<Duration>,
+
+    /// Write progress and results to stdout as JSON lines
+    #[arg(long, global = true)]
+    jsonl: bool,
+
+    /// Write stable, prefixed status and result lines to stdout for scripts
+    #[arg(
+        long,
+        global = true,
+        value_name = "VERSION",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "v1",
+        conflicts_with = "jsonl"
+    )]
+    porcelain: Option<porcelain::Version>,
+
+    /// Tell speakers apart (backends that can) and write one "Speaker 1: ..." block per turn
+    #[arg(long, global = true)]
+    diarize: bool,
+
     /// Show Claude's correction comments
     #[arg(long, global = true)]
     debug: bool,
 
+    /// Read the final text aloud (platform speech synthesizer, or 'speak' in config)
+    #[arg(long, global = true)]
+    speak: bool,
+
+    /// Print the correction prompt for the transcription instead of calling Claude
+    #[arg(long, global = true)]
+    show_prompt: bool,
+
+    /// Transcribe with several backends at once and print the results side by side, e.g. mistral,openai
+    #[arg(long, value_name = "BACKENDS", value_delimiter = ',', global = true)]
+    compare: Vec<String>,
+
+    /// Save sanitized HTTP requests and raw responses of this run to a directory
+    #[arg(long, value_name = "DIR", global = true)]
+    debug_http: Option<PathBuf>,
+
     /// Use voxtral-mini-2602 model (v2)
     #[arg(long, global = true)]
     v2: bool,
 
+    /// Transcription model of the backend (e.g. 'whisper-1', 'scribe_v1', 'small.en' for local)
+    #[arg(long, global = true, conflicts_with = "v2")]
+    model: Option<String>,
+
     /// Language code (e.g. 'en', 'fr')
     #[arg(short = 'l', long, alias = "lang", global = true)]
     language: Option<String>,
 
-    /// Send custom words as context_bias to Mistral
+    /// Translate the result into this language (e.g. 'en')
+    #[arg(long, global = true)]
+    to: Option<String>,
+
+    /// Profile from config (speech/output language)
+    #[arg(short, long, global = true)]
+    profile: Option<String>,
+
+    /// Describe what is dictated to backends taking a prompt (OpenAI, local, compatible servers), e.g. "Kubernetes migration notes"
+    #[arg(long, global = true)]
+    prompt: Option<String>,
+
+    /// Send custom words as context_bias to Mistral (in the prompt to OpenAI and Whisper-style backends, a phrase set to Google, a vocabulary to AWS)
     #[arg(short = 'b', long, global = true)]
     bias: bool,
 }
@@ -54,230 +247,3042 @@ struct Args {
 enum Commands {
     /// Add a custom word to the vocabulary (for Claude correction)
     AddWord { word: String },
+    /// Inspect the custom vocabulary
+    Words {
+        #[command(subcommand)]
+        command: WordsCommand,
+    },
+    /// Rate a correction (defaults to the latest); bad ones become negative examples
+    Feedback { verdict: Verdict, id: Option<usize> },
+    /// Check API keys, local backend setup and available acceleration
+    Doctor,
+    /// Measure the microphone's noise and speech levels and tune gain and silence detection to them
+    Calibrate,
+    /// List capture devices and what they support, for --device and default_device
+    Devices,
+    /// Manage whisper.cpp models for the local backend
+    Model {
+        #[command(subcommand)]
+        command: ModelCommand,
+    },
+    /// Inspect past corrections
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+    /// Debugging helpers that don't call any API
+    Debug {
+        #[command(subcommand)]
+        command: DebugCommand,
+    },
+    /// Export or list recording sessions
+    Session {
+        #[command(subcommand)]
+        command: SessionCommand,
+    },
+    /// Serve dictation over HTTP, with Prometheus metrics at /metrics
+    #[command(alias = "daemon")]
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:7373")]
+        listen: String,
+        /// Also serve the gRPC API (proto/rec.proto) on this address, e.g. 127.0.0.1:7374
+        #[arg(long, value_name = "ADDRESS")]
+        grpc: Option<String>,
+        /// Run as a systemd service: notify readiness, accept a socket-activated
+        /// listener and log for the journal
+        #[arg(long)]
+        systemd: bool,
+    },
+    /// Record (or read --file), transcribe and compare with a reference text
+    Check {
+        /// Text that was read aloud
+        #[arg(long)]
+        reference: PathBuf,
+    },
+    /// Record (or read --file) a question and print Claude's answer (read aloud with --speak)
+    Ask,
+    /// Download the audio of a video or podcast URL (with yt-dlp) and print a timestamped transcript
+    Url {
+        /// Page or media link (YouTube, podcast episode, direct file...)
+        link: String,
+    },
+    /// Record two microphones to separate tracks and transcribe them as a conversation
+    Interview {
+        /// Input device of the first speaker (A)
+        #[arg(long)]
+        device_a: String,
+        /// Input device of the second speaker (B)
+        #[arg(long)]
+        device_b: String,
+    },
+    /// Record (or read --file) more and append it to the previous dictation, in history, -o and the clipboard
+    Continue,
+    /// Drop a highlight marker in the recording in progress (bind it to a hotkey)
+    Mark,
+    /// Stop the recording in progress without sending it (bind it to a hotkey)
+    Cancel,
+    /// Copy the next part of a --split-on dictation (bind it to a hotkey)
+    Next,
+    /// Merge history and config with an encrypted bundle on a remote, then upload it
+    Sync {
+        /// WebDAV URL, s3://bucket/key or git repository (default: sync.remote in config)
+        #[arg(long)]
+        remote: Option<String>,
+    },
+    /// Answer shell plugins asking for recent dictations and frequent phrases, on a Unix socket
+    CompleteServer,
+    /// Print the most recent transcript without recording
+    Last {
+        /// Print the full history entry as JSON
+        #[arg(long)]
+        json: bool,
+        /// Copy the transcript to the clipboard
+        #[arg(long)]
+        copy: bool,
+        /// Print the transcript as it was before correction
+        #[arg(long)]
+        show_original: bool,
+    },
 }
 
-/// Clear line and print status
-fn status(msg: &str) {
-    eprint!("\r\x1b[K{}", msg);
-    io::stderr().flush().ok();
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Verdict {
+    Good,
+    Bad,
 }
 
-/// Move up one line, clear it, and print status
-fn status_up(msg: &str) {
-    eprint!("\x1b[A\r\x1b[K{}", msg);
-    io::stderr().flush().ok();
+#[derive(Subcommand)]
+enum ModelCommand {
+    /// List available and downloaded models
+    List,
+    /// Download a model (e.g. 'base.en')
+    Pull {
+        name: String,
+        /// Download it even when the host publishes no checksum
+        #[arg(long)]
+        no_verify: bool,
+    },
+    /// Delete a downloaded model
+    Remove { name: String },
+    /// Use a model for the local backend, downloading it if needed
+    Default {
+        name: String,
+        /// Download it even when the host publishes no checksum
+        #[arg(long)]
+        no_verify: bool,
+    },
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+#[derive(Subcommand)]
+enum HistoryCommand {
+    /// List recent entries
+    List {
+        /// Number of entries to show
+        #[arg(short = 'n', long, default_value_t = 10)]
+        count: usize,
+    },
+    /// Show an entry in full (defaults to the latest)
+    Show { id: Option<usize> },
+}
 
-    #[cfg(debug_assertions)]
-    dotenvy::dotenv().ok();
+#[derive(Subcommand)]
+enum WordsCommand {
+    /// How often each custom word was dictated and how often Claude had to fix it
+    Stats,
+    /// Pin how a word is written, whatever the backend or Claude make of it
+    Override {
+        #[command(subcommand)]
+        command: OverrideCommand,
+    },
+}
 
-    // Handle add-word subcommand
-    if let Some(Commands::AddWord { word }) = args.command {
-        let mut config = config::Config::load()?;
-        config.add_custom_word(word.clone());
-        config.save()?;
-        eprintln!("Word added: {}", word);
-        return Ok(());
-    }
+#[derive(Subcommand)]
+enum OverrideCommand {
+    /// Always write HEARD (any casing, as a whole word or phrase) as WRITTEN
+    Add { heard: String, written: String },
+    /// Stop overriding a word
+    Remove { heard: String },
+    /// List the overrides
+    List,
+}
 
-    // Select backend
-    let rec_api_key = std::env::var("REC_API_KEY").ok();
-    let rec_api_url = std::env::var("REC_API_URL").ok();
-    let mistral_key = std::env::var("MISTRAL_API_KEY").ok();
+#[derive(Subcommand)]
+enum DebugCommand {
+    /// Print the correction prompt for a text (defaults to the latest original transcript)
+    Prompt { text: Option<String> },
+}
 
-    let backend = if let (Some(api_key), Some(api_url)) = (rec_api_key, rec_api_url) {
-        Backend::RecApi { api_url, api_key }
-    } else if let Some(api_key) = mistral_key {
-        Backend::Mistral { api_key }
-    } else {
-        return Err("Set REC_API_KEY + REC_API_URL or MISTRAL_API_KEY".into());
-    };
+#[derive(Subcommand)]
+enum SessionCommand {
+    /// List sessions with their number of dictations
+    List,
+    /// Print all dictations of a session as one Markdown document
+    Export { name: String },
+}
 
-    let wav_buffer = if let Some(path) = &args.file {
-        // Read audio file
-        status("Reading file...");
-        std::fs::read(path)?
-    } else {
-        // Record from microphone
-        status("Loading...");
+/// Parse a duration like '30s', '2m', '1h' or a bare number of seconds
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (value, unit) = match s.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("Invalid duration: {}", s))?;
+    let secs = match unit {
+        "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return Err(format!("Invalid duration unit: {}", unit)),
+    };
+    if secs <= 0.0 {
+        return Err("Duration must be positive".to_string());
+    }
+    Ok(Duration::from_secs_f64(secs))
+}
 
-        let host = cpal::default_host();
-        let device = host.default_input_device().ok_or("No mic")?;
-        let config = device.default_input_config()?;
-        let sample_rate = config.sample_rate();
-        let channels = config.channels();
+/// Parse a `key=value` pair for --meta
+fn parse_meta(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid metadata '{}': expected key=value", s))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(format!("Invalid metadata '{}': empty key", s));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
 
-        status("Recording...");
+/// Handle `rec doctor`, with the accelerator given by --accel if any
+fn doctor_command(accel: Option<accel::Accelerator>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = config::Config::load()?;
+    if let Some(accel) = accel {
+        config.local.accelerator = accel;
+    }
+    let check = |ok: bool| if ok { "ok" } else { "--" };
 
-        let samples: Arc<Mutex<Vec<f32>>> = Arc::new(Mutex::new(Vec::new()));
-        let samples_clone = samples.clone();
-
-        let stream = match config.sample_format() {
-            cpal::SampleFormat::F32 => device.build_input_stream(
-                &config.into(),
-                move |data: &[f32], _: &_| {
-                    samples_clone.lock().unwrap().extend_from_slice(data);
-                },
-                |err| eprintln!("Error: {}", err),
-                None,
-            )?,
-            cpal::SampleFormat::I16 => device.build_input_stream(
-                &config.into(),
-                move |data: &[i16], _: &_| {
-                    let floats: Vec<f32> = data.iter().map(|&s| s as f32 / 32768.0).collect();
-                    samples_clone.lock().unwrap().extend(floats);
-                },
-                |err| eprintln!("Error: {}", err),
-                None,
-            )?,
-            _ => return Err("Unsupported format".into()),
-        };
+    println!("Cloud");
+    match Backend::from_env(&config) {
+        Ok(backend) => println!(
+            "  [ok] default backend: {} ({})",
+            backend.name(),
+            backend.capabilities()
+        ),
+        Err(e) => println!("  [--] {}", e),
+    }
+    for name in config.backends.keys() {
+        match Backend::named(name, &config) {
+            Ok(backend) => println!("  [ok] {} ({})", name, backend.capabilities()),
+            Err(e) => println!("  [--] {}: {}", name, e),
+        }
+    }
+    let anthropic = std::env::var("ANTHROPIC_API_KEY").is_ok();
+    println!(
+        "  [{}] Claude correction (ANTHROPIC_API_KEY)",
+        check(anthropic)
+    );
 
-        stream.play()?;
+    println!("Language");
+    match (config.auto_language, locale::system_language()) {
+        (false, _) => println!("  [--] auto_language is off, the backend detects it"),
+        (true, Some(language)) => println!("  [ok] {} (system locale)", language),
+        (true, None) => println!("  [--] no locale set, the backend detects it"),
+    }
+    if let Some(language) = locale::keyboard_language() {
+        println!("  [ok] {} (keyboard layout, used by rec serve)", language);
+    }
 
-        // Wait for Enter
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
+    println!("Local");
+    let binary = accel::find_in_path(&config.local.binary);
+    match &binary {
+        Some(path) => println!("  [ok] whisper.cpp: {}", path.display()),
+        None => println!(
+            "  [--] whisper.cpp: {} not found in PATH",
+            config.local.binary
+        ),
+    }
+    match config.local.model_path() {
+        Some(model) => println!("  [{}] model: {}", check(model.is_file()), model.display()),
+        None => println!("  [--] model: local.model not set"),
+    }
+    match &config.vosk.model_dir {
+        Some(dir) => println!("  [{}] vosk model: {}", check(dir.is_dir()), dir.display()),
+        None => println!("  [--] vosk model: vosk.model_dir not set"),
+    }
 
-        drop(stream);
+    println!("Acceleration");
+    println!("  CPU threads: {}", accel::cpu_threads());
+    let available = accel::available();
+    if available.is_empty() {
+        println!("  GPU: none detected");
+    } else {
+        let names: Vec<_> = available.iter().map(|a| a.to_string()).collect();
+        println!("  GPU: {}", names.join(", "));
+    }
+    println!(
+        "  Selected: {} (configured: {})",
+        config.local.accelerator.resolve(),
+        config.local.accelerator
+    );
 
-        let recorded = samples.lock().unwrap();
-        let duration = recorded.len() as f32 / sample_rate as f32 / channels as f32;
+    Ok(())
+}
 
-        if recorded.is_empty() {
-            status_up("No audio\n");
-            return Err("No audio".into());
-        }
+/// Whether the local backend has its whisper.cpp binary and model
+fn local_installed(config: &config::Config) -> bool {
+    accel::find_in_path(&config.local.binary).is_some()
+        && config
+            .local
+            .model_path()
+            .is_some_and(|model| model.is_file())
+}
 
-        status_up(&format!("{:.1}s transcribing...", duration));
+/// Warn about options the backend can't honor, before anything is recorded
+fn check_capabilities(backend: &Backend, config: &config::Config, args: &Args) {
+    let capabilities = backend.capabilities();
+    let name = backend.name();
+    if args.bias && !capabilities.bias {
+        eprintln!("Warning: {} takes no custom words, --bias is ignored", name);
+    }
+    let diarize = args.diarize || config.backends.get(name).is_some_and(|entry| entry.diarize);
+    if diarize && !capabilities.diarization {
+        eprintln!(
+            "Warning: {} can't tell speakers apart, diarize is ignored",
+            name
+        );
+    }
+    if matches!(args.command, Some(Commands::Interview { .. })) && !capabilities.timestamps {
+        eprintln!(
+            "Warning: {} returns no timestamps, the interview will be one speaker after the other",
+            name
+        );
+    }
+    if args.prompt.is_some() && !capabilities.prompt {
+        eprintln!("Warning: {} takes no prompt, --prompt is ignored", name);
+    }
+    if args.model.is_some() && !capabilities.model {
+        eprintln!("Warning: {} has no model choice, --model is ignored", name);
+    }
+    if args.chapters && !capabilities.timestamps {
+        eprintln!(
+            "Warning: {} returns no timestamps, chapter times will be estimated",
+            name
+        );
+    }
+}
 
-        // Encode WAV
-        let mut wav_buffer = Vec::new();
-        {
-            let cursor = std::io::Cursor::new(&mut wav_buffer);
-            let spec = WavSpec {
-                channels,
-                sample_rate,
-                bits_per_sample: 16,
-                sample_format: hound::SampleFormat::Int,
-            };
-            let mut writer = WavWriter::new(BufWriter::new(cursor), spec)?;
+/// Wait for Enter on stdin without blocking the runtime
+fn wait_for_enter() -> tokio::task::JoinHandle<io::Result<usize>> {
+    tokio::task::spawn_blocking(|| {
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)
+    })
+}
 
-            for &s in recorded.iter() {
-                writer.write_sample((s * 32767.0).clamp(-32768.0, 32767.0) as i16)?;
-            }
+/// Pieces of a transcript with their start time in seconds
+///
+/// Uses the backend's segments when there are any; otherwise splits the text
+/// into sentences and estimates their time from their position (the returned
+/// flag is then true).
+fn timed_parts(transcript: &Transcript, duration: f32) -> (Vec<(f32, &str)>, bool) {
+    if !transcript.segments.is_empty() {
+        let parts = transcript
+            .segments
+            .iter()
+            .map(|s| (s.start, s.text.as_str()))
+            .collect();
+        return (parts, false);
+    }
 
-            writer.finalize()?;
-        }
-        wav_buffer
-    };
+    let total = transcript.text.len().max(1) as f32;
+    let mut offset = 0;
+    let parts = text::sentences(&transcript.text)
+        .into_iter()
+        .map(|sentence| {
+            let start = duration * offset as f32 / total;
+            offset += sentence.len();
+            (start, sentence)
+        })
+        .collect();
+    (parts, true)
+}
 
-    status("Transcribing...");
+/// Transcript with one `[4:12] ...` line per segment (or sentence, with
+/// estimated `[~4:12]` times when the backend gave no usable segments)
+fn timestamped(transcript: &Transcript, duration: f32) -> Transcript {
+    let (parts, estimated) = timed_parts(transcript, duration);
+    let prefix = if estimated { "~" } else { "" };
+    let lines: Vec<String> = parts
+        .iter()
+        .map(|(start, text)| {
+            format!(
+                "[{}{}] {}",
+                prefix,
+                correction::timestamp(*start),
+                text.trim()
+            )
+        })
+        .collect();
+    Transcript::from_text(lines.join("\n"))
+}
 
-    let config = config::Config::load()?;
+/// Recordings shorter than this (in seconds) don't get chapters
+const CHAPTERS_MIN_SECS: f32 = 180.0;
 
-    let model = if args.v2 { MODEL_V2 } else { MODEL_V1 };
-    let text = backend
-        .transcribe(backend::TranscribeOptions {
-            wav_data: wav_buffer,
-            model: model.to_string(),
-            language: args.language,
-            context_bias: if args.bias {
-                config
-                    .custom_words
-                    .iter()
-                    .flat_map(|w| {
-                        w.split(':')
-                            .next()
-                            .unwrap_or(w)
-                            .split_whitespace()
-                            .map(|s| s.to_string())
-                            .collect::<Vec<_>>()
-                    })
-                    .filter(|w| !w.is_empty())
-                    .collect()
-            } else {
-                vec![]
-            },
-        })
-        .await?;
+/// How often input levels are reported with --jsonl
+const LEVEL_INTERVAL: Duration = Duration::from_millis(100);
 
-    let final_text = if args.correct {
-        status("Correcting with Claude...");
+/// Warn when the sound server has the input muted, before recording silence
+fn warn_if_muted(device: Option<&str>) {
+    if audio::source_muted(device) {
+        status("");
+        eprintln!("Warning: the input is muted in the sound settings, nothing will be heard");
+    }
+}
 
-        let anthropic_key =
-            std::env::var("ANTHROPIC_API_KEY").map_err(|_| "ANTHROPIC_API_KEY not set")?;
+/// Say which input a recording comes from, with --verbose
+fn show_input(pipeline: &Pipeline, recorder: &audio::Recorder) {
+    if pipeline.verbose {
+        status("");
+        eprintln!(
+            "Recording from {}, {} Hz, {} channel(s)",
+            recorder.input, recorder.sample_rate, recorder.channels
+        );
+    }
+}
 
-        let history = config::Config::load_history().unwrap_or_default();
+/// Announce a started recording with --jsonl
+fn recording_started(pipeline: &Pipeline, recorder: &audio::Recorder) {
+    show_input(pipeline, recorder);
+    events::emit(
+        "recording_started",
+        serde_json::json!({
+            "sample_rate": recorder.sample_rate,
+            "channels": recorder.channels,
+        }),
+    );
+}
 
-        match correction::correct_transcription(
-            &text,
-            &config.custom_words,
-            &config.claude_model,
-            &anthropic_key,
-            &history,
-        )
-        .await
-        {
-            Ok(output) => {
-                status("");
+/// Stop on Enter, for [`wait_for_stop`]
+async fn enter_pressed() -> Result<instance::Request, Box<dyn std::error::Error>> {
+    wait_for_enter().await??;
+    Ok(instance::Request::Stop)
+}
 
-                // Check if correction was made
-                let was_corrected = output.corrected.is_some();
-                let final_text = output.corrected.unwrap_or_else(|| text.clone());
+/// Wait for `stop` (Enter, or the --ptt key let go), reporting input levels
+/// in the meantime with --jsonl
+///
+/// With `controls`, Space pauses and resumes the recording, Enter or
+/// Ctrl+C end it, and `h`, `/` and `w` list history, search it and add a
+/// word meanwhile (see browse.rs). Returns `Request::Cancel` when `rec cancel` or Ctrl+C
+/// discarded the recording.
+async fn wait_for_stop(
+    recorder: &audio::Recorder,
+    marks: &mut marks::Marks,
+    lock: &mut instance::Lock,
+    limits: &limits::LimitsConfig,
+    mut controls: Option<&mut ptt::Keys>,
+    stop: impl Future<Output = Result<instance::Request, Box<dyn std::error::Error>>>,
+) -> Result<instance::Request, Box<dyn std::error::Error>> {
+    tokio::pin!(stop);
+    let mut meter = tokio::time::interval(LEVEL_INTERVAL);
+    let mut check = tokio::time::interval(LIMITS_INTERVAL);
+    let mut browser = browse::Browser::spawn();
+    let mut prompt: Option<browse::Prompt> = None;
 
-                // Save to history only if correction was made
-                if was_corrected {
-                    if let Err(e) = config::Config::add_to_history(
-                        &text,
-                        &final_text,
-                        &config.claude_model,
-                        &config.custom_words,
-                    ) {
-                        eprintln!("Warning: Failed to save to history: {}", e);
-                    }
+    loop {
+        tokio::select! {
+            request = &mut stop => return request,
+            request = lock.requested() => {
+                requested(request);
+                return Ok(request);
+            }
+            _ = check.tick() => {
+                if let Some(reason) = limits::capture_exceeded(limits, recorder.buffered_bytes()) {
+                    limit_reached(&reason);
+                    return Ok(instance::Request::Stop);
                 }
-
-                // Display
-                if args.debug {
-                    if was_corrected {
-                        eprintln!("Original:  {}", text);
-                        eprintln!("Corrected: {}", final_text);
-                        if let Some(explanation) = output.explanation {
-                            eprintln!("Reason:    {}", explanation);
+            }
+            time = marks.next() => marked(time, marks.times.len()),
+            key = async { controls.as_mut().unwrap().next().await }, if controls.is_some() => {
+                let key = key?;
+                if let Some(typing) = &mut prompt {
+                    match typing.key(key) {
+                        browse::Typed::Editing => status(&typing.line()),
+                        typed => {
+                            if let browse::Typed::Done(request) = typed {
+                                browser.send(request);
+                            }
+                            prompt = None;
+                            status(recording_status(recorder.is_paused()));
                         }
+                    }
+                    continue;
+                }
+                match key {
+                    b' ' => {
+                        let paused = !recorder.is_paused();
+                        recorder.set_paused(paused);
+                        marks.set_paused(paused);
+                        paused_changed(paused);
+                    }
+                    b'\r' | b'\n' => {
+                        // Not echoed in raw mode
                         eprintln!();
-                    } else {
-                        eprintln!("No correction needed");
+                        return Ok(instance::Request::Stop);
+                    }
+                    ptt::CTRL_C => {
                         eprintln!();
+                        return Ok(instance::Request::Cancel);
+                    }
+                    b'h' => browser.send(browse::Request::Recent),
+                    key => {
+                        if let Some(opened) = browse::Prompt::open(key) {
+                            status(&opened.line());
+                            prompt = Some(opened);
+                        }
                     }
-                } else if was_corrected {
-                    // Gray/dim for original, normal for corrected
-                    eprintln!("\x1b[90m{}\x1b[0m", text);
-                    eprintln!();
                 }
-
-                final_text
             }
-            Err(e) => {
-                eprintln!("\nClaude correction failed: {}", e);
-                eprintln!("Falling back to original transcription\n");
-                text
+            lines = browser.answer() => {
+                status("");
+                for line in lines {
+                    eprintln!("{}", line);
+                }
+                match &prompt {
+                    Some(typing) => status(&typing.line()),
+                    None => status(recording_status(recorder.is_paused())),
+                }
+            }
+            _ = meter.tick(), if events::enabled() => {
+                events::emit("level", serde_json::json!({ "rms": recorder.level() }));
             }
         }
-    } else {
-        text
+    }
+}
+
+/// How often recordings are checked against the memory limits
+const LIMITS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Report a recording stopped by a limit instead of Enter
+fn limit_reached(reason: &str) {
+    events::emit("limit_reached", serde_json::json!({ "reason": reason }));
+    // Two newlines: the status that follows replaces the line Enter would have left
+    status(&format!("{}\n\n", reason));
+}
+
+/// Report a recording stopped by another `rec` (`"when_busy": "stop"` or `rec cancel`)
+fn requested(request: instance::Request) {
+    let event = match request {
+        instance::Request::Stop => "stop_requested",
+        instance::Request::Cancel => "recording_cancelled",
+    };
+    events::emit(event, serde_json::json!({}));
+    // The newline Enter would have left, for the status that follows
+    eprintln!();
+}
+
+/// Report the recording paused or resumed with Space
+fn paused_changed(paused: bool) {
+    let event = match paused {
+        true => "paused",
+        false => "resumed",
     };
+    events::emit(event, serde_json::json!({}));
+    status(recording_status(paused));
+}
+
+/// Status line of a recording with controls
+fn recording_status(paused: bool) -> &'static str {
+    match paused {
+        true => "PAUSED (Space to resume, Enter to finish)",
+        false => "Recording...",
+    }
+}
+
+/// Acknowledge a highlight mark dropped with `rec mark`
+fn marked(time: f32, count: usize) {
+    events::emit("mark", serde_json::json!({ "time": time }));
+    status(&format!(
+        "Recording... {} highlight(s), last at {}",
+        count,
+        correction::timestamp(time)
+    ));
+}
 
+/// Read the final text aloud with --speak; a failure doesn't fail the dictation
+async fn speak_back(config: &config::Config, text: &str) {
+    status("Speaking...");
+    let result = speak::speak(&config.speak, text).await;
     status("");
-    println!("{}", final_text);
+    if let Err(e) = result {
+        eprintln!("Warning: --speak failed: {}", e);
+    }
+}
+
+tokio::task_local! {
+    /// Profile of the focused app for the dictation being served
+    static APP_PROFILE: config::Profile;
+}
+
+/// A field of the focused app's profile, within `Pipeline::run_focused`
+fn app_profile<T>(field: impl FnOnce(&config::Profile) -> Option<T>) -> Option<T> {
+    APP_PROFILE
+        .try_with(|profile| field(profile))
+        .ok()
+        .flatten()
+}
+
+/// Everything needed to turn audio into final text
+struct Pipeline {
+    backend: Backend,
+    /// Where requests go, in order, when `backend` fails or stops answering
+    /// (`failover`, then `watchdog.fallback`)
+    failover: Vec<Backend>,
+    config: config::Config,
+    model: String,
+    language: Option<String>,
+    /// Description of what is dictated, for backends taking a prompt
+    prompt: Option<String>,
+    /// Use the active keyboard layout's language, falling back to `language`
+    follow_keyboard: bool,
+    /// Apply the focused app's profile (`app_profiles`) to each dictation served
+    follow_focus: bool,
+    output_language: Option<String>,
+    bias: bool,
+    correct: bool,
+    debug: bool,
+    /// Say which input is recorded from
+    verbose: bool,
+    /// Run recordings through RNNoise before they are trimmed
+    denoise: bool,
+    /// How filler words are removed, unless the focused app's profile says otherwise
+    fillers: config::Fillers,
+    template: Option<String>,
+    /// Ask before saving a dictation that repeats the previous one
+    confirm_duplicates: bool,
+    /// Ask for the names of unknown speakers on the terminal
+    ask_speakers: bool,
+    session: Option<String>,
+    /// Key-value pairs from --meta, stored in history and JSON events
+    meta: std::collections::BTreeMap<String, String>,
+    /// Repository and branch of the working directory, stored in history
+    git: Option<git::GitContext>,
+    /// Dictation this one continues (`rec continue`): its text is context for
+    /// correction, and the two are saved as one history entry
+    continues: Option<config::HistoryEntry>,
+    /// Masks secrets in text sent to Claude, unless disabled
+    scrubber: Option<scrub::Scrubber>,
+    plugins: Plugins,
+}
+
+impl Pipeline {
+    /// Transcription request for a WAV buffer
+    fn options(&self, wav_data: Vec<u8>) -> backend::TranscribeOptions {
+        backend::TranscribeOptions {
+            wav_data,
+            model: self.model.clone(),
+            language: self.speech_language(),
+            context_bias: if self.bias {
+                self.context_bias()
+            } else {
+                vec![]
+            },
+            prompt: self.prompt.clone(),
+        }
+    }
+
+    /// Language spoken in the current dictation, if known
+    fn speech_language(&self) -> Option<String> {
+        app_profile(|p| p.speech_language.clone())
+            .or_else(|| {
+                self.follow_keyboard
+                    .then(locale::keyboard_language)
+                    .flatten()
+            })
+            .or_else(|| self.language.clone())
+    }
+
+    /// How filler words are removed in the current dictation
+    fn fillers(&self) -> config::Fillers {
+        app_profile(|p| p.fillers).unwrap_or(self.fillers)
+    }
+
+    /// Language of the final text in the current dictation, if set
+    fn output_language(&self) -> Option<String> {
+        app_profile(|p| p.output_language.clone()).or_else(|| self.output_language.clone())
+    }
+
+    /// Correction settings for the current dictation
+    fn tuning(&self) -> correction::Tuning {
+        let mut tuning = correction_tuning(&self.config, self.git.as_ref());
+        if let Some(prompt) = app_profile(|p| p.correction_prompt.clone()) {
+            tuning.system = Some(prompt);
+        }
+        if self.fillers() == config::Fillers::Prompt {
+            tuning.fillers = self.config.filler_words.clone();
+        }
+        tuning.preceding = self
+            .continues
+            .as_ref()
+            .map(|entry| self.mask(&entry.corrected).text);
+        tuning.on_partial = Some(std::sync::Arc::new(|text: &str| {
+            events::emit("correction_partial", serde_json::json!({ "text": text }));
+            progress::preview(text);
+        }));
+        tuning
+    }
+
+    /// Whether a backend that may get the audio only takes WAV
+    fn wav_only(&self) -> bool {
+        std::iter::once(&self.backend)
+            .chain(&self.failover)
+            .any(|backend| backend.capabilities().wav_only)
+    }
+
+    /// Run a dictation with the profile of the focused app, with `follow_focus`
+    async fn run_focused(
+        &self,
+        wav_data: Vec<u8>,
+    ) -> Result<Transcript, Box<dyn std::error::Error>> {
+        let profile = match self.follow_focus {
+            true => self.config.app_profile(),
+            false => None,
+        };
+        match profile.and_then(|name| self.config.profile(Some(&name)).ok()) {
+            Some(profile) => APP_PROFILE.scope(profile, self.run(wav_data)).await,
+            None => self.run(wav_data).await,
+        }
+    }
+
+    /// Transcribe audio, then correct it if requested
+    async fn run(&self, wav_data: Vec<u8>) -> Result<Transcript, Box<dyn std::error::Error>> {
+        let original = self.transcribe(wav_data).await?;
+        self.refine(original).await
+    }
+
+    /// Raw transcription from the backend
+    async fn transcribe(
+        &self,
+        wav_data: Vec<u8>,
+    ) -> Result<Transcript, Box<dyn std::error::Error>> {
+        stage(&format!(
+            "Transcribing {}",
+            progress::megabytes(wav_data.len())
+        ));
+        let hooks = &self.config.hooks;
+        let wav_data = hooks::audio(hooks, wav_data).await;
+        let transcript = upload::transcribe(
+            &self.backend,
+            &self.failover,
+            self.options(wav_data),
+            &self.config.limits,
+            &self.config.watchdog,
+        )
+        .await?;
+        let transcript = if transcript.speakers().is_empty() {
+            transcript
+        } else {
+            self.name_speakers(transcript)
+        };
+
+        let text = hooks::text(hooks, "post_transcribe", transcript.text.clone()).await;
+        let text = self.plugins.apply(PluginStage::PostTranscribe, text);
+        if text == transcript.text {
+            Ok(transcript)
+        } else {
+            Ok(Transcript::from_text(text))
+        }
+    }
+
+    /// Head the next dictation in the notes file with the date and calendar
+    /// event, and the git repository and branch, as enabled in config
+    fn write_notes_header(&self, output: Option<&PathBuf>) -> std::io::Result<()> {
+        let Some(path) = output else {
+            return Ok(());
+        };
+        let mut parts = Vec::new();
+        if self.config.calendar.notes_header {
+            parts.push(calendar::Moment::now(&self.config.calendar).heading());
+        }
+        if let Some(git) = &self.git
+            && self.config.git.notes_header
+        {
+            parts.push(git.to_string());
+        }
+        if !parts.is_empty() {
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            writeln!(file, "### {}", parts.join(" · "))?;
+        }
+        Ok(())
+    }
+
+    /// Add the --meta pairs to an event, if any
+    fn with_meta(&self, mut data: serde_json::Value) -> serde_json::Value {
+        if !self.meta.is_empty() {
+            data["meta"] = serde_json::json!(self.meta);
+        }
+        data
+    }
+
+    /// Whether refining involves Claude calls that may take a while
+    fn refines(&self) -> bool {
+        self.correct || self.output_language().is_some() || self.template.is_some()
+    }
+
+    /// Correct, record, translate and template a raw transcription
+    ///
+    /// Segments are kept only if the text comes out unchanged.
+    async fn refine(
+        &self,
+        transcript: Transcript,
+    ) -> Result<Transcript, Box<dyn std::error::Error>> {
+        let threshold = self.config.confidence_threshold;
+        events::emit(
+            "transcription_final",
+            self.with_meta(serde_json::json!({
+                "text": transcript.text,
+                "segments": segments_json(&transcript, threshold),
+            })),
+        );
+        let original = transcript.text.clone();
+
+        let (text, explanation) = if self.correct {
+            let (text, explanation) = self.correct(&transcript).await?;
+            let text = hooks::text(&self.config.hooks, "post_correct", text).await;
+            let text = self.plugins.apply(PluginStage::PostCorrect, text);
+            (text, explanation)
+        } else {
+            (original.clone(), None)
+        };
+        let text = self.apply_word_rules(text);
+        let text = match self.fillers() {
+            // Claude already judged which were fillers
+            config::Fillers::Prompt if self.correct => text,
+            config::Fillers::Off => text,
+            _ => text::remove_fillers(&text, &self.config.filler_words),
+        };
+
+        self.save_history(&original, &text, explanation).await;
+
+        let text = match &self.output_language() {
+            Some(language)
+                if self.speech_language().as_deref().map(language::code)
+                    != Some(language::code(language)) =>
+            {
+                self.translate(&text, language).await?
+            }
+            _ => text,
+        };
+
+        let text = match &self.template {
+            Some(template) => self.fill_template(&text, template).await?,
+            None => text,
+        };
+
+        if self.refines() {
+            events::emit(
+                "correction_final",
+                self.with_meta(serde_json::json!({ "text": text, "original": original })),
+            );
+        }
+
+        if text == original {
+            Ok(transcript)
+        } else {
+            Ok(Transcript::from_text(text))
+        }
+    }
+
+    /// Translate the text into the output language with Claude
+    async fn translate(
+        &self,
+        text: &str,
+        language: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        stage(&format!("Translating to {}", language));
+        let masked = self.mask(text);
+        if self.config.mock.enabled {
+            return Ok(self.unmask(&masked, &self.mock_echo(&masked.text).await));
+        }
+
+        let anthropic_key =
+            std::env::var("ANTHROPIC_API_KEY").map_err(|_| "ANTHROPIC_API_KEY not set")?;
+
+        let translated = correction::translate(
+            &masked.text,
+            language,
+            &self.config.claude_model,
+            &anthropic_key,
+        )
+        .await?;
+
+        Ok(self.unmask(&masked, &translated))
+    }
+
+    /// Map the dictation onto a template with Claude
+    async fn fill_template(
+        &self,
+        text: &str,
+        template: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        stage("Filling template");
+        let masked = self.mask(text);
+        if self.config.mock.enabled {
+            return Ok(self.unmask(&masked, &self.mock_echo(&masked.text).await));
+        }
+
+        let anthropic_key =
+            std::env::var("ANTHROPIC_API_KEY").map_err(|_| "ANTHROPIC_API_KEY not set")?;
+
+        let filled = correction::fill_template(
+            &masked.text,
+            template,
+            moment_context(&self.config).as_deref(),
+            &self.config.claude_model,
+            &anthropic_key,
+        )
+        .await?;
+
+        Ok(self.unmask(&masked, &filled))
+    }
+
+    /// The text with its secrets masked, as set by `scrub` in config
+    fn mask(&self, text: &str) -> scrub::Masked {
+        match &self.scrubber {
+            Some(scrubber) => scrubber.mask(text),
+            None => scrub::Masked::unchanged(text),
+        }
+    }
+
+    /// History for Claude's examples of past corrections, secrets masked
+    fn masked_history(&self) -> Vec<config::HistoryEntry> {
+        let mut history = config::Config::load_history().unwrap_or_default();
+        for entry in &mut history {
+            entry.original = self.mask(&entry.original).text;
+            entry.corrected = self.mask(&entry.corrected).text;
+        }
+        history
+    }
+
+    /// Put the masked secrets back in Claude's answer
+    fn unmask(&self, masked: &scrub::Masked, answer: &str) -> String {
+        if !masked.kept_in(answer) {
+            status("");
+            eprintln!("Warning: Claude left out a masked secret, it is missing from the text");
+        }
+        masked.restore(answer)
+    }
+
+    /// Insert timestamped chapter headings found by Claude
+    ///
+    /// Times are exact when the backend returned segments and the text came
+    /// out of refining unchanged; otherwise they are estimated from the
+    /// position in the text. On failure the text is kept as is.
+    async fn add_chapters(&self, transcript: Transcript, duration: f32) -> Transcript {
+        stage("Finding chapters");
+
+        let (parts, estimated) = timed_parts(&transcript, duration);
+        let masked = self.mask(&transcript.text);
+        let hidden: Vec<_> = parts
+            .iter()
+            .map(|(start, part)| (*start, masked.hide(part)))
+            .collect();
+        let hidden: Vec<_> = hidden
+            .iter()
+            .map(|(start, part)| (*start, part.as_str()))
+            .collect();
+
+        let result = if self.config.mock.enabled {
+            Ok(vec![(0, "Mock chapter".to_string())])
+        } else {
+            match std::env::var("ANTHROPIC_API_KEY") {
+                Ok(key) => correction::chapters(&hidden, &self.config.claude_model, &key)
+                    .await
+                    .map_err(|e| e.to_string()),
+                Err(_) => Err("ANTHROPIC_API_KEY not set".to_string()),
+            }
+        };
+        let chapters = match result {
+            Ok(chapters) if !chapters.is_empty() => chapters
+                .into_iter()
+                .map(|(start, title)| (start, masked.restore(&title)))
+                .collect::<Vec<_>>(),
+            Ok(_) => return transcript,
+            Err(e) => {
+                status("");
+                eprintln!("Warning: Failed to find chapters: {}", e);
+                return transcript;
+            }
+        };
+
+        let prefix = if estimated { "~" } else { "" };
+        let mut markdown = String::new();
+        let mut next = chapters.iter().peekable();
+        for (i, (start, part)) in parts.iter().enumerate() {
+            if let Some((_, title)) = next.next_if(|(first, _)| *first == i) {
+                if !markdown.is_empty() {
+                    markdown.push_str("\n\n");
+                }
+                let time = correction::timestamp(*start);
+                markdown.push_str(&format!("## {}{} {}\n\n", prefix, time, title));
+            } else if !markdown.is_empty() {
+                markdown.push(' ');
+            }
+            markdown.push_str(part.trim());
+        }
+
+        let list: Vec<_> = chapters
+            .iter()
+            .map(|(i, title)| {
+                serde_json::json!({ "start": parts[*i].0, "estimated": estimated, "title": title })
+            })
+            .collect();
+        events::emit("chapters", serde_json::json!({ "chapters": list }));
+
+        Transcript::from_text(markdown)
+    }
+
+    /// Mock stand-in for translation and templates: the text, unchanged
+    async fn mock_echo(&self, text: &str) -> String {
+        tokio::time::sleep(Duration::from_millis(self.config.mock.latency_ms)).await;
+        text.to_string()
+    }
+
+    /// Write words with a rule in their exact casing, and with their declared
+    /// plural, then aliases as their word, then apply the overrides
+    fn apply_word_rules(&self, mut text: String) -> String {
+        for (word, rule) in &self.config.word_rules {
+            if let Some(plural) = &rule.plural {
+                // The plurals a backend or Claude would make up
+                for made_up in [format!("{}s", word), format!("{}es", word)] {
+                    text = text::replace_word(&text, &made_up, plural);
+                }
+            }
+            text = text::replace_word(&text, word, word);
+        }
+        for (word, aliases) in &self.config.word_aliases {
+            for alias in aliases {
+                text = text::replace_word(&text, alias, word);
+            }
+        }
+        for (heard, written) in &self.config.word_overrides {
+            text = text::replace_word(&text, heard, written);
+        }
+        text
+    }
+
+    /// Custom words split into single terms, as expected by context_bias
+    fn context_bias(&self) -> Vec<String> {
+        self.config
+            .custom_words
+            .iter()
+            .flat_map(|w| {
+                w.split(':')
+                    .next()
+                    .unwrap_or(w)
+                    .split_whitespace()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|w| !w.is_empty())
+            .collect()
+    }
+
+    /// Label each speaker turn with a name from config or the session
+    ///
+    /// On a terminal, unknown speakers are asked for, and the answers are
+    /// remembered in the session if there is one. Segments keep their timing,
+    /// labelled with the names shown.
+    fn name_speakers(&self, transcript: Transcript) -> Transcript {
+        let mut session_names = match &self.session {
+            Some(session) => session::speaker_names(session).unwrap_or_default(),
+            None => Default::default(),
+        };
+        let mut names = self.config.speakers.clone();
+        names.extend(session_names.clone());
+
+        if self.ask_speakers && io::stdin().is_terminal() {
+            status("");
+            let shown = transcript.speaker_names(&names);
+            let mut named = false;
+            for label in transcript.speakers() {
+                if names.contains_key(label) {
+                    continue;
+                }
+                let first = transcript
+                    .segments
+                    .iter()
+                    .find(|s| s.speaker.as_deref() == Some(label))
+                    .map(|s| s.text.as_str())
+                    .unwrap_or_default();
+                eprint!("Who is {} (\"{}\")? [Enter to skip] ", shown[label], first);
+                io::stderr().flush().ok();
+
+                let mut answer = String::new();
+                io::stdin().read_line(&mut answer).ok();
+                let answer = answer.trim();
+                if !answer.is_empty() {
+                    names.insert(label.to_string(), answer.to_string());
+                    session_names.insert(label.to_string(), answer.to_string());
+                    named = true;
+                }
+            }
+
+            if named
+                && let Some(session) = &self.session
+                && let Err(e) = session::save_speaker_names(session, &session_names)
+            {
+                eprintln!("Warning: Failed to save speaker names: {}", e);
+            }
+        }
+
+        let shown = transcript.speaker_names(&names);
+        Transcript {
+            text: transcript.speaker_text(&names),
+            segments: transcript
+                .segments
+                .into_iter()
+                .map(|segment| Segment {
+                    speaker: segment.speaker.map(|label| shown[&label].clone()),
+                    ..segment
+                })
+                .collect(),
+        }
+    }
+
+    /// Record a dictation in history, checking for near-duplicates first
+    async fn save_history(&self, original: &str, corrected: &str, explanation: Option<String>) {
+        if let Some(previous) = &self.continues {
+            if let Err(e) = extend_history(previous, original, corrected, explanation) {
+                eprintln!("Warning: Failed to save to history: {}", e);
+            }
+            return;
+        }
+        let config = &self.config;
+        let model = if self.correct {
+            config.claude_model.as_str()
+        } else {
+            ""
+        };
+        let mut entry = config::HistoryEntry::new(original, corrected, model, &config.custom_words);
+        entry.explanation = explanation;
+        entry.session = self.session.clone();
+        entry.meta = self.meta.clone();
+        entry.git = self.git.clone();
+
+        if self.confirm_duplicates && !self.keep_if_duplicate(&entry).await {
+            return;
+        }
+
+        if let Err(e) = config::Config::add_to_history(entry) {
+            eprintln!("Warning: Failed to save to history: {}", e);
+        }
+    }
+
+    /// Apply the input gain and, with --denoise, suppress background noise
+    fn clean(&self, samples: Vec<f32>, sample_rate: u32, channels: u16) -> Vec<f32> {
+        let samples = audio::amplify(samples, self.config.input_gain);
+        if !self.denoise {
+            return samples;
+        }
+        denoise::denoise(&samples, sample_rate, channels)
+    }
+
+    /// Clean the samples, then drop silence with the configured voice
+    /// activity detector
+    fn trim_silence(&self, samples: Vec<f32>, sample_rate: u32, channels: u16) -> Vec<f32> {
+        let samples = self.clean(samples, sample_rate, channels);
+        match vad::detector(&self.config.vad, &samples, sample_rate, channels) {
+            Ok(Some(mut detector)) => vad::trim(&samples, sample_rate, channels, detector.as_mut()),
+            Ok(None) => samples,
+            Err(e) => {
+                eprintln!(
+                    "Warning: Voice activity detection failed: {}; sending the audio untrimmed",
+                    e
+                );
+                samples
+            }
+        }
+    }
+
+    /// Keep the audio of a dictation with its session, if any, in the `kept_audio` format
+    fn keep_audio(&self, samples: &[f32], sample_rate: u32, channels: u16) {
+        let Some(session) = &self.session else {
+            return;
+        };
+        let saved = self
+            .config
+            .kept_audio
+            .encode(samples, sample_rate, channels)
+            .and_then(|wav_data| {
+                session::session_dir(session)
+                    .and_then(|dir| limits::check_disk(&self.config.limits, &dir, wav_data.len()))
+                    .and_then(|_| session::save_audio(session, &wav_data))
+            });
+        if let Err(e) = saved {
+            eprintln!("Warning: Failed to save audio: {}", e);
+        }
+    }
+
+    /// Whether to save an entry that may repeat the previous dictation
+    async fn keep_if_duplicate(&self, entry: &config::HistoryEntry) -> bool {
+        let threshold = self.config.duplicate_threshold;
+        if threshold <= 0.0 {
+            return true;
+        }
+
+        let history = config::Config::load_history().unwrap_or_default();
+        let Some(previous) = history.last() else {
+            return true;
+        };
+
+        let similarity = text::similarity(&previous.corrected, &entry.corrected);
+        if similarity < threshold {
+            return true;
+        }
+
+        eprintln!(
+            "Warning: nearly identical to the previous dictation (#{}, {:.0}% similar)",
+            history.len(),
+            similarity * 100.0
+        );
+        eprintln!("  Previous: {}", previous.corrected);
+        eprintln!("  New:      {}", entry.corrected);
+
+        if !io::stdin().is_terminal() {
+            return true;
+        }
+
+        eprint!("Save it to history anyway? [y/N] ");
+        io::stderr().flush().ok();
+        let answer = tokio::task::spawn_blocking(|| {
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer).map(|_| answer)
+        })
+        .await;
+        matches!(answer, Ok(Ok(answer)) if matches!(answer.trim(), "y" | "Y" | "yes"))
+    }
+
+    /// Correct a transcription with Claude, falling back to the original on failure
+    ///
+    /// Returns the final text and Claude's explanation if it changed anything.
+    async fn correct(
+        &self,
+        transcript: &Transcript,
+    ) -> Result<(String, Option<String>), Box<dyn std::error::Error>> {
+        stage("Correcting");
+        let config = &self.config;
+        let text = transcript.text.clone();
+        let masked = self.mask(&text);
+
+        let result = if config.mock.enabled {
+            Ok(correction::mock_correction(&masked.text, &config.mock).await)
+        } else {
+            let anthropic_key =
+                std::env::var("ANTHROPIC_API_KEY").map_err(|_| "ANTHROPIC_API_KEY not set")?;
+            let history = self.masked_history();
+            let mut tuning = self.tuning();
+            tuning.uncertain = uncertain_text(transcript, config.confidence_threshold);
+
+            correction::correct_transcription(
+                &masked.text,
+                &correction_words(config),
+                &config.claude_model,
+                &anthropic_key,
+                &history,
+                &tuning,
+            )
+            .await
+        };
+
+        match result {
+            Ok(mut output) => {
+                // Losing a secret would be worse than a missed correction
+                output.corrected = match output.corrected {
+                    Some(corrected) if masked.kept_in(&corrected) => {
+                        Some(masked.restore(&corrected))
+                    }
+                    Some(_) => {
+                        status("");
+                        eprintln!(
+                            "Warning: Claude's correction left out a masked secret, keeping the transcription"
+                        );
+                        None
+                    }
+                    None => None,
+                };
+                output.explanation = output.explanation.map(|e| masked.restore(&e));
+
+                // Check if correction was made
+                let was_corrected = output.corrected.is_some();
+                let final_text = output.corrected.unwrap_or_else(|| text.clone());
+
+                // Display
+                if self.debug || was_corrected {
+                    status("");
+                }
+                if self.debug {
+                    if was_corrected {
+                        eprintln!("Original:  {}", text);
+                        eprintln!("Corrected: {}", final_text);
+                        if let Some(explanation) = &output.explanation {
+                            eprintln!("Reason:    {}", explanation);
+                        }
+                        eprintln!();
+                    } else {
+                        eprintln!("No correction needed");
+                        eprintln!();
+                    }
+                } else if was_corrected {
+                    // Gray/dim for original, normal for corrected
+                    eprintln!("\x1b[90m{}\x1b[0m", text);
+                    eprintln!();
+                }
+
+                Ok((final_text, output.explanation))
+            }
+            Err(e) => {
+                eprintln!("\nClaude correction failed: {}", e);
+                eprintln!("Falling back to original transcription\n");
+                Ok((text, None))
+            }
+        }
+    }
+}
+
+/// Handle `rec history` subcommands
+fn history_command(command: HistoryCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let history = config::Config::load_history()?;
+
+    match command {
+        HistoryCommand::List { count } => {
+            let start = history.len().saturating_sub(count);
+            for (i, entry) in history.iter().enumerate().skip(start) {
+                println!("{:>4}  {}  {}", i + 1, entry.timestamp, entry.corrected);
+            }
+        }
+        HistoryCommand::Show { id } => {
+            let id = id.unwrap_or(history.len());
+            let entry = id
+                .checked_sub(1)
+                .and_then(|i| history.get(i))
+                .ok_or(format!("No history entry #{}", id))?;
+
+            println!("Entry:       #{}", id);
+            println!("Timestamp:   {}", entry.timestamp);
+            println!("Model:       {}", entry.model);
+            if let Some(git) = &entry.git {
+                println!("Git:         {}", git);
+            }
+            println!("Original:    {}", entry.original);
+            println!("Corrected:   {}", entry.corrected);
+            if let Some(explanation) = &entry.explanation {
+                println!("Explanation: {}", explanation);
+            }
+            if let Some(feedback) = entry.feedback {
+                println!("Feedback:    {:?}", feedback);
+            }
+            for (key, value) in &entry.meta {
+                println!("{:<13}{}", format!("{}:", key), value);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Append a continuation to the history entry it continues
+///
+/// The entry is updated in place if it is still the latest; otherwise the
+/// joined dictation is added as a new one.
+fn extend_history(
+    previous: &config::HistoryEntry,
+    original: &str,
+    corrected: &str,
+    explanation: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut entry = previous.clone();
+    entry.original = join_continuation(&previous.original, original);
+    entry.corrected = join_continuation(&previous.corrected, corrected);
+    entry.explanation = match (previous.explanation.clone(), explanation) {
+        (Some(before), Some(now)) => Some(format!("{}; {}", before, now)),
+        (before, now) => before.or(now),
+    };
+
+    let mut history = config::Config::load_history()?;
+    match history.last_mut() {
+        Some(last) if last.timestamp == previous.timestamp => *last = entry,
+        _ => history.push(entry),
+    }
+    config::Config::save_history(&history)
+}
+
+/// Text of a dictation followed by its continuation
+fn join_continuation(previous: &str, text: &str) -> String {
+    match (previous.trim_end(), text.trim_start()) {
+        ("", text) => text.to_string(),
+        (previous, "") => previous.to_string(),
+        (previous, text) => format!("{} {}", previous, text),
+    }
+}
+
+/// Continue the previous dictation's paragraph in the notes file when the
+/// file ends with it, or add the text as a paragraph of its own
+fn write_continuation(path: &Path, previous: &str, text: &str) -> io::Result<()> {
+    let content = std::fs::read_to_string(path).unwrap_or_default();
+    let end = content.trim_end();
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    if !previous.trim().is_empty() && end.ends_with(previous.trim()) {
+        file.set_len(end.len() as u64)?;
+        writeln!(file, " {}", text.trim())
+    } else {
+        writeln!(file, "{}", text)
+    }
+}
+
+/// Custom words as listed for Claude, with their casing and plural rules and
+/// the other spellings merged into them
+fn correction_words(config: &config::Config) -> Vec<String> {
+    let key = config::word_key;
+    let describe = |word: &str| {
+        let mut notes = Vec::new();
+        if let Some(rule) = config.word_rules.get(key(word)) {
+            notes.push("exact casing".to_string());
+            if let Some(plural) = &rule.plural {
+                notes.push(format!("plural: \"{}\"", plural));
+            }
+        }
+        if let Some(aliases) = config.word_aliases.get(key(word))
+            && !aliases.is_empty()
+        {
+            notes.push(format!("not: {}", aliases.join(", ")));
+        }
+        match notes.is_empty() {
+            true => word.to_string(),
+            false => format!("{} ({})", word, notes.join("; ")),
+        }
+    };
+
+    let mut words: Vec<String> = config.custom_words.iter().map(|w| describe(w)).collect();
+    for word in config.word_rules.keys() {
+        if !config.custom_words.iter().any(|w| key(w) == word) {
+            words.push(describe(word));
+        }
+    }
+    words
+}
+
+/// Add a word to the vocabulary, offering to merge it into a custom word it
+/// looks like rather than keeping both spellings
+fn add_word_command(word: String) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = config::Config::load()?;
+    let key = config::word_key(&word);
+    if let Some(entry) = config
+        .custom_words
+        .iter()
+        .find(|w| config::word_key(w) == key)
+    {
+        eprintln!("Already in the vocabulary: {}", entry);
+        return Ok(());
+    }
+    let Some(existing) = config.similar_custom_word(&word) else {
+        config.add_custom_word(word.clone());
+        config.save()?;
+        eprintln!("Word added: {}", word);
+        return Ok(());
+    };
+
+    eprintln!(
+        "'{}' looks like '{}', already in the vocabulary",
+        word, existing
+    );
+    // A merge rewrites the alias in every future dictation, so a different
+    // word one letter away ('Laure', 'Laura') is only merged when asked to
+    if !io::stdin().is_terminal() {
+        config.add_custom_word(word.clone());
+        config.save()?;
+        eprintln!(
+            "Word added: {} (run rec add-word in a terminal to merge it instead)",
+            word
+        );
+        return Ok(());
+    }
+    eprint!(
+        "Keep '{}' and merge '{}' into it [k], write it '{}' from now on [w], or add both [B]? ",
+        existing, key, key
+    );
+    io::stderr().flush().ok();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    match answer.trim().to_lowercase().as_str() {
+        "k" => {
+            config.add_word_alias(&existing, key);
+            eprintln!("Merged: '{}' is written '{}'", key, existing);
+        }
+        "w" => {
+            config.respell_custom_word(&existing, &word);
+            eprintln!("'{}' is now written '{}'", existing, key);
+        }
+        _ => {
+            config.add_custom_word(word.clone());
+            eprintln!("Word added: {}", word);
+        }
+    }
+    config.save()?;
+    Ok(())
+}
+
+/// Text of the segments scored below `threshold`, or `None` when the backend
+/// gave no scores
+fn uncertain_text(transcript: &Transcript, threshold: f32) -> Option<String> {
+    if transcript.segments.iter().all(|s| s.confidence.is_none()) {
+        return None;
+    }
+    let uncertain: Vec<&str> = transcript
+        .segments
+        .iter()
+        .filter(|s| s.confidence.is_some_and(|c| c < threshold))
+        .map(|s| s.text.as_str())
+        .collect();
+    Some(uncertain.join(" "))
+}
+
+/// Dictations a custom word must have missed before it is reported as unused
+const MIN_DICTATIONS_FOR_UNUSED: usize = 20;
+
+/// Sampling settings, system prompt and project context for correction, from config
+fn correction_tuning(config: &config::Config, git: Option<&git::GitContext>) -> correction::Tuning {
+    correction::Tuning {
+        temperature: config.correction_temperature,
+        top_p: config.correction_top_p,
+        system: config.correction_prompt.clone(),
+        project: git
+            .filter(|_| config.git.correction)
+            .map(|git| git.to_string()),
+        notes: retrieval::Notes::shared(&config.retrieval),
+        uncertain: None,
+        moment: moment_context(config),
+        preceding: None,
+        on_partial: None,
+        fillers: vec![],
+    }
+}
+
+/// Date, time and calendar event for Claude, with `calendar.correction`
+fn moment_context(config: &config::Config) -> Option<String> {
+    config
+        .calendar
+        .correction
+        .then(|| calendar::Moment::now(&config.calendar).to_string())
+}
+
+/// List audio hosts and capture devices, marking the default and the one --device picks
+fn devices_command(device: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let host = audio::host()?.id().to_string();
+    let others: Vec<String> = audio::host_names()
+        .into_iter()
+        .filter(|name| *name != host)
+        .collect();
+    match others.is_empty() {
+        true => println!("Host: {}", host),
+        false => println!("Host: {} (also: {})", host, others.join(", ")),
+    }
+    let devices = audio::input_devices()?;
+    if devices.is_empty() {
+        println!("No capture devices");
+    }
+    for (index, input) in devices.iter().enumerate() {
+        let mut marks = Vec::new();
+        if input.default {
+            marks.push("default");
+        }
+        if device.is_some_and(|d| d == input.name || d == index.to_string()) {
+            marks.push("selected");
+        }
+        match marks.is_empty() {
+            true => println!("{}: {}", index, input.name),
+            false => println!("{}: {} ({})", index, input.name, marks.join(", ")),
+        }
+        for config in &input.configs {
+            println!("    {}", config);
+        }
+    }
+    println!("Sound server sources can be used too: 'pulse:<source>' or 'pipewire:<target>'");
+    Ok(())
+}
+
+/// Measure the input levels and store the settings derived from them
+async fn calibrate_command(device: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = config::Config::load()?;
+    warn_if_muted(device);
+    let levels = calibrate::measure(device, config.capture_buffer_ms).await?;
+    let recommended = levels.recommend();
+    recommended.apply(&mut config);
+    config.save()?;
+
+    println!(
+        "Noise floor: {:.0} dBFS, speech: {:.0} dBFS ({:.0} dB above)",
+        calibrate::dbfs(levels.noise),
+        calibrate::dbfs(levels.speech),
+        calibrate::dbfs(levels.speech) - calibrate::dbfs(levels.noise)
+    );
+    println!("Saved to config:");
+    println!("  input_gain: {}", recommended.gain);
+    println!("  vad.threshold: {:.4}", recommended.vad_threshold);
+    println!("  vad.ratio: {}", recommended.vad_ratio);
+    println!("  vad.noise_floor: {:.4}", recommended.noise_floor);
+    if config.vad.engine == vad::VadEngine::None {
+        println!(
+            "Silence trimming is off: turn it on with --vad energy, or adaptive in a noisy place"
+        );
+    }
+    Ok(())
+}
+
+/// Handle `rec words` subcommands
+fn words_command(command: WordsCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let command = match command {
+        WordsCommand::Stats => return words_stats(),
+        WordsCommand::Override { command } => command,
+    };
+    let mut config = config::Config::load()?;
+    match command {
+        OverrideCommand::Add { heard, written } => {
+            config.word_overrides.insert(heard.clone(), written.clone());
+            config.save()?;
+            eprintln!("\"{}\" will be written \"{}\"", heard, written);
+        }
+        OverrideCommand::Remove { heard } => {
+            if config.word_overrides.remove(&heard).is_none() {
+                return Err(format!("No override for {}", heard).into());
+            }
+            config.save()?;
+            eprintln!("Override removed: {}", heard);
+        }
+        OverrideCommand::List if config.word_overrides.is_empty() => {
+            eprintln!("No overrides (add some with 'rec words override add')");
+        }
+        OverrideCommand::List => {
+            for (heard, written) in &config.word_overrides {
+                println!("{} -> {}", heard, written);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How each custom word fared in the history (`rec words stats`)
+fn words_stats() -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::Config::load()?;
+    let history = config::Config::load_history()?;
+
+    if config.custom_words.is_empty() {
+        eprintln!("No custom words (add some with 'rec add-word')");
+        return Ok(());
+    }
+
+    // For each word, only dictations made while it was in the vocabulary count
+    let mut stats: Vec<(&String, usize, usize, usize)> = config
+        .custom_words
+        .iter()
+        .map(|word| {
+            let active: Vec<_> = history
+                .iter()
+                .filter(|entry| entry.custom_words.contains(word))
+                .collect();
+            let used = active
+                .iter()
+                .filter(|entry| text::contains_word(&entry.corrected, word))
+                .count();
+            let fixed = active
+                .iter()
+                .filter(|entry| {
+                    text::contains_word(&entry.corrected, word)
+                        && !text::contains_word(&entry.original, word)
+                })
+                .count();
+            (word, active.len(), used, fixed)
+        })
+        .collect();
+    stats.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0)));
+
+    println!("{:<24} {:>6} {:>6}", "Word", "Used", "Fixed");
+    for (word, active, used, fixed) in stats {
+        let note = if used == 0 && active >= MIN_DICTATIONS_FOR_UNUSED {
+            format!(
+                "never dictated in {} dictations, consider removing it",
+                active
+            )
+        } else if used >= 3 && fixed * 2 > used {
+            "usually misheard, Claude fixes it".to_string()
+        } else {
+            String::new()
+        };
+        let line = format!("{:<24} {:>6} {:>6}  {}", word, used, fixed, note);
+        println!("{}", line.trim_end());
+    }
+
+    Ok(())
+}
+
+/// Handle `rec debug` subcommands
+fn debug_command(command: DebugCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let DebugCommand::Prompt { text } = command;
+    let config = config::Config::load()?;
+    let history = config::Config::load_history()?;
+
+    let text = match text {
+        Some(text) => text,
+        None => history
+            .last()
+            .map(|entry| entry.original.clone())
+            .ok_or("No history yet: pass a text")?,
+    };
+
+    print!(
+        "{}",
+        correction::preview_prompts(
+            &text,
+            &correction_words(&config),
+            &history,
+            &correction_tuning(&config, git::detect().as_ref())
+        )
+    );
+    Ok(())
+}
+
+/// Handle `rec session` subcommands
+fn session_command(command: SessionCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        SessionCommand::List => {
+            for (name, count) in session::list()? {
+                println!("{:<24} {} dictation(s)", name, count);
+            }
+        }
+        SessionCommand::Export { name } => print!("{}", session::export(&name)?),
+    }
+    Ok(())
+}
+
+/// Handle `rec ask`: transcribe a question and answer it with Claude
+///
+/// The question is shown on stderr and the answer printed to stdout;
+/// neither is saved to history.
+async fn ask_command(
+    pipeline: &Pipeline,
+    wav_data: Vec<u8>,
+    speak: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let question = pipeline.transcribe(wav_data).await?.text;
+    if question.trim().is_empty() {
+        status("");
+        return Err("No question heard".into());
+    }
+    status("");
+    eprintln!("\x1b[90m{}\x1b[0m", question.trim());
+
+    stage("Thinking");
+    let masked = pipeline.mask(&question);
+    let answer = if pipeline.config.mock.enabled {
+        pipeline.mock_echo(&masked.text).await
+    } else {
+        let anthropic_key =
+            std::env::var("ANTHROPIC_API_KEY").map_err(|_| "ANTHROPIC_API_KEY not set")?;
+        correction::answer(&masked.text, &pipeline.config.claude_model, &anthropic_key).await?
+    };
+    let answer = masked.restore(&answer);
+    status("");
+
+    events::emit(
+        "answer",
+        serde_json::json!({ "question": question, "text": answer }),
+    );
+    if porcelain::enabled() {
+        porcelain::text(&answer);
+    } else if !events::enabled() {
+        println!("{}", answer);
+    }
+    if speak {
+        speak_back(&pipeline.config, &answer).await;
+    }
+    Ok(())
+}
+
+/// Handle `rec check`: transcribe, then print the word error rate and a word diff
+///
+/// Correction applies with --correct, but nothing is saved to history.
+async fn check_command(
+    pipeline: &Pipeline,
+    wav_data: Vec<u8>,
+    reference: &PathBuf,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let reference = std::fs::read_to_string(reference)?;
+
+    let transcript = pipeline.transcribe(wav_data).await?;
+    let actual = if pipeline.correct {
+        pipeline.correct(&transcript).await?.0
+    } else {
+        transcript.text
+    };
+    status("");
+
+    let edits = text::word_edits(&reference, &actual);
+    let (mut substituted, mut deleted, mut inserted, mut words) = (0, 0, 0, 0);
+    for edit in &edits {
+        match edit {
+            text::WordEdit::Same(_) => words += 1,
+            text::WordEdit::Substituted { .. } => {
+                substituted += 1;
+                words += 1;
+            }
+            text::WordEdit::Deleted(_) => {
+                deleted += 1;
+                words += 1;
+            }
+            text::WordEdit::Inserted(_) => inserted += 1,
+        }
+    }
+
+    println!("{}", render_diff(&edits));
+    println!();
+
+    let errors = substituted + deleted + inserted;
+    let wer = if words == 0 {
+        0.0
+    } else {
+        errors as f32 / words as f32 * 100.0
+    };
+    println!(
+        "WER: {:.1}% ({} substituted, {} missed, {} extra, {} reference words)",
+        wer, substituted, deleted, inserted, words
+    );
+
+    Ok(())
+}
+
+/// Word diff for the terminal: red for reference words that were missed,
+/// green for what was heard instead (`[-missed-]{+heard+}` when piped)
+fn render_diff(edits: &[text::WordEdit]) -> String {
+    let color = io::stdout().is_terminal();
+    let missed = |word: &str| match color {
+        true => format!("\x1b[31m{}\x1b[0m", word),
+        false => format!("[-{}-]", word),
+    };
+    let heard = |word: &str| match color {
+        true => format!("\x1b[32m{}\x1b[0m", word),
+        false => format!("{{+{}+}}", word),
+    };
+
+    edits
+        .iter()
+        .map(|edit| match edit {
+            text::WordEdit::Same(word) => word.to_string(),
+            text::WordEdit::Substituted { reference, actual } => {
+                format!("{}{}", missed(reference), heard(actual))
+            }
+            text::WordEdit::Deleted(word) => missed(word),
+            text::WordEdit::Inserted(word) => heard(word),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Handle `--compare`: transcribe with every backend at once, print the
+/// results in columns, then each one's word diff against the first
+///
+/// Nothing is corrected or saved to history.
+async fn compare_command(
+    pipeline: &Pipeline,
+    wav_data: Vec<u8>,
+    names: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if names.len() < 2 {
+        return Err("--compare needs at least two backends, e.g. --compare mistral,openai".into());
+    }
+    let backends = names
+        .iter()
+        .map(|name| Backend::named(name, &pipeline.config))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    stage(&format!("Transcribing with {}", names.join(", ")));
+    let results = futures_util::future::join_all(backends.iter().map(|backend| {
+        let options = pipeline.options(wav_data.clone());
+        async move {
+            let started = std::time::Instant::now();
+            let result = backend.transcribe(options).await;
+            let text = result.map(|t| t.text).map_err(|e| e.to_string());
+            (text, started.elapsed())
+        }
+    }))
+    .await;
+    status("");
+
+    let width = terminal_width();
+    let column = (width.saturating_sub(3 * (names.len() - 1)) / names.len()).max(20);
+    // Each heading: backend, time and the custom words it got right
+    let mut cells: Vec<Vec<String>> = names
+        .iter()
+        .zip(&results)
+        .map(|(name, (text, elapsed))| {
+            let heard: Vec<&str> = pipeline
+                .config
+                .custom_words
+                .iter()
+                .map(|w| w.split(':').next().unwrap_or(w).trim())
+                .filter(|w| text.as_ref().is_ok_and(|t| text::contains_word(t, w)))
+                .collect();
+            let mut cell = vec![format!("{} ({:.1}s)", name, elapsed.as_secs_f32())];
+            if !heard.is_empty() {
+                cell.extend(wrap(&format!("Custom words: {}", heard.join(", ")), column));
+            }
+            cell
+        })
+        .collect();
+    let heading = cells.iter().map(Vec::len).max().unwrap_or_default();
+    for (cell, (text, _)) in cells.iter_mut().zip(&results) {
+        cell.resize(heading, String::new());
+        cell.push("-".repeat(column));
+        match text {
+            Ok(text) => cell.extend(wrap(text, column)),
+            Err(e) => cell.extend(wrap(&format!("Error: {}", e), column)),
+        }
+    }
+    let rows = cells.iter().map(Vec::len).max().unwrap_or_default();
+    for row in 0..rows {
+        let line: Vec<String> = cells
+            .iter()
+            .map(|cell| format!("{:<1$}", cell.get(row).map_or("", String::as_str), column))
+            .collect();
+        println!("{}", line.join(" | ").trim_end());
+    }
+
+    // Diffs against the first backend that answered
+    let mut answered = names
+        .iter()
+        .zip(&results)
+        .filter_map(|(name, (text, _))| Some((name, text.as_ref().ok()?)));
+    let Some((first, reference)) = answered.next() else {
+        return Err("Every backend failed".into());
+    };
+    for (name, actual) in answered {
+        let edits = text::word_edits(reference, actual);
+        let changed = edits
+            .iter()
+            .filter(|edit| !matches!(edit, text::WordEdit::Same(_)))
+            .count();
+        println!();
+        println!("{} vs {}: {} word(s) differ", name, first, changed);
+        println!("{}", render_diff(&edits));
+    }
+    Ok(())
+}
+
+/// Lines of at most `width` characters, broken between words
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Columns of the terminal, or `COLUMNS`, or 100
+fn terminal_width() -> usize {
+    #[cfg(unix)]
+    {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+        if ok && size.ws_col > 0 {
+            return size.ws_col as usize;
+        }
+    }
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(100)
+}
+
+/// Handle `rec last`
+fn last_command(
+    json: bool,
+    copy: bool,
+    show_original: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let history = config::Config::load_history()?;
+    let entry = history.last().ok_or("No transcript yet")?;
+
+    let text = if show_original {
+        &entry.original
+    } else {
+        &entry.corrected
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(entry)?);
+    } else {
+        println!("{}", text);
+    }
+
+    if copy {
+        match copy_text(text)? {
+            None => eprintln!("Copied to clipboard"),
+            Some(path) => eprintln!("No clipboard: written to {}", path.display()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Handle `rec model` subcommands
+async fn model_command(command: ModelCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        ModelCommand::List => {
+            let config = config::Config::load()?;
+            let downloaded = models::downloaded()?;
+
+            let mut names: Vec<String> =
+                models::KNOWN_MODELS.iter().map(|s| s.to_string()).collect();
+            for name in &downloaded {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+
+            for name in names {
+                let path = models::model_path(&name)?;
+                let is_default = config.local.model.as_ref() == Some(&path);
+                let mark = if is_default { "*" } else { " " };
+                let state = if downloaded.contains(&name) {
+                    "downloaded"
+                } else {
+                    ""
+                };
+                let line = format!("{} {:<22} {}", mark, name, state);
+                println!("{}", line.trim_end());
+            }
+        }
+        ModelCommand::Pull { name, no_verify } => {
+            let path = models::pull(&name, !no_verify).await?;
+            eprintln!("Model ready: {}", path.display());
+        }
+        ModelCommand::Remove { name } => {
+            models::remove(&name)?;
+            eprintln!("Model removed: {}", name);
+        }
+        ModelCommand::Default { name, no_verify } => {
+            let path = models::pull(&name, !no_verify).await?;
+            let mut config = config::Config::load()?;
+            config.local.model = Some(path);
+            config.save()?;
+            eprintln!("Default model: {}", name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Handle `rec feedback`
+fn feedback_command(verdict: Verdict, id: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut history = config::Config::load_history()?;
+    let id = id.unwrap_or(history.len());
+    let entry = id
+        .checked_sub(1)
+        .and_then(|i| history.get_mut(i))
+        .ok_or(format!("No history entry #{}", id))?;
+
+    entry.feedback = Some(match verdict {
+        Verdict::Good => config::Feedback::Good,
+        Verdict::Bad => config::Feedback::Bad,
+    });
+    config::Config::save_history(&history)?;
+
+    eprintln!("Feedback recorded for entry #{}", id);
+    Ok(())
+}
+
+/// Clipboard output, optionally appending to what was there before this run
+struct Clip {
+    prefix: Option<String>,
+    /// Whether the text went to the clipboard file already this run
+    spilled: std::sync::atomic::AtomicBool,
+}
+
+impl Clip {
+    fn new(append: bool, separator: &str) -> Self {
+        let existing = if append {
+            Clipboard::new().and_then(|mut c| c.get_text()).ok()
+        } else {
+            None
+        };
+
+        Self {
+            prefix: existing
+                .filter(|text| !text.is_empty())
+                .map(|text| text + separator),
+            spilled: Default::default(),
+        }
+    }
+
+    /// Replace this run's contribution to the clipboard
+    fn set(&self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let content = match &self.prefix {
+            Some(prefix) => format!("{}{}", prefix, text),
+            None => text.to_string(),
+        };
+        if let Some(path) = copy_text(&content)?
+            && !self
+                .spilled
+                .swap(true, std::sync::atomic::Ordering::Relaxed)
+        {
+            status("");
+            eprintln!(
+                "Warning: No clipboard available, the text is in {}",
+                path.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Copy the final text, or only its first part with `--split-on`
+    fn set_result(
+        &self,
+        text: &str,
+        split_on: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(separator) = split_on else {
+            return self.set(text);
+        };
+        let parts = slots::split(text, separator);
+        self.set(parts.first().map(String::as_str).unwrap_or_default())?;
+        slots::save(&parts)?;
+        if parts.len() > 1 {
+            eprintln!(
+                "Copied part 1 of {}, 'rec next' copies the next",
+                parts.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Copy the next part of the last --split-on dictation
+fn next_command() -> Result<(), Box<dyn std::error::Error>> {
+    let Some(part) = slots::next()? else {
+        return Err("No parts left to copy".into());
+    };
+    match copy_text(&part.text)? {
+        Some(path) => eprintln!(
+            "No clipboard: part {} of {} written to {}",
+            part.number,
+            part.total,
+            path.display()
+        ),
+        None => eprintln!("Copied part {} of {}", part.number, part.total),
+    }
+    Ok(())
+}
+
+/// Copy text to the clipboard, or to a file when there is no clipboard
+///
+/// Headless sessions and some Wayland setups have no clipboard; failing
+/// there would lose a transcription already paid for. Returns the file's
+/// path when the text went there (`$XDG_RUNTIME_DIR/rec-clip.txt`).
+fn copy_text(text: &str) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    let Err(e) = Clipboard::new().and_then(|mut c| c.set_text(text)) else {
+        return Ok(None);
+    };
+    let path = dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("rec-clip.txt");
+    write_private(&path, text).map_err(|io| {
+        format!(
+            "No clipboard ({}), and failed to write {}: {}",
+            e,
+            path.display(),
+            io
+        )
+    })?;
+    Ok(Some(path))
+}
+
+/// Write a file only the user can read, as dictations can be private
+fn write_private(path: &Path, text: &str) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(text.as_bytes())
+}
+
+/// Segments for JSON output, flagging those below the confidence threshold
+fn segments_json(transcript: &Transcript, threshold: f32) -> serde_json::Value {
+    transcript
+        .segments
+        .iter()
+        .map(|segment| {
+            let mut value = serde_json::to_value(segment).unwrap_or_default();
+            if let Some(confidence) = segment.confidence {
+                value["low_confidence"] = (confidence < threshold).into();
+            }
+            value
+        })
+        .collect()
+}
+
+/// Transcript text with low-confidence segments dimmed and underlined
+fn render_heatmap(transcript: &Transcript, threshold: f32) -> String {
+    transcript
+        .segments
+        .iter()
+        .map(|segment| match segment.confidence {
+            Some(confidence) if confidence < threshold => {
+                format!("\x1b[2;4m{}\x1b[0m", segment.text)
+            }
+            _ => segment.text.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Print text to stdout and append it to the output file if any
+///
+/// On a terminal, low-confidence segments are highlighted.
+fn emit(
+    transcript: &Transcript,
+    output: Option<&PathBuf>,
+    threshold: f32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let text = &transcript.text;
+
+    // With --jsonl, the text is already part of the events
+    if porcelain::enabled() {
+        porcelain::text(text);
+    } else if !events::enabled() {
+        // Speaker turns are in the text only
+        if transcript.has_confidence()
+            && transcript.speakers().is_empty()
+            && io::stdout().is_terminal()
+        {
+            println!("{}", render_heatmap(transcript, threshold));
+        } else {
+            println!("{}", text);
+        }
+    }
+
+    if let Some(path) = output {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        writeln!(file, "{}", text)?;
+    }
+
+    Ok(())
+}
+
+type Job<'a> = Pin<Box<dyn Future<Output = Result<Transcript, Box<dyn std::error::Error>>> + 'a>>;
+
+/// Record until Enter, transcribing and emitting a segment every `every`
+///
+/// Segments are processed one at a time in recording order; a failed segment
+/// is reported and skipped so the rest of the recording is not lost.
+async fn record_segments(
+    pipeline: &Pipeline,
+    every: Duration,
+    device: Option<&str>,
+    output: Option<&PathBuf>,
+    lock: &mut instance::Lock,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    status("Loading...");
+    let recorder = audio::Recorder::start(device, pipeline.config.capture_buffer_ms)?;
+    warn_if_muted(device);
+    status("Recording...");
+    recording_started(pipeline, &recorder);
+    let mut recording_marks = marks::Marks::listen();
+
+    let mut enter = wait_for_enter();
+    let mut meter = tokio::time::interval(LEVEL_INTERVAL);
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
+    let mut check = tokio::time::interval(LIMITS_INTERVAL);
+    // Segments waiting for transcription, with their start time in seconds
+    let mut pending: std::collections::VecDeque<(f32, Vec<f32>)> = Default::default();
+    let mut job: Option<Job> = None;
+    let mut job_start = 0.0;
+    let mut texts: Vec<(f32, String)> = Vec::new();
+    // Set by Enter or a limit; the recording stops after this iteration
+    let mut stop = false;
+    let mut stopped = false;
+    let started = std::time::Instant::now();
+    let mut cut = 0.0;
+
+    loop {
+        if job.is_none()
+            && let Some((start, samples)) = pending.pop_front()
+        {
+            pipeline.keep_audio(&samples, recorder.sample_rate, recorder.channels);
+            let wav = audio::encode_wav(&samples, recorder.sample_rate, recorder.channels)?;
+            job = Some(Box::pin(pipeline.run(wav)));
+            job_start = start;
+        }
+
+        if stopped && job.is_none() {
+            break;
+        }
+
+        tokio::select! {
+            _ = ticker.tick(), if !stopped => {
+                let start = std::mem::replace(&mut cut, started.elapsed().as_secs_f32());
+                let samples = pipeline.trim_silence(recorder.take(), recorder.sample_rate, recorder.channels);
+                if !samples.is_empty() {
+                    pending.push_back((start, samples));
+                }
+            }
+            time = recording_marks.next(), if !stopped => marked(time, recording_marks.times.len()),
+            _ = meter.tick(), if !stopped && events::enabled() => {
+                events::emit("level", serde_json::json!({ "rms": recorder.level() }));
+            }
+            _ = check.tick(), if !stopped => {
+                let buffered = recorder.buffered_bytes() + pending.iter().map(|(_, samples)| std::mem::size_of_val(samples.as_slice())).sum::<usize>();
+                if let Some(reason) = limits::capture_exceeded(&pipeline.config.limits, buffered) {
+                    limit_reached(&reason);
+                    stop = true;
+                }
+            }
+            res = &mut enter, if !stopped => {
+                res??;
+                stop = true;
+            }
+            request = lock.requested(), if !stopped => {
+                requested(request);
+                // Segments already out stay out; the rest isn't sent
+                if request == instance::Request::Cancel {
+                    recorder.take();
+                    pending.clear();
+                }
+                stop = true;
+            }
+            res = async { job.as_mut().unwrap().await }, if job.is_some() => {
+                job = None;
+                status("");
+                match res {
+                    Ok(transcript) if !transcript.text.trim().is_empty() => {
+                        emit(&transcript, output, pipeline.config.confidence_threshold)?;
+                        texts.push((job_start, transcript.text));
+                    }
+                    Ok(_) => {}
+                    Err(e) => eprintln!("Segment failed: {}", e),
+                }
+                status(if stopped { "Transcribing..." } else { "Recording..." });
+            }
+        }
+
+        if stop {
+            stop = false;
+            stopped = true;
+            events::emit("recording_stopped", serde_json::json!({}));
+            let samples =
+                pipeline.trim_silence(recorder.take(), recorder.sample_rate, recorder.channels);
+            if !samples.is_empty() {
+                pending.push_back((cut, samples));
+            }
+            status_up("Transcribing...");
+        }
+    }
+
+    drop(recorder);
+    status("");
+
+    if texts.is_empty() {
+        return Err("No audio".into());
+    }
+
+    if !recording_marks.times.is_empty() {
+        let parts: Vec<(f32, &str)> = texts
+            .iter()
+            .map(|(start, text)| (*start, text.as_str()))
+            .collect();
+        let section = marks::highlights(&parts, &recording_marks.times);
+        emit(
+            &Transcript::from_text(section),
+            output,
+            pipeline.config.confidence_threshold,
+        )?;
+    }
+
+    Ok(texts.into_iter().map(|(_, text)| text).collect())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = Args::parse();
+
+    #[cfg(debug_assertions)]
+    dotenvy::dotenv().ok();
+
+    // A broken config is reported by whatever loads it next
+    let startup = config::Config::load().ok();
+    if args.device.is_none() {
+        args.device = startup.as_ref().and_then(|c| c.default_device.clone());
+    }
+    if let Some(host) = args
+        .audio_host
+        .as_ref()
+        .or(startup.as_ref().and_then(|c| c.audio_host.as_ref()))
+    {
+        audio::select_host(host)?;
+    }
+
+    // Handle subcommands
+    match args.command {
+        Some(Commands::AddWord { word }) => return add_word_command(word),
+        Some(Commands::Words { command }) => return words_command(command),
+        Some(Commands::Feedback { verdict, id }) => return feedback_command(verdict, id),
+        Some(Commands::Doctor) => return doctor_command(args.accel),
+        Some(Commands::Calibrate) => return calibrate_command(args.device.as_deref()).await,
+        Some(Commands::Devices) => return devices_command(args.device.as_deref()),
+        Some(Commands::Model { command }) => return model_command(command).await,
+        Some(Commands::History { command }) => return history_command(command),
+        Some(Commands::Session { command }) => return session_command(command),
+        Some(Commands::Debug { command }) => return debug_command(command),
+        Some(Commands::Mark) => {
+            marks::send()?;
+            return Ok(());
+        }
+        Some(Commands::Cancel) => return instance::cancel(),
+        Some(Commands::Next) => return next_command(),
+        Some(Commands::Sync { remote }) => return sync::sync(remote.as_deref()).await,
+        Some(Commands::CompleteServer) => return complete::serve().await,
+        Some(Commands::Last {
+            json,
+            copy,
+            show_original,
+        }) => return last_command(json, copy || args.clip, show_original),
+        Some(Commands::Check { ref reference }) if !reference.is_file() => {
+            return Err(format!("Reference not found: {}", reference.display()).into());
+        }
+        Some(
+            Commands::Serve { .. }
+            | Commands::Check { .. }
+            | Commands::Ask
+            | Commands::Url { .. }
+            | Commands::Interview { .. }
+            | Commands::Continue,
+        )
+        | None => {}
+    }
+
+    if args.jsonl {
+        events::enable();
+    }
+    if let Some(version) = args.porcelain {
+        porcelain::enable(version);
+    }
+    if let Some(Commands::Serve { systemd: true, .. }) = &args.command {
+        systemd::enable();
+    }
+    progress::spawn_ticker();
+
+    if let Some(dir) = &args.debug_http {
+        http::enable_debug(dir.clone())?;
+    }
+
+    let mut config = config::Config::load()?;
+    // Names and locales become codes now, and typos errors, rather than a backend's 400
+    for (name, profile) in config.profiles.iter_mut() {
+        language::normalize(&mut profile.speech_language)
+            .and_then(|_| language::normalize(&mut profile.output_language))
+            .map_err(|e| format!("Profile {}: {}", name, e))?;
+    }
+    let mut http_settings: std::collections::BTreeMap<_, _> = config
+        .backends
+        .iter()
+        .map(|(name, entry)| (name.clone(), entry.http.clone()))
+        .collect();
+    http_settings.insert("claude".to_string(), config.claude_http.clone());
+    http::configure(http_settings);
+
+    // Local backend overrides
+    if let Some(threads) = args.threads {
+        config.local.threads = Some(threads);
+    }
+    if let Some(accel) = args.accel {
+        config.local.accelerator = accel;
+    }
+    if let Some(engine) = args.vad {
+        config.vad.engine = engine;
+    }
+    // Backends that diarize read it from their entry. Custom servers only
+    // when their entry says so: most OpenAI-style ones reject diarized_json
+    if args.diarize {
+        for name in ["elevenlabs", "openai", "aws"] {
+            config.backends.entry(name.to_string()).or_default().diarize = true;
+        }
+    }
+
+    // Select backend; hybrid mode drafts locally first, or runs fully local
+    // when no cloud backend is configured
+    // With --compare, the first backend compared stands for the others
+    let cloud = match (
+        args.backend.as_ref().or(args.compare.first()),
+        config.failover.first(),
+    ) {
+        (Some(name), _) => Backend::named(name, &config),
+        (None, Some(name)) if !config.mock.enabled => Backend::named(name, &config),
+        (None, _) => Backend::from_env(&config),
+    };
+    let cloud = match &args.model {
+        Some(model) => cloud.and_then(|backend| backend.with_model(model)),
+        None => cloud,
+    };
+    let (backend, draft_backend) = match (cloud, args.hybrid) {
+        // No key set: an installed local backend beats failing before recording
+        (Err(e), false)
+            if args.backend.is_none() && args.compare.is_empty() && local_installed(&config) =>
+        {
+            eprintln!("Warning: {}; transcribing locally", e);
+            (Backend::named("local", &config)?, None)
+        }
+        (cloud, false) => (cloud?, None),
+        (Ok(cloud), true) => (cloud, Some(Backend::named("local", &config)?)),
+        (Err(_), true) => (Backend::named("local", &config)?, None),
+    };
+    let mut failover_names: Vec<&String> = Vec::new();
+    for name in config.failover.iter().chain(&config.watchdog.fallback) {
+        if name != backend.name() && !failover_names.contains(&name) {
+            failover_names.push(name);
+        }
+    }
+    let failover = failover_names
+        .into_iter()
+        .filter_map(|name| {
+            Backend::named(name, &config)
+                .map_err(|e| eprintln!("Warning: Failover backend {} unavailable: {}", name, e))
+                .ok()
+        })
+        .collect();
+    check_capabilities(&backend, &config, &args);
+
+    // Hotkeys can't say which app they were pressed in, but the focused app can
+    let serving = matches!(args.command, Some(Commands::Serve { .. }));
+    let profile_name = match &args.profile {
+        Some(name) => Some(name.clone()),
+        None if !serving => config.app_profile(),
+        None => None,
+    };
+    let profile = config.profile(profile_name.as_deref())?;
+    if let Some(prompt) = profile.correction_prompt {
+        config.correction_prompt = Some(prompt);
+    }
+    if let Some(name) = &args.session {
+        session::validate(name)?;
+    }
+
+    let template = match &args.template {
+        Some(name) => Some(
+            config
+                .templates
+                .get(name)
+                .cloned()
+                .ok_or(format!("Unknown template: {}", name))?,
+        ),
+        None => None,
+    };
+
+    // Without an explicit language, guess it from the system rather than
+    // letting the backend detect it on every dictation
+    let explicit_language = match &args.language {
+        Some(language) => Some(language::parse(language)?),
+        None => profile.speech_language,
+    };
+    let auto_language = explicit_language.is_none() && config.auto_language;
+    let language = match explicit_language {
+        Some(language) => Some(language),
+        None if auto_language => locale::system_language(),
+        None => None,
+    };
+
+    // Without a Claude key, a plain transcription beats failing after the recording
+    let claude = config.mock.enabled || std::env::var("ANTHROPIC_API_KEY").is_ok();
+    let output_language = match &args.to {
+        Some(language) => Some(language::parse(language)?),
+        None => profile.output_language,
+    };
+    let skipped: Vec<&str> = [
+        (args.correct, "correction"),
+        (output_language.is_some(), "translation"),
+        (template.is_some(), "template"),
+    ]
+    .into_iter()
+    .filter(|(asked, _)| *asked && !claude)
+    .map(|(_, step)| step)
+    .collect();
+    if !skipped.is_empty() {
+        eprintln!(
+            "Warning: ANTHROPIC_API_KEY not set, transcribing without {}",
+            skipped.join(" or ")
+        );
+    }
+
+    let pipeline = Pipeline {
+        backend,
+        failover,
+        model: match &args.model {
+            Some(model) => model.clone(),
+            None if args.v2 => MODEL_V2.to_string(),
+            None => MODEL_V1.to_string(),
+        },
+        language,
+        prompt: args.prompt.clone().or(config.transcription_prompt.clone()),
+        follow_keyboard: auto_language && serving,
+        follow_focus: serving && args.profile.is_none() && !config.app_profiles.is_empty(),
+        output_language: output_language.filter(|_| claude),
+        bias: args.bias,
+        correct: args.correct && claude,
+        debug: args.debug,
+        verbose: args.verbose,
+        denoise: args.denoise,
+        fillers: profile.fillers.unwrap_or(config.fillers),
+        template: template.filter(|_| claude),
+        confirm_duplicates: args.segment.is_none() && args.command.is_none(),
+        ask_speakers: args.command.is_none() && !args.jsonl && args.porcelain.is_none(),
+        session: args.session.clone(),
+        meta: args.meta.iter().cloned().collect(),
+        git: git::detect(),
+        continues: match args.command {
+            Some(Commands::Continue) => Some(
+                config::Config::load_history()?
+                    .pop()
+                    .ok_or("No dictation to continue: history is empty")?,
+            ),
+            _ => None,
+        },
+        scrubber: scrub::Scrubber::new(&config.scrub)?,
+        plugins: Plugins::load(&config.plugins)?,
+        config,
+    };
+
+    if let Some(Commands::Serve {
+        listen,
+        grpc,
+        systemd,
+    }) = &args.command
+    {
+        return server::serve(
+            pipeline,
+            listen,
+            grpc.as_deref(),
+            args.output.clone(),
+            args.device.clone(),
+            *systemd,
+        )
+        .await;
+    }
+
+    let clip = if args.clip || args.clip_append || args.split_on.is_some() {
+        Some(Clip::new(args.clip_append, &pipeline.config.clip_separator))
+    } else {
+        None
+    };
+
+    let link = match &args.command {
+        Some(Commands::Url { link }) => Some(link),
+        _ => None,
+    };
+
+    // Kept loaded until the end of the run, unloaded on drop
+    let echo_cancel = if args.echo_cancel && args.file.is_none() && link.is_none() {
+        Some(audio::EchoCancel::load(args.device.as_deref())?)
+    } else {
+        None
+    };
+    let device = match &echo_cancel {
+        Some(aec) => Some(aec.device.as_str()),
+        None => args.device.as_deref(),
+    };
+
+    let debounce = Duration::from_millis(pipeline.config.debounce_ms);
+
+    if let Some(Commands::Interview { device_a, device_b }) = &args.command {
+        let Some(mut lock) = instance::acquire(pipeline.config.when_busy, debounce)? else {
+            return Ok(());
+        };
+        let Some(transcript) =
+            interview::record(&pipeline, [device_a, device_b], &mut lock).await?
+        else {
+            return Ok(());
+        };
+        let final_text = pipeline.refine(pipeline.name_speakers(transcript)).await?;
+        status("");
+        pipeline.write_notes_header(args.output.as_ref())?;
+        emit(
+            &final_text,
+            args.output.as_ref(),
+            pipeline.config.confidence_threshold,
+        )?;
+        if let Some(clip) = &clip {
+            clip.set_result(&final_text.text, args.split_on.as_deref())?;
+        }
+        return Ok(());
+    }
+
+    // Segmented recording emits as it goes
+    if let Some(every) = args.segment
+        && args.file.is_none()
+        && args.command.is_none()
+        && !args.show_prompt
+        && args.compare.is_empty()
+    {
+        let Some(mut lock) = instance::acquire(pipeline.config.when_busy, debounce)? else {
+            return Ok(());
+        };
+        pipeline.write_notes_header(args.output.as_ref())?;
+        let texts =
+            record_segments(&pipeline, every, device, args.output.as_ref(), &mut lock).await?;
+        let text = texts.join("\n");
+        if let Some(clip) = &clip {
+            clip.set_result(&text, args.split_on.as_deref())?;
+        }
+        if args.inject_tty {
+            inject::inject(&texts.join(" "))?;
+        }
+        if args.speak {
+            speak_back(&pipeline.config, &text).await;
+        }
+        return Ok(());
+    }
+
+    // Highlight marks dropped during the recording, in seconds
+    let mut mark_times = Vec::new();
+
+    let wav_buffer = if let Some(link) = link {
+        status("Downloading...");
+        download::audio(link)?
+    } else if let Some(path) = &args.file {
+        if media::is_video(path) {
+            status("Extracting audio...");
+            media::extract_audio(path)?
+        } else if pipeline.wav_only() && !media::is_wav(path) {
+            status("Decoding audio...");
+            media::extract_audio(path)?
+        } else {
+            // Read audio file
+            status("Reading file...");
+            std::fs::read(path)?
+        }
+    } else {
+        // Record from microphone, unless another rec is
+        let Some(mut lock) = instance::acquire(pipeline.config.when_busy, debounce)? else {
+            return Ok(());
+        };
+        let mut keys = args.ptt.map(ptt::Keys::listen).transpose()?;
+        // Space to pause, on a terminal; otherwise Enter is read by line
+        let mut controls = match keys {
+            Some(_) => None,
+            None => ptt::Keys::controls(),
+        };
+        status("Loading...");
+        let recorder = audio::Recorder::start(device, pipeline.config.capture_buffer_ms)?;
+        warn_if_muted(device);
+
+        if let (Some(keys), Some(key)) = (&mut keys, args.ptt) {
+            status(&format!("Hold {} to talk...", ptt::key_name(key)));
+            if !keys.pressed().await? {
+                drop(recorder.stop());
+                status("Cancelled\n");
+                return Ok(());
+            }
+            // Nothing from before the key went down
+            drop(recorder.take());
+        }
+
+        status("Recording...");
+        recording_started(&pipeline, &recorder);
+
+        // Wait for Enter, or the key to be let go
+        let mut recording_marks = marks::Marks::listen();
+        let limits = &pipeline.config.limits;
+        let request = match &mut keys {
+            Some(keys) => {
+                let released = async {
+                    let released = keys.released().await?;
+                    // The newline Enter would have left, for the status that follows
+                    eprintln!();
+                    Ok(match released {
+                        true => instance::Request::Stop,
+                        false => instance::Request::Cancel,
+                    })
+                };
+                wait_for_stop(
+                    &recorder,
+                    &mut recording_marks,
+                    &mut lock,
+                    limits,
+                    None,
+                    released,
+                )
+                .await?
+            }
+            None => match &mut controls {
+                Some(controls) => {
+                    wait_for_stop(
+                        &recorder,
+                        &mut recording_marks,
+                        &mut lock,
+                        limits,
+                        Some(controls),
+                        std::future::pending(),
+                    )
+                    .await?
+                }
+                None => {
+                    wait_for_stop(
+                        &recorder,
+                        &mut recording_marks,
+                        &mut lock,
+                        limits,
+                        None,
+                        enter_pressed(),
+                    )
+                    .await?
+                }
+            },
+        };
+        drop(controls);
+        mark_times = recording_marks.times.clone();
+
+        if request == instance::Request::Cancel {
+            drop(recorder.stop());
+            status_up("Cancelled\n");
+            return Ok(());
+        }
+
+        let sample_rate = recorder.sample_rate;
+        let channels = recorder.channels;
+        let recorded = match keys.take() {
+            Some(keys) => keys.cut(recorder.stop(), sample_rate, channels),
+            None => recorder.stop(),
+        };
+        let duration = recorded.len() as f32 / sample_rate as f32 / channels as f32;
+        events::emit(
+            "recording_stopped",
+            serde_json::json!({ "duration": duration }),
+        );
+
+        if recorded.is_empty() {
+            status_up("No audio\n");
+            return Err("No audio".into());
+        }
+
+        // An accidental tap of the hotkey shouldn't reach the API
+        let min_secs = pipeline.config.min_recording_ms as f32 / 1000.0;
+        if duration < min_secs {
+            status_up("Too short\n");
+            return Err(format!(
+                "Recording too short ({:.1}s, min_recording_ms is {}): nothing sent",
+                duration, pipeline.config.min_recording_ms
+            )
+            .into());
+        }
+
+        if audio::is_digital_silence(&recorded) {
+            status_up("Microphone muted\n");
+            return Err(audio::MUTED.into());
+        }
+
+        let recorded = pipeline.trim_silence(recorded, sample_rate, channels);
+        if recorded.is_empty() {
+            status_up("No speech\n");
+            return Err("No speech".into());
+        }
+
+        status_up(&format!("{:.1}s transcribing...", duration));
+
+        pipeline.keep_audio(&recorded, sample_rate, channels);
+        audio::encode_wav(&recorded, sample_rate, channels)?
+    };
+
+    let wav_buffer = if args.enhance {
+        stage("Enhancing");
+        enhance::enhance(&wav_buffer)?
+    } else {
+        wav_buffer
+    };
+
+    if args.show_prompt {
+        let transcript = pipeline.transcribe(wav_buffer).await?;
+        status("");
+        let history = pipeline.masked_history();
+        print!(
+            "{}",
+            correction::preview_prompts(
+                &pipeline.mask(&transcript.text).text,
+                &correction_words(&pipeline.config),
+                &history,
+                &pipeline.tuning()
+            )
+        );
+        return Ok(());
+    }
+
+    if !args.compare.is_empty() {
+        return compare_command(&pipeline, wav_buffer, &args.compare).await;
+    }
+
+    if let Some(Commands::Check { reference }) = &args.command {
+        return check_command(&pipeline, wav_buffer, reference).await;
+    }
+
+    if let Some(Commands::Ask) = &args.command {
+        return ask_command(&pipeline, wav_buffer, args.speak).await;
+    }
+
+    let duration = audio::wav_duration(&wav_buffer);
+
+    let final_text = if let Some(draft_backend) = draft_backend {
+        stage("Transcribing locally");
+        // The cloud backend starts right away, refining in the background
+        // while the local draft is shown
+        let local = draft_backend.transcribe(pipeline.options(wav_buffer.clone()));
+        let refine = pipeline.run(wav_buffer);
+        tokio::pin!(local, refine);
+
+        let mut draft = None;
+        let refined = tokio::select! {
+            refined = &mut refine => refined,
+            local = &mut local => {
+                // Show the draft, then replace it when the refined version arrives
+                if let Ok(local) = &local {
+                    events::emit(
+                        "transcription_partial",
+                        serde_json::json!({ "text": local.text }),
+                    );
+                    status("");
+                    eprintln!("\x1b[90m{}\x1b[0m", local.text);
+                    if let Some(clip) = &clip {
+                        clip.set(&local.text)?;
+                    }
+                }
+                draft = Some(local);
+                refine.await
+            }
+        };
+
+        match refined {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("\nRefinement failed: {}", e);
+                eprintln!("Keeping local transcription\n");
+                match draft {
+                    Some(draft) => draft?,
+                    None => local.await?,
+                }
+            }
+        }
+    } else {
+        let original = pipeline.transcribe(wav_buffer).await?;
+
+        let deadline = args.deadline.or(pipeline
+            .config
+            .correction_deadline
+            .map(Duration::from_secs_f32));
+
+        match deadline.filter(|_| pipeline.refines()) {
+            Some(deadline) => {
+                let refine = pipeline.refine(original.clone());
+                tokio::pin!(refine);
+
+                match tokio::time::timeout(deadline, &mut refine).await {
+                    Ok(text) => text?,
+                    Err(_) => {
+                        // Don't hold up the user: hand over the raw text now,
+                        // and the refined one when it arrives
+                        status("");
+                        if porcelain::enabled() {
+                            porcelain::draft(&original.text);
+                        } else if !events::enabled() {
+                            println!("{}", original.text);
+                        }
+                        if let Some(clip) = &clip {
+                            clip.set(&original.text)?;
+                        }
+                        stage("Still correcting");
+                        refine.await?
+                    }
+                }
+            }
+            None => pipeline.refine(original).await?,
+        }
+    };
+
+    let highlights = (!mark_times.is_empty()).then(|| {
+        let (parts, _) = timed_parts(&final_text, duration.unwrap_or_default());
+        marks::highlights(&parts, &mark_times)
+    });
+
+    let final_text = match duration {
+        Some(duration) if args.chapters && duration >= CHAPTERS_MIN_SECS => {
+            pipeline.add_chapters(final_text, duration).await
+        }
+        // Talks and podcasts are found again by time
+        Some(duration) if link.is_some() && !args.chapters => timestamped(&final_text, duration),
+        _ => final_text,
+    };
+
+    let final_text = match highlights {
+        Some(section) => Transcript::from_text(format!("{}\n\n{}", final_text.text, section)),
+        None => final_text,
+    };
+
+    status("");
+    let threshold = pipeline.config.confidence_threshold;
+    if let Some(previous) = &pipeline.continues {
+        // The continuation on its own, then joined to the previous text
+        // wherever that went
+        emit(&final_text, None, threshold)?;
+        if let Some(path) = &args.output {
+            write_continuation(path, &previous.corrected, &final_text.text)?;
+        }
+        if let Some(clip) = &clip {
+            let text = match args.clip_append {
+                true => final_text.text.clone(),
+                false => join_continuation(&previous.corrected, &final_text.text),
+            };
+            clip.set_result(&text, args.split_on.as_deref())?;
+        }
+    } else {
+        pipeline.write_notes_header(args.output.as_ref())?;
+        emit(&final_text, args.output.as_ref(), threshold)?;
+        if let Some(clip) = &clip {
+            clip.set_result(&final_text.text, args.split_on.as_deref())?;
+        }
+    }
+
+    if args.inject_tty {
+        inject::inject(&final_text.text)?;
+    }
 
-    if args.clip {
-        Clipboard::new()?.set_text(&final_text)?;
+    if args.speak {
+        speak_back(&pipeline.config, &final_text.text).await;
     }
 
     Ok(())
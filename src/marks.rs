@@ -0,0 +1,174 @@
+//! Highlight markers dropped while recording (`rec mark`)
+//!
+//! A recording `rec` registers its pid; `rec mark`, bound to a global hotkey,
+//! sends it SIGUSR1. The transcript then ends with a Highlights section
+//! quoting what was said around each mark.
+
+use crate::correction::timestamp;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Transcript quoted before and after each mark, in seconds
+const BEFORE_SECS: f32 = 20.0;
+const AFTER_SECS: f32 = 10.0;
+
+/// Marks received during a recording, timed from its start
+///
+/// Pauses don't count, so that times match the recorded audio.
+pub struct Marks {
+    started: Instant,
+    paused_at: Option<Instant>,
+    paused_for: Duration,
+    pub times: Vec<f32>,
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+    file: Option<PathBuf>,
+}
+
+impl Marks {
+    /// Start accepting marks, timing them from now
+    pub fn listen() -> Self {
+        Self {
+            started: Instant::now(),
+            paused_at: None,
+            paused_for: Duration::ZERO,
+            times: Vec::new(),
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1())
+                .ok(),
+            file: register().ok(),
+        }
+    }
+
+    /// Wait for the next mark and return its time in seconds
+    pub async fn next(&mut self) -> f32 {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            signal.recv().await;
+            let time = self.recorded().as_secs_f32();
+            self.times.push(time);
+            return time;
+        }
+        std::future::pending().await
+    }
+
+    /// Stop or restart the clock along with the recording
+    pub fn set_paused(&mut self, paused: bool) {
+        match (paused, self.paused_at) {
+            (true, None) => self.paused_at = Some(Instant::now()),
+            (false, Some(at)) => {
+                self.paused_for += at.elapsed();
+                self.paused_at = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Time recorded so far, pauses left out
+    fn recorded(&self) -> Duration {
+        let now = self.paused_at.unwrap_or_else(Instant::now);
+        (now - self.started).saturating_sub(self.paused_for)
+    }
+}
+
+impl Drop for Marks {
+    fn drop(&mut self) {
+        if let Some(file) = &self.file {
+            std::fs::remove_file(file).ok();
+        }
+    }
+}
+
+/// Directory holding one file per recording `rec`, named after its pid
+fn recordings_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = dirs::runtime_dir()
+        .or_else(dirs::cache_dir)
+        .ok_or("Could not find runtime directory")?
+        .join("rec")
+        .join("recording");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn register() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let file = recordings_dir()?.join(std::process::id().to_string());
+    std::fs::write(&file, "")?;
+    Ok(file)
+}
+
+/// Drop a mark in every recording in progress, returning how many got it
+pub fn send() -> Result<usize, Box<dyn std::error::Error>> {
+    let mut sent = 0;
+    for entry in std::fs::read_dir(recordings_dir()?)? {
+        let path = entry?.path();
+        let Some(pid) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse::<i32>().ok())
+        else {
+            continue;
+        };
+
+        if is_rec(pid) && signal(pid) {
+            sent += 1;
+        } else {
+            // Left behind by a rec that crashed
+            std::fs::remove_file(&path).ok();
+        }
+    }
+
+    if sent == 0 {
+        return Err("No recording in progress".into());
+    }
+    Ok(sent)
+}
+
+/// Whether a pid still belongs to rec, in case it was reused after a crash
+///
+/// Only checked where /proc exists; SIGUSR1 would kill another program.
+fn is_rec(pid: i32) -> bool {
+    let Ok(cmdline) = std::fs::read(format!("/proc/{}/cmdline", pid)) else {
+        return !std::path::Path::new("/proc/self").exists();
+    };
+    let program = cmdline.split(|&b| b == 0).next().unwrap_or_default();
+    let program = std::path::Path::new(std::str::from_utf8(program).unwrap_or_default());
+    let own = std::env::current_exe().ok();
+    program.file_name() == own.as_deref().and_then(|exe| exe.file_name())
+}
+
+#[cfg(unix)]
+fn signal(pid: i32) -> bool {
+    // SAFETY: kill has no memory safety requirements
+    unsafe { libc::kill(pid, libc::SIGUSR1) == 0 }
+}
+
+#[cfg(not(unix))]
+fn signal(_pid: i32) -> bool {
+    false
+}
+
+/// Markdown section quoting the transcript around each mark
+///
+/// `parts` are consecutive pieces of the transcript with their start time.
+pub fn highlights(parts: &[(f32, &str)], marks: &[f32]) -> String {
+    let mut section = String::from("## Highlights\n");
+
+    for &mark in marks {
+        let mut quoted: Vec<&str> = parts
+            .iter()
+            .filter(|(start, _)| *start >= mark - BEFORE_SECS && *start <= mark + AFTER_SECS)
+            .map(|(_, text)| text.trim())
+            .collect();
+
+        // A long part can start well before the mark and still cover it
+        if quoted.is_empty()
+            && let Some((_, text)) = parts.iter().rev().find(|(start, _)| *start <= mark)
+        {
+            quoted.push(text.trim());
+        }
+
+        section.push_str(&format!("\n- {}: {}", timestamp(mark), quoted.join(" ")));
+    }
+    section.push('\n');
+    section
+}
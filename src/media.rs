@@ -0,0 +1,137 @@
+//! Audio track of video files (`rec -f talk.mp4`)
+//!
+//! Screen recordings and downloaded talks are decoded in-process when
+//! symphonia knows the container and codec (MP4/MOV and MKV/WebM with AAC,
+//! MP3, FLAC or Vorbis audio), and with the `ffmpeg` command otherwise.
+
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CODEC_TYPE_NULL, DecoderOptions};
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// Extensions treated as video containers
+const VIDEO_EXTENSIONS: &[&str] = &[
+    "mp4", "m4v", "mov", "mkv", "webm", "avi", "wmv", "flv", "mpg", "mpeg", "ts",
+];
+
+/// Whether a file is a video, judging by its extension
+pub fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Whether a file is WAV, judging by its header
+pub fn is_wav(path: &Path) -> bool {
+    let mut header = [0; 12];
+    std::fs::File::open(path)
+        .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut header))
+        .is_ok_and(|_| &header[..4] == b"RIFF" && &header[8..] == b"WAVE")
+}
+
+/// Audio track of a video file, or the audio of any media file, as a mono WAV
+pub fn extract_audio(path: &Path) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let error = match decode(path) {
+        Ok((samples, _)) if samples.is_empty() => {
+            return Err(format!("No audio in {}", path.display()).into());
+        }
+        Ok((samples, rate)) => return crate::audio::encode_wav(&samples, rate, 1),
+        Err(e) => e,
+    };
+
+    match ffmpeg(path) {
+        Some(wav) => Ok(wav),
+        None => Err(format!(
+            "Could not read the audio of {}: {} (install ffmpeg for more formats)",
+            path.display(),
+            error
+        )
+        .into()),
+    }
+}
+
+/// Samples of the first audio track mixed down to mono, with their rate
+fn decode(path: &Path) -> Result<(Vec<f32>, u32), Box<dyn std::error::Error>> {
+    let source = MediaSourceStream::new(Box::new(std::fs::File::open(path)?), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &hint,
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?
+        .format;
+
+    // Video tracks have no sample rate
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL && t.codec_params.sample_rate.is_some())
+        .ok_or("no audio track")?;
+    let track_id = track.id;
+    let mut rate = track.codec_params.sample_rate.unwrap_or_default();
+    // PCM tracks (OBS can record them in MKV) may not state their packet
+    // size, which the PCM decoder needs; allow up to a second
+    let mut params = track.codec_params.clone();
+    if params.max_frames_per_packet.is_none() {
+        params.with_max_frames_per_packet(rate as u64);
+    }
+    let mut decoder = symphonia::default::get_codecs().make(&params, &DecoderOptions::default())?;
+
+    let mut mono = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(DecodeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt packet only costs a few milliseconds of audio
+            Err(DecodeError::DecodeError(_)) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+        mono.extend(
+            buffer
+                .samples()
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        );
+        rate = spec.rate;
+    }
+    Ok((mono, rate))
+}
+
+/// Mono WAV of the audio track with ffmpeg, if it is installed and succeeds
+fn ffmpeg(path: &Path) -> Option<Vec<u8>> {
+    // A WAV written to a pipe has no sizes in its header, so go through a file
+    let wav = std::env::temp_dir().join(format!("rec-video-{}.wav", std::process::id()));
+    let status = std::process::Command::new("ffmpeg")
+        .args(["-nostdin", "-loglevel", "error", "-y", "-i"])
+        .arg(path)
+        .args(["-vn", "-ac", "1"])
+        .arg(&wav)
+        .status();
+    let data = std::fs::read(&wav).ok();
+    std::fs::remove_file(&wav).ok();
+    data.filter(|_| status.is_ok_and(|s| s.success()))
+}
@@ -0,0 +1,102 @@
+//! Prometheus metrics for `rec serve`
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds (in seconds) of the latency histogram buckets
+const BUCKETS: &[f64] = &[0.5, 1.0, 2.0, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+#[derive(Default)]
+struct BackendMetrics {
+    dictations: u64,
+    errors: u64,
+    /// Cumulative counts, one per bucket
+    buckets: [u64; BUCKETS.len()],
+    seconds: f64,
+}
+
+/// Counters and latency histograms per backend
+#[derive(Default)]
+pub struct Metrics {
+    backends: Mutex<BTreeMap<String, BackendMetrics>>,
+}
+
+impl Metrics {
+    /// Record a successful dictation and how long it took
+    pub fn dictation(&self, backend: &str, elapsed: Duration) {
+        let mut backends = self.backends.lock().unwrap();
+        let metrics = backends.entry(backend.to_string()).or_default();
+        let seconds = elapsed.as_secs_f64();
+
+        metrics.dictations += 1;
+        metrics.seconds += seconds;
+        for (count, bound) in metrics.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+    }
+
+    /// Record a failed dictation
+    pub fn error(&self, backend: &str) {
+        let mut backends = self.backends.lock().unwrap();
+        backends.entry(backend.to_string()).or_default().errors += 1;
+    }
+
+    /// Metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let backends = self.backends.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP rec_dictations_total Dictations transcribed\n");
+        out.push_str("# TYPE rec_dictations_total counter\n");
+        for (backend, m) in backends.iter() {
+            let _ = writeln!(
+                out,
+                "rec_dictations_total{{backend=\"{}\"}} {}",
+                backend, m.dictations
+            );
+        }
+
+        out.push_str("# HELP rec_errors_total Dictations that failed\n");
+        out.push_str("# TYPE rec_errors_total counter\n");
+        for (backend, m) in backends.iter() {
+            let _ = writeln!(
+                out,
+                "rec_errors_total{{backend=\"{}\"}} {}",
+                backend, m.errors
+            );
+        }
+
+        out.push_str("# HELP rec_dictation_duration_seconds Time to process a dictation\n");
+        out.push_str("# TYPE rec_dictation_duration_seconds histogram\n");
+        for (backend, m) in backends.iter() {
+            for (count, bound) in m.buckets.iter().zip(BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "rec_dictation_duration_seconds_bucket{{backend=\"{}\",le=\"{}\"}} {}",
+                    backend, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "rec_dictation_duration_seconds_bucket{{backend=\"{}\",le=\"+Inf\"}} {}",
+                backend, m.dictations
+            );
+            let _ = writeln!(
+                out,
+                "rec_dictation_duration_seconds_sum{{backend=\"{}\"}} {}",
+                backend, m.seconds
+            );
+            let _ = writeln!(
+                out,
+                "rec_dictation_duration_seconds_count{{backend=\"{}\"}} {}",
+                backend, m.dictations
+            );
+        }
+
+        out
+    }
+}
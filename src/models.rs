@@ -0,0 +1,201 @@
+//! Whisper model management for the local backend
+
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+const MODEL_BASE_URL: &str = "https://huggingface.co/ggerganov/whisper.cpp/resolve/main";
+
+/// Models published by whisper.cpp
+pub const KNOWN_MODELS: &[&str] = &[
+    "tiny",
+    "tiny.en",
+    "tiny-q5_1",
+    "base",
+    "base.en",
+    "base-q5_1",
+    "small",
+    "small.en",
+    "small-q5_1",
+    "medium",
+    "medium.en",
+    "medium-q5_0",
+    "large-v3",
+    "large-v3-q5_0",
+    "large-v3-turbo",
+    "large-v3-turbo-q5_0",
+];
+
+/// Directory where models are stored
+pub fn models_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = dirs::data_dir()
+        .ok_or("Could not find data directory")?
+        .join("rec")
+        .join("models");
+
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Path of a model file by name (e.g. 'base.en')
+///
+/// Names with path separators or '..' are rejected, so a model always
+/// stays inside the models directory.
+pub fn model_path(name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+        return Err(format!("Invalid model name: {}", name).into());
+    }
+    Ok(models_dir()?.join(format!("ggml-{}.bin", name)))
+}
+
+/// Names of the models downloaded so far
+pub fn downloaded() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut names: Vec<String> = fs::read_dir(models_dir()?)?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().to_string_lossy().to_string();
+            let name = name.strip_prefix("ggml-")?.strip_suffix(".bin")?;
+            Some(name.to_string())
+        })
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Download a model, resuming a previous partial download if any
+///
+/// The file is verified against the SHA-256 published by the model host
+/// before being moved into place. Without one, the download is refused
+/// unless `verify` is off.
+pub async fn pull(name: &str, verify: bool) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = model_path(name)?;
+    if path.exists() {
+        return Ok(path);
+    }
+
+    let url = format!("{}/ggml-{}.bin", MODEL_BASE_URL, name);
+    let part_path = path.with_extension("bin.part");
+
+    let expected = expected_sha256(&url).await?;
+    if expected.is_none() {
+        if verify {
+            return Err(format!(
+                "No checksum published for {}, run again with --no-verify to download it unchecked",
+                name
+            )
+            .into());
+        }
+        eprintln!(
+            "Warning: No checksum published for {}, downloading it unchecked",
+            name
+        );
+    }
+
+    let offset = fs::metadata(&part_path).map(|m| m.len()).unwrap_or(0);
+
+    let client = reqwest::Client::new();
+    let mut req = client.get(&url);
+    if offset > 0 {
+        req = req.header("range", format!("bytes={}-", offset));
+    }
+    let mut resp = req.send().await?;
+
+    let status = resp.status();
+    if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // The partial file is already complete
+    } else if !status.is_success() {
+        return Err(format!("Download failed for {}: {}", name, status).into());
+    } else {
+        let resumed = status == reqwest::StatusCode::PARTIAL_CONTENT;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(&part_path)?;
+
+        let mut done = if resumed { offset } else { 0 };
+        let total = resp.content_length().map(|len| len + done);
+
+        while let Some(chunk) = resp.chunk().await? {
+            file.write_all(&chunk)?;
+            done += chunk.len() as u64;
+            match total {
+                Some(total) => eprint!(
+                    "\r\x1b[KDownloading {}... {:.0}%",
+                    name,
+                    done as f64 / total as f64 * 100.0
+                ),
+                None => eprint!("\r\x1b[KDownloading {}... {} MB", name, done / 1_000_000),
+            }
+        }
+        eprint!("\r\x1b[K");
+    }
+
+    if let Some(expected) = expected {
+        eprint!("\r\x1b[KVerifying {}...", name);
+        let actual = sha256_file(&part_path)?;
+        eprint!("\r\x1b[K");
+
+        if !actual.eq_ignore_ascii_case(&expected) {
+            fs::remove_file(&part_path)?;
+            return Err(format!(
+                "Checksum mismatch for {} (expected {}, got {})",
+                name, expected, actual
+            )
+            .into());
+        }
+    }
+
+    fs::rename(&part_path, &path)?;
+    Ok(path)
+}
+
+/// Remove a downloaded model
+pub fn remove(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let path = model_path(name)?;
+    if !path.exists() {
+        return Err(format!("Model not downloaded: {}", name).into());
+    }
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+/// SHA-256 advertised by Hugging Face for an LFS file, if any
+async fn expected_sha256(url: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    let resp = client.head(url).send().await?;
+
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(format!("Unknown model: {}", url).into());
+    }
+
+    Ok(resp
+        .headers()
+        .get("x-linked-etag")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim_matches('"').to_string())
+        .filter(|v| v.len() == 64))
+}
+
+fn sha256_file(path: &PathBuf) -> Result<String, Box<dyn std::error::Error>> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
@@ -0,0 +1,137 @@
+//! WebAssembly text post-processors (`plugins` in config)
+//!
+//! A plugin is a core WASM module exporting:
+//! - `memory`
+//! - `alloc(len: i32) -> i32`, returning where rec may write `len` bytes
+//! - `transform(ptr: i32, len: i32) -> i64`, reading UTF-8 text and returning
+//!   the result's pointer in the high 32 bits and its length in the low 32 bits
+//!
+//! Plugins get no imports (no files, network or clock), bounded memory and a
+//! bounded amount of work, so sharing them is safe.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use wasmtime::{Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/// Memory a plugin may grow to
+const MAX_MEMORY: usize = 64 << 20;
+
+/// Work a plugin may do per call, in wasmtime fuel units (about one per instruction)
+const FUEL: u64 = 2_000_000_000;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PluginConfig {
+    /// Path to the .wasm file
+    pub path: PathBuf,
+    #[serde(default)]
+    pub stage: PluginStage,
+}
+
+/// Where in the pipeline a plugin runs
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginStage {
+    /// On the raw transcription, before it is corrected or saved to history
+    #[default]
+    PostTranscribe,
+    /// On the corrected text (with --correct)
+    PostCorrect,
+}
+
+struct Plugin {
+    name: String,
+    stage: PluginStage,
+    module: Module,
+}
+
+/// Plugins compiled once, then instantiated fresh for every text
+pub struct Plugins {
+    engine: Engine,
+    plugins: Vec<Plugin>,
+}
+
+impl Plugins {
+    /// Compile the configured plugins
+    pub fn load(configs: &[PluginConfig]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)?;
+
+        let mut plugins = Vec::new();
+        for plugin in configs {
+            let module = Module::from_file(&engine, &plugin.path)
+                .map_err(|e| format!("Plugin {}: {}", plugin.path.display(), e))?;
+            if let Some(import) = module.imports().next() {
+                return Err(format!(
+                    "Plugin {}: imports are not allowed ({}::{})",
+                    plugin.path.display(),
+                    import.module(),
+                    import.name()
+                )
+                .into());
+            }
+
+            plugins.push(Plugin {
+                name: plugin.path.display().to_string(),
+                stage: plugin.stage,
+                module,
+            });
+        }
+
+        Ok(Self { engine, plugins })
+    }
+
+    /// Run the text through every plugin of a stage, in config order
+    ///
+    /// A failing plugin is reported and skipped.
+    pub fn apply(&self, stage: PluginStage, mut text: String) -> String {
+        for plugin in self.plugins.iter().filter(|p| p.stage == stage) {
+            match self.run(plugin, &text) {
+                Ok(output) => text = output,
+                Err(e) => eprintln!("Warning: plugin {} failed: {}", plugin.name, e),
+            }
+        }
+        text
+    }
+
+    fn run(&self, plugin: &Plugin, text: &str) -> Result<String, String> {
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build();
+        let mut store: Store<StoreLimits> = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL).map_err(|e| e.to_string())?;
+
+        let instance = Instance::new(&mut store, &plugin.module, &[]).map_err(|e| e.to_string())?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or("missing 'memory' export")?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&mut store, "alloc")
+            .map_err(|e| e.to_string())?;
+        let transform = instance
+            .get_typed_func::<(i32, i32), i64>(&mut store, "transform")
+            .map_err(|e| e.to_string())?;
+
+        let len = i32::try_from(text.len()).map_err(|_| "text too long")?;
+        let ptr = alloc.call(&mut store, len).map_err(describe)?;
+        memory
+            .write(&mut store, ptr as u32 as usize, text.as_bytes())
+            .map_err(|e| e.to_string())?;
+
+        let result = transform.call(&mut store, (ptr, len)).map_err(describe)? as u64;
+        let (out_ptr, out_len) = ((result >> 32) as usize, (result & 0xffff_ffff) as usize);
+
+        let output = memory
+            .data(&store)
+            .get(out_ptr..out_ptr + out_len)
+            .ok_or("result out of bounds")?;
+        String::from_utf8(output.to_vec()).map_err(|_| "result is not UTF-8".to_string())
+    }
+}
+
+/// Traps as one line (e.g. "all fuel consumed"), without the wasm backtrace
+fn describe(error: wasmtime::Error) -> String {
+    match error.downcast_ref::<wasmtime::Trap>() {
+        Some(trap) => trap.to_string(),
+        None => error.to_string(),
+    }
+}
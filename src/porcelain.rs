@@ -0,0 +1,73 @@
+//! Stable line-oriented output for scripts (--porcelain)
+//!
+//! Like `git status --porcelain`, the format is versioned and doesn't
+//! follow the status line's look. Everything goes to stdout, one line each,
+//! after a `# rec porcelain v1` header:
+//!
+//! - `status <message>`: a status or processing stage
+//! - `text <line>`: a line of the result, a block of them ending with `end`
+//! - `draft <line>`: a line of the uncorrected text handed over at the
+//!   --deadline, ending with `end`, before the `text` of the corrected one
+//!
+//! Lines of a new kind may be added within v1 and should be skipped; the
+//! existing ones only change with a new version. Warnings and errors stay
+//! on stderr, and a failed run exits non-zero.
+
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Versions of the format, picked with `--porcelain=<version>`
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Version {
+    V1,
+}
+
+/// Switch stdout to porcelain lines for the rest of the run
+pub fn enable(version: Version) {
+    ENABLED.store(true, Ordering::Relaxed);
+    crate::progress::plain();
+    let name = match version {
+        Version::V1 => "v1",
+    };
+    write_lines([format!("# rec porcelain {}", name)]);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// A status line, its message flattened to one line
+pub fn status(message: &str) {
+    let message = message.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !message.is_empty() {
+        write_lines([format!("status {}", message)]);
+    }
+}
+
+/// The result, each line prefixed with `text `
+pub fn text(text: &str) {
+    block("text", text);
+}
+
+/// The uncorrected text handed over while correction goes on
+pub fn draft(text: &str) {
+    block("draft", text);
+}
+
+fn block(kind: &str, text: &str) {
+    write_lines(
+        text.lines()
+            .map(|line| format!("{} {}", kind, line))
+            .chain(["end".to_string()]),
+    );
+}
+
+fn write_lines(lines: impl IntoIterator<Item = String>) {
+    let mut stdout = std::io::stdout().lock();
+    for line in lines {
+        writeln!(stdout, "{}", line).ok();
+    }
+    stdout.flush().ok();
+}
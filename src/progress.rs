@@ -0,0 +1,143 @@
+//! Status line on stderr, with live stage timings while processing
+
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// How often the elapsed time of the current stage is refreshed
+const TICK: Duration = Duration::from_millis(100);
+
+/// Stages of the current dictation, with the time each one started
+static STAGES: Mutex<Vec<(String, Instant)>> = Mutex::new(Vec::new());
+
+/// Text the current stage has produced so far, e.g. the correction streaming in
+static PREVIEW: Mutex<String> = Mutex::new(String::new());
+
+/// Characters of the preview shown, its end
+const PREVIEW_CHARS: usize = 40;
+
+/// Print each status as a line of its own, for logs rather than a terminal
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Switch to plain status lines for the rest of the run (e.g. under journald)
+pub fn plain() {
+    PLAIN.store(true, Ordering::Relaxed);
+}
+
+fn is_plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+/// Clear the line and print status
+pub fn status(msg: &str) {
+    let mut stages = STAGES.lock().unwrap();
+    stages.clear();
+    PREVIEW.lock().unwrap().clear();
+    if is_plain() {
+        print_line(msg);
+        return;
+    }
+    eprint!("\r\x1b[K{}", msg);
+    io::stderr().flush().ok();
+}
+
+/// Move up one line, clear it, and print status
+pub fn status_up(msg: &str) {
+    let mut stages = STAGES.lock().unwrap();
+    stages.clear();
+    PREVIEW.lock().unwrap().clear();
+    if is_plain() {
+        print_line(msg);
+        return;
+    }
+    eprint!("\x1b[A\r\x1b[K{}", msg);
+    io::stderr().flush().ok();
+}
+
+/// Status without the blank lines and partial lines a terminal needs
+fn print_line(msg: &str) {
+    if crate::porcelain::enabled() {
+        crate::porcelain::status(msg);
+        return;
+    }
+    let msg = msg.trim();
+    if !msg.is_empty() {
+        eprintln!("{}", msg);
+    }
+}
+
+/// Start a processing stage
+///
+/// The status line lists the stages so far with how long each took, and
+/// the current one with its running time, e.g.
+/// `Transcribing 1.2 MB 2.1s · Correcting 0.4s...`
+pub fn stage(name: &str) {
+    let mut stages = STAGES.lock().unwrap();
+    stages.push((name.to_string(), Instant::now()));
+    PREVIEW.lock().unwrap().clear();
+    if is_plain() {
+        print_line(&format!("{}...", name));
+        return;
+    }
+    render(&stages);
+}
+
+/// Show the end of what the current stage has produced so far after it
+pub fn preview(text: &str) {
+    let stages = STAGES.lock().unwrap();
+    *PREVIEW.lock().unwrap() = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !stages.is_empty() && !is_plain() {
+        render(&stages);
+    }
+}
+
+/// Refresh the running time of the current stage until the process exits
+pub fn spawn_ticker() {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(TICK);
+        loop {
+            interval.tick().await;
+            let stages = STAGES.lock().unwrap();
+            if !stages.is_empty() && !is_plain() {
+                render(&stages);
+            }
+        }
+    });
+}
+
+fn render(stages: &[(String, Instant)]) {
+    let mut parts: Vec<String> = stages
+        .windows(2)
+        .map(|pair| {
+            let elapsed = pair[1].1.duration_since(pair[0].1);
+            format!("{} {:.1}s", pair[0].0, elapsed.as_secs_f32())
+        })
+        .collect();
+    if let Some((name, started)) = stages.last() {
+        parts.push(format!(
+            "{} {:.1}s...",
+            name,
+            started.elapsed().as_secs_f32()
+        ));
+    }
+
+    let preview = PREVIEW.lock().unwrap();
+    let skip = preview.chars().count().saturating_sub(PREVIEW_CHARS);
+    if skip > 0 {
+        parts.push(format!(
+            "\x1b[90m…{}\x1b[0m",
+            preview.chars().skip(skip).collect::<String>()
+        ));
+    } else if !preview.is_empty() {
+        parts.push(format!("\x1b[90m{}\x1b[0m", preview));
+    }
+
+    eprint!("\r\x1b[K{}", parts.join(" · "));
+    io::stderr().flush().ok();
+}
+
+/// Size of a payload for the status line
+pub fn megabytes(len: usize) -> String {
+    format!("{:.1} MB", len as f64 / 1_000_000.0)
+}
@@ -0,0 +1,214 @@
+//! Push-to-talk (--ptt): record while a key is held
+//!
+//! Terminals report key presses but not releases. A held key repeats,
+//! though, so the key counts as released once its repeats stop: after the
+//! keyboard's repeat delay for the first one, then after a short gap. The
+//! audio recorded while waiting for the next repeat is cut off.
+//!
+//! Without --ptt, the same raw terminal gives the recording its controls:
+//! Space pauses and resumes, Enter finishes, and `h`, `/` and `w` browse
+//! history and add words (see browse.rs).
+
+use std::io::IsTerminal;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Longest wait for the first repeat, above the usual repeat delays (250-660 ms)
+const FIRST_REPEAT: Duration = Duration::from_millis(700);
+
+/// Longest wait between repeats, well above the usual repeat rates (25-30 per second)
+const NEXT_REPEAT: Duration = Duration::from_millis(150);
+
+/// Audio kept after the last repeat, as the key was still down for a moment
+const RELEASE_MARGIN: Duration = Duration::from_millis(50);
+
+/// Ctrl+C, read as a byte since the terminal doesn't turn it into a signal
+pub const CTRL_C: u8 = 3;
+
+/// How often the reading thread checks whether it should stop
+const POLL_MS: i32 = 50;
+
+/// Key given to --ptt: a character, or 'space'
+pub fn parse_key(name: &str) -> Result<u8, String> {
+    match name {
+        "space" => Ok(b' '),
+        _ if name.len() == 1 && name.as_bytes()[0].is_ascii_graphic() => Ok(name.as_bytes()[0]),
+        _ => Err(format!(
+            "Invalid key {}: a letter, digit or symbol, or 'space'",
+            name
+        )),
+    }
+}
+
+/// Name of a key for the status line
+pub fn key_name(key: u8) -> String {
+    match key {
+        b' ' => "Space".to_string(),
+        _ => (key as char).to_string(),
+    }
+}
+
+/// Key presses from the terminal, with the terminal in raw mode until dropped
+pub struct Keys {
+    key: u8,
+    presses: mpsc::UnboundedReceiver<(u8, Instant)>,
+    stop: Arc<AtomicBool>,
+    /// Audio recorded after the key was let go, to cut off
+    overshoot: Duration,
+    _raw: RawMode,
+}
+
+impl Keys {
+    /// Start reading presses of `key` from stdin
+    pub fn listen(key: u8) -> Result<Self, Box<dyn std::error::Error>> {
+        if !std::io::stdin().is_terminal() {
+            return Err("--ptt needs a terminal".into());
+        }
+        let raw = RawMode::enable()?;
+        let (tx, presses) = mpsc::unbounded_channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        std::thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                if let Some(byte) = read_byte()
+                    && tx.send((byte, Instant::now())).is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            key,
+            presses,
+            stop,
+            overshoot: Duration::ZERO,
+            _raw: raw,
+        })
+    }
+
+    /// Keys to pause and stop a recording with, when stdin is a terminal
+    /// that can be put in raw mode
+    pub fn controls() -> Option<Self> {
+        Self::listen(b' ').ok()
+    }
+
+    /// Wait for the key to be pressed; false on Ctrl+C
+    pub async fn pressed(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        loop {
+            match self.presses.recv().await {
+                Some((CTRL_C, _)) => return Ok(false),
+                Some((byte, _)) if byte == self.key => return Ok(true),
+                Some(_) => {}
+                None => return Err("Stopped reading the terminal".into()),
+            }
+        }
+    }
+
+    /// Wait for any key, Ctrl+C included
+    pub async fn next(&mut self) -> Result<u8, Box<dyn std::error::Error>> {
+        match self.presses.recv().await {
+            Some((byte, _)) => Ok(byte),
+            None => Err("Stopped reading the terminal".into()),
+        }
+    }
+
+    /// Wait for the key to be let go; false on Ctrl+C
+    pub async fn released(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut last = Instant::now();
+        let mut wait = FIRST_REPEAT;
+        loop {
+            match tokio::time::timeout_at((last + wait).into(), self.presses.recv()).await {
+                Ok(Some((CTRL_C, _))) => return Ok(false),
+                Ok(Some((byte, at))) if byte == self.key => {
+                    last = at;
+                    wait = NEXT_REPEAT;
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => return Err("Stopped reading the terminal".into()),
+                Err(_) => {
+                    self.overshoot = last.elapsed().saturating_sub(RELEASE_MARGIN);
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    /// Drop the samples captured after the key was let go
+    pub fn cut(&self, mut samples: Vec<f32>, sample_rate: u32, channels: u16) -> Vec<f32> {
+        let frames = (self.overshoot.as_secs_f32() * sample_rate as f32) as usize;
+        let len = samples.len().saturating_sub(frames * channels as usize);
+        samples.truncate(len);
+        samples
+    }
+}
+
+impl Drop for Keys {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A byte from stdin, or `None` when none came within `POLL_MS`
+#[cfg(unix)]
+fn read_byte() -> Option<u8> {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    if unsafe { libc::poll(&mut fd, 1, POLL_MS) } <= 0 {
+        return None;
+    }
+    let mut byte = 0u8;
+    let read = unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) };
+    (read == 1).then_some(byte)
+}
+
+#[cfg(not(unix))]
+fn read_byte() -> Option<u8> {
+    None
+}
+
+/// Terminal reading single unechoed keys, restored when dropped
+///
+/// Signals are off too, so that Ctrl+C cancels the recording and the
+/// terminal is restored rather than left without echo.
+struct RawMode {
+    #[cfg(unix)]
+    saved: libc::termios,
+}
+
+impl RawMode {
+    #[cfg(unix)]
+    fn enable() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Self { saved })
+    }
+
+    #[cfg(not(unix))]
+    fn enable() -> Result<Self, Box<dyn std::error::Error>> {
+        Err("--ptt is only supported on Unix terminals".into())
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+    }
+}
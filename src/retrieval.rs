@@ -0,0 +1,338 @@
+//! Names from the user's notes for correction (`retrieval` in config)
+//!
+//! Project names rarely make it into `custom_words`, but they are all over
+//! the user's notes. The notes directory is searched for name-like terms
+//! (`OpenWorkers`, `pg_dump`, `Kubernetes` mid-sentence), and those that
+//! sound like words of the transcription are shown to Claude with the line
+//! they were found on, like a ripgrep match.
+//!
+//! Sounding alike is judged on a phonetic key: consonants grouped into
+//! classes of similar sounds, vowels dropped. "open workers" and
+//! "OpenWorkers" share a key, as do "box trail" and "Voxtral". Words spelled
+//! exactly like a term need no help and are skipped.
+//!
+//! When the backend scores its segments, only those below
+//! `confidence_threshold` are looked up: the rest was heard clearly. The
+//! notes are read once per process, on the first correction.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+/// Notes larger than this are skipped (exports, logs)
+const MAX_FILE_BYTES: u64 = 1 << 20;
+
+/// Files read at most, so a huge directory doesn't stall correction
+const MAX_FILES: usize = 5000;
+
+/// Shortest phonetic key matched, as shorter ones match too many words
+const MIN_KEY: usize = 3;
+
+/// Consecutive transcription words tried together as one term
+const MAX_WORDS: usize = 3;
+
+/// Characters of the matching line shown with a term
+const SNIPPET_CHARS: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetrievalConfig {
+    /// Directory of notes searched recursively (e.g. '~/notes'); unset disables retrieval
+    #[serde(default)]
+    pub notes_dir: Option<PathBuf>,
+    /// Extensions of the files read
+    #[serde(default = "default_extensions")]
+    pub extensions: Vec<String>,
+    /// Most terms added to a correction prompt
+    #[serde(default = "default_max_terms")]
+    pub max_terms: usize,
+}
+
+impl Default for RetrievalConfig {
+    fn default() -> Self {
+        Self {
+            notes_dir: None,
+            extensions: default_extensions(),
+            max_terms: default_max_terms(),
+        }
+    }
+}
+
+fn default_extensions() -> Vec<String> {
+    ["md", "txt", "org", "rst"].map(String::from).to_vec()
+}
+
+fn default_max_terms() -> usize {
+    10
+}
+
+/// Name-like term found in the notes
+#[derive(Debug)]
+struct Term {
+    text: String,
+    key: String,
+    /// Times seen, to prefer the names the user writes most
+    count: usize,
+    /// First line it was seen on, as `path:line: text`
+    source: String,
+}
+
+/// Terms of the notes directory, indexed by phonetic key
+#[derive(Debug, Default)]
+pub struct Notes {
+    terms: Vec<Term>,
+    by_key: HashMap<String, Vec<usize>>,
+    max_terms: usize,
+}
+
+impl Notes {
+    /// The notes directory, read on the first call and kept for the process
+    pub fn shared(config: &RetrievalConfig) -> Option<Arc<Self>> {
+        static NOTES: OnceLock<Option<Arc<Notes>>> = OnceLock::new();
+        NOTES
+            .get_or_init(|| {
+                let load = || Self::load(config).map(Arc::new);
+                // Thousands of files shouldn't hold up the runtime's other tasks
+                match tokio::runtime::Handle::try_current() {
+                    Ok(runtime)
+                        if runtime.runtime_flavor()
+                            == tokio::runtime::RuntimeFlavor::MultiThread =>
+                    {
+                        tokio::task::block_in_place(load)
+                    }
+                    _ => load(),
+                }
+            })
+            .clone()
+    }
+
+    /// Read the notes directory, if one is configured
+    fn load(config: &RetrievalConfig) -> Option<Self> {
+        let dir = expand_home(config.notes_dir.as_ref()?);
+        if !dir.is_dir() {
+            eprintln!(
+                "Warning: Notes directory {} not found, correcting without it",
+                dir.display()
+            );
+            return None;
+        }
+        let mut files = Vec::new();
+        walk(&dir, &config.extensions, &mut files);
+        if files.len() >= MAX_FILES {
+            eprintln!(
+                "Warning: Only the first {} files of {} are searched for names",
+                MAX_FILES,
+                dir.display()
+            );
+        }
+
+        let mut notes = Notes {
+            max_terms: config.max_terms,
+            ..Default::default()
+        };
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for path in &files {
+            let Ok(content) = std::fs::read_to_string(path) else {
+                continue;
+            };
+            let name = path.strip_prefix(&dir).unwrap_or(path).display();
+            for (number, line) in content.lines().enumerate() {
+                for term in terms(line) {
+                    let key = phonetic_key(term);
+                    if key.len() < MIN_KEY {
+                        continue;
+                    }
+                    match index.get(term) {
+                        Some(&i) => notes.terms[i].count += 1,
+                        None => {
+                            index.insert(term.to_string(), notes.terms.len());
+                            notes
+                                .by_key
+                                .entry(key.clone())
+                                .or_default()
+                                .push(notes.terms.len());
+                            notes.terms.push(Term {
+                                text: term.to_string(),
+                                key,
+                                count: 1,
+                                source: format!("{}:{}: {}", name, number + 1, snippet(line)),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        Some(notes)
+    }
+
+    /// Terms sounding like words of `text`, most frequent first, as prompt lines
+    pub fn matches(&self, text: &str) -> Vec<String> {
+        let words: Vec<&str> = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .collect();
+
+        let mut found: Vec<usize> = Vec::new();
+        for start in 0..words.len() {
+            for len in 1..=MAX_WORDS.min(words.len() - start) {
+                let phrase = words[start..start + len].concat();
+                let key = phonetic_key(&phrase);
+                if key.len() < MIN_KEY {
+                    continue;
+                }
+                for i in self.similar(&key) {
+                    if !found.contains(&i) {
+                        found.push(i);
+                    }
+                }
+            }
+        }
+
+        // Terms spelled exactly as in the text are not worth mentioning
+        found.retain(|&i| !words.contains(&self.terms[i].text.as_str()));
+        found.sort_by_key(|&i| std::cmp::Reverse(self.terms[i].count));
+        found
+            .into_iter()
+            .take(self.max_terms)
+            .map(|i| format!("- {} (in {})", self.terms[i].text, self.terms[i].source))
+            .collect()
+    }
+
+    /// Terms with the same key, or one sound off for long keys
+    fn similar(&self, key: &str) -> Vec<usize> {
+        let mut similar = self.by_key.get(key).cloned().unwrap_or_default();
+        if key.len() >= 5 {
+            similar.extend(
+                self.terms
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, t)| t.key != key && t.key.len() >= 5)
+                    .filter(|(_, t)| {
+                        crate::text::levenshtein(t.key.as_bytes(), key.as_bytes()) <= 1
+                    })
+                    .map(|(i, _)| i),
+            );
+        }
+        similar
+    }
+}
+
+/// `~/notes` as a path under the home directory
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Note files under `dir`, skipping hidden entries (.git, .obsidian...)
+fn walk(dir: &Path, extensions: &[String], files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries.filter_map(|e| e.ok()).collect();
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        if files.len() >= MAX_FILES {
+            return;
+        }
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if meta.is_dir() {
+            walk(&path, extensions, files);
+        } else if meta.len() <= MAX_FILE_BYTES
+            && path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+        {
+            files.push(path);
+        }
+    }
+}
+
+/// Name-like words of a line of notes
+///
+/// Mixed case (`OpenWorkers`, `iOS`), digits (`S3`), inner `-` or `_`
+/// (`pg_dump`), or a capital mid-sentence (a proper noun rather than the
+/// first word of a sentence).
+fn terms(line: &str) -> Vec<&str> {
+    let mut terms = Vec::new();
+    let mut sentence_start = true;
+    let mut rest = line;
+
+    while let Some(start) = rest.find(|c: char| c.is_alphanumeric()) {
+        // Markdown list marks and headings don't end sentences
+        let before = rest[..start].trim();
+        if before.ends_with(['.', '!', '?', ':']) {
+            sentence_start = true;
+        }
+        let word_len = rest[start..]
+            .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or(rest.len() - start);
+        let word = rest[start..start + word_len].trim_end_matches(['-', '_']);
+        rest = &rest[start + word_len..];
+
+        let mut chars = word.chars();
+        let first_upper = chars.next().is_some_and(char::is_uppercase);
+        let inner_upper = chars.clone().any(char::is_uppercase);
+        let has_digit = word.chars().any(|c| c.is_ascii_digit());
+        let has_letter = word.chars().any(char::is_alphabetic);
+        let joined = word.contains(['-', '_']);
+        let name = has_letter
+            && word.chars().count() >= 3
+            && (inner_upper || has_digit || joined || (first_upper && !sentence_start));
+        if name {
+            terms.push(word);
+        }
+        sentence_start = false;
+    }
+    terms
+}
+
+/// Consonant classes of a word, vowels dropped and repeats merged
+///
+/// A leading vowel is kept as 'A' so "Ubuntu" and "bento" differ. Digits
+/// are kept as they are.
+fn phonetic_key(word: &str) -> String {
+    let mut key = String::new();
+    let mut last = None;
+    for (i, c) in word.to_lowercase().chars().enumerate() {
+        let class = match c {
+            'b' | 'f' | 'p' | 'v' => Some('P'),
+            'c' | 'g' | 'j' | 'k' | 'q' | 's' | 'x' | 'z' | 'ç' => Some('K'),
+            'd' | 't' => Some('T'),
+            'l' => Some('L'),
+            'm' | 'n' => Some('N'),
+            'r' => Some('R'),
+            '0'..='9' => Some(c),
+            _ if i == 0 && c.is_alphabetic() && "aeiouàâéèêëîïôùûü".contains(c) => {
+                Some('A')
+            }
+            _ => None,
+        };
+        if let Some(class) = class
+            && last != Some(class)
+        {
+            key.push(class);
+        }
+        // A vowel between two identical consonants keeps them apart
+        last = class.or(last.filter(|_| !"aeiouy".contains(c)));
+    }
+    key
+}
+
+/// Start of a line of notes, trimmed for the prompt
+fn snippet(line: &str) -> String {
+    let line = line.trim();
+    match line.char_indices().nth(SNIPPET_CHARS) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    }
+}
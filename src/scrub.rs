@@ -0,0 +1,146 @@
+//! Secrets masked before text goes to Claude (`scrub` in config)
+//!
+//! A dictation may spell out a token, and a transcribed screen recording may
+//! hold a key shown on screen. Before correction, translation, a template,
+//! chapters or `rec ask`, anything shaped like a secret is replaced by a
+//! placeholder such as `[SECRET_1]`, and put back in Claude's answer, so the
+//! secret itself never leaves the machine for the corrector.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Shapes of well-known secrets
+const BUILTIN_PATTERNS: &[&str] = &[
+    // JSON Web Tokens: base64url header, payload and signature
+    r"\beyJ[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}",
+    // AWS access key IDs, long-term and temporary
+    r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b",
+    // AWS secret keys, when named
+    r"(?i)aws_secret_access_key\s*[=:]\s*[A-Za-z0-9/+=]{40}",
+    // Prefixed API keys: OpenAI and Anthropic (sk-), Stripe, GitHub, Slack, Google
+    r"\b(?:sk|pk|rk)-[A-Za-z0-9_-]{16,}",
+    r"\b(?:sk|pk|rk)_(?:live|test)_[A-Za-z0-9]{16,}",
+    r"\bgh[pousr]_[A-Za-z0-9]{30,}",
+    r"\bxox[abprs]-[A-Za-z0-9-]{10,}",
+    r"\bAIza[0-9A-Za-z_-]{35}",
+    // PEM private keys
+    r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScrubConfig {
+    /// Mask secrets before Claude sees the text
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// More secret shapes as regular expressions, e.g. "\\bACME-[0-9a-f]{32}\\b"
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+impl Default for ScrubConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            patterns: vec![],
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+pub struct Scrubber {
+    patterns: Vec<Regex>,
+}
+
+/// Text with its secrets replaced by placeholders
+pub struct Masked {
+    pub text: String,
+    /// Secret of each placeholder, in order: `[SECRET_1]` is the first
+    secrets: Vec<String>,
+}
+
+impl Scrubber {
+    /// The built-in patterns and those of config, or `None` when disabled
+    pub fn new(config: &ScrubConfig) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let mut patterns = Vec::new();
+        for pattern in BUILTIN_PATTERNS
+            .iter()
+            .copied()
+            .chain(config.patterns.iter().map(String::as_str))
+        {
+            patterns.push(
+                Regex::new(pattern)
+                    .map_err(|e| format!("Invalid scrub pattern {}: {}", pattern, e))?,
+            );
+        }
+        Ok(Some(Self { patterns }))
+    }
+
+    /// Replace every secret with a placeholder, the same one for repeats
+    pub fn mask(&self, text: &str) -> Masked {
+        let mut secrets: Vec<String> = Vec::new();
+        let mut text = text.to_string();
+        for pattern in &self.patterns {
+            text = pattern
+                .replace_all(&text, |found: &regex::Captures| {
+                    let secret = &found[0];
+                    let number = match secrets.iter().position(|s| s == secret) {
+                        Some(i) => i + 1,
+                        None => {
+                            secrets.push(secret.to_string());
+                            secrets.len()
+                        }
+                    };
+                    placeholder(number)
+                })
+                .into_owned();
+        }
+        Masked { text, secrets }
+    }
+}
+
+impl Masked {
+    /// Text as it is, for when scrubbing is disabled
+    pub fn unchanged(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            secrets: vec![],
+        }
+    }
+
+    /// Whether every placeholder is still in `text`
+    pub fn kept_in(&self, text: &str) -> bool {
+        (1..=self.secrets.len()).all(|number| text.contains(&placeholder(number)))
+    }
+
+    /// Another piece of the same text (e.g. a part of it) with its secrets
+    /// masked by the same placeholders
+    pub fn hide(&self, text: &str) -> String {
+        let mut secrets: Vec<_> = self.secrets.iter().enumerate().collect();
+        // Longest first, so a secret containing another is masked whole
+        secrets.sort_by_key(|(_, secret)| std::cmp::Reverse(secret.len()));
+        let mut text = text.to_string();
+        for (i, secret) in secrets {
+            text = text.replace(secret.as_str(), &placeholder(i + 1));
+        }
+        text
+    }
+
+    /// Put the secrets back in text made from the masked one
+    pub fn restore(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (i, secret) in self.secrets.iter().enumerate() {
+            text = text.replace(&placeholder(i + 1), secret);
+        }
+        text
+    }
+}
+
+fn placeholder(number: usize) -> String {
+    format!("[SECRET_{}]", number)
+}
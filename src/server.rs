@@ -0,0 +1,558 @@
+//! HTTP server exposing dictation to other programs (`rec serve`)
+
+use crate::backend::Transcript;
+use crate::config::{Config, Scope, ServerConfig};
+use crate::grpc;
+use crate::live::Feed;
+use crate::metrics::Metrics;
+use crate::{Pipeline, audio, limits, segments_json, systemd};
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code};
+use axum::extract::{DefaultBodyLimit, FromRequest, Multipart, Query, Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Largest audio file accepted, enough for hours of WAV
+pub const MAX_UPLOAD: usize = 1 << 30;
+
+/// Page turning a browser into a remote microphone
+const MIC_PAGE: &str = include_str!("mic.html");
+
+/// Page following the session's transcript
+const LIVE_PAGE: &str = include_str!("live.html");
+
+pub struct Server {
+    pub pipeline: Pipeline,
+    /// Input device recorded from by the gRPC `Start` (--device)
+    pub device: Option<String>,
+    metrics: Metrics,
+    /// Notes file every result is appended to (-o)
+    output: Option<PathBuf>,
+    /// Transcript shown at `/live`
+    live: Feed,
+}
+
+/// Serve until the process is stopped
+///
+/// - `POST /transcribe` with an audio file as body returns `{"text", "segments"}`
+/// - `POST /upload` with an audio file as body or multipart `file` field
+///   answers right away and processes it in the background
+/// - `GET /mic` is a web page streaming the browser's microphone to `/mic/ws`
+/// - `GET /live` is a web page following the transcript of the session as
+///   dictations finish, through server-sent events from `/live/events`
+/// - `GET /history?n=10` returns the latest history entries
+/// - `GET /metrics` returns Prometheus metrics
+/// - `GET /health` returns `ok`
+///
+/// With `grpc`, the gRPC API described in proto/rec.proto is served there too.
+///
+/// When tokens are configured every endpoint but `/health` needs a bearer
+/// token with the matching scope; without tokens the server only listens on
+/// loopback addresses, and only answers requests addressed to localhost.
+///
+/// Under systemd (`--systemd`), a socket passed by a `.socket` unit is used
+/// instead of `listen`, and readiness is notified once listening.
+pub async fn serve(
+    pipeline: Pipeline,
+    listen: &str,
+    grpc: Option<&str>,
+    output: Option<PathBuf>,
+    device: Option<String>,
+    under_systemd: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let settings = &pipeline.config.server;
+    let activated = match under_systemd {
+        true => systemd::listener()?,
+        false => None,
+    };
+    let listener = match activated {
+        Some(listener) => tokio::net::TcpListener::from_std(listener)?,
+        None => tokio::net::TcpListener::bind(listen).await?,
+    };
+    let addr = listener.local_addr()?;
+    check_exposure(settings, addr)?;
+    let grpc_listener = match grpc {
+        Some(grpc) => {
+            let listener = tokio::net::TcpListener::bind(grpc).await?;
+            check_exposure(settings, listener.local_addr()?)?;
+            Some(listener)
+        }
+        None => None,
+    };
+
+    let live = Feed::new(pipeline.session.as_deref());
+    let server = Arc::new(Server {
+        pipeline,
+        device,
+        metrics: Metrics::default(),
+        output,
+        live,
+    });
+
+    let app = Router::new()
+        .route("/transcribe", post(transcribe))
+        .route("/upload", post(upload))
+        .route("/mic", get(|| async { Html(MIC_PAGE) }))
+        .route("/mic/ws", get(mic_socket))
+        .route("/live", get(|| async { Html(LIVE_PAGE) }))
+        .route("/live/events", get(live_events))
+        .route("/history", get(history))
+        .route("/metrics", get(metrics))
+        .route("/health", get(|| async { "ok" }))
+        .layer(DefaultBodyLimit::max(MAX_UPLOAD))
+        .with_state(server.clone());
+    // Tokens keep out pages from other sites; without them the Host is checked
+    // (the gRPC API speaks only cleartext HTTP/2, which browsers don't)
+    let port = addr.port();
+    let app = match server.pipeline.config.server.tokens.is_empty() {
+        true => app.layer(middleware::from_fn(move |request: Request, next: Next| {
+            require_loopback_host(port, request, next)
+        })),
+        false => app,
+    };
+
+    eprintln!("Listening on http://{}", addr);
+    if let Some(listener) = &grpc_listener {
+        eprintln!("Serving gRPC on {}", listener.local_addr()?);
+    }
+    if under_systemd {
+        systemd::notify(&format!("READY=1\nSTATUS=Listening on http://{}", addr))?;
+    }
+    match grpc_listener {
+        Some(grpc_listener) => tokio::select! {
+            served = axum::serve(listener, app) => served?,
+            served = grpc::serve(server, grpc_listener) => served?,
+        },
+        None => axum::serve(listener, app).await?,
+    }
+    Ok(())
+}
+
+/// Refuse to listen beyond loopback without protection
+fn check_exposure(
+    settings: &ServerConfig,
+    addr: std::net::SocketAddr,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if addr.ip().is_loopback() {
+        return Ok(());
+    }
+    if settings.localhost_only {
+        return Err(format!("Refusing to listen on {}: localhost_only is set", addr).into());
+    }
+    if settings.tokens.is_empty() {
+        return Err(format!(
+            "Refusing to listen on {} without tokens: add server.tokens to config",
+            addr
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Host names of this machine, the only ones accepted without tokens
+const LOOPBACK_HOSTS: &[&str] = &["localhost", "127.0.0.1", "[::1]"];
+
+/// Refuse requests naming the server by anything but a loopback host and its port
+///
+/// A page from any site can reach a server on localhost through DNS
+/// rebinding: its own host name then resolves to 127.0.0.1, and the browser
+/// treats the server as that site, Origin included. Only the Host header
+/// gives it away.
+async fn require_loopback_host(port: u16, request: Request, next: Next) -> Response {
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| {
+            request
+                .uri()
+                .authority()
+                .map(|authority| authority.as_str())
+        });
+    // Browsers always send one; other clients can't be rebound
+    let Some(host) = host else {
+        return next.run(request).await;
+    };
+    let (name, given_port) = match host.rsplit_once(':') {
+        Some((name, given_port)) if !given_port.contains(']') => (name, Some(given_port)),
+        _ => (host, None),
+    };
+    let loopback = LOOPBACK_HOSTS
+        .iter()
+        .any(|loopback| loopback.eq_ignore_ascii_case(name))
+        && given_port.is_none_or(|given_port| given_port == port.to_string());
+    if !loopback {
+        return (
+            StatusCode::FORBIDDEN,
+            "Without server.tokens in config, only localhost, 127.0.0.1 and [::1] are accepted as Host",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// Check the request's bearer token against the configured tokens
+fn authorize(
+    server: &Server,
+    headers: &HeaderMap,
+    scope: Scope,
+) -> Result<(), (StatusCode, &'static str)> {
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or("");
+    check_token(server, presented, scope)
+}
+
+/// Check a token against the configured tokens
+pub fn check_token(
+    server: &Server,
+    presented: &str,
+    scope: Scope,
+) -> Result<(), (StatusCode, &'static str)> {
+    let tokens = &server.pipeline.config.server.tokens;
+    if tokens.is_empty() {
+        return Ok(());
+    }
+
+    match tokens
+        .iter()
+        .find(|t| constant_time_eq(&t.token, presented))
+    {
+        Some(token) if token.scopes.contains(&scope) => Ok(()),
+        Some(_) => Err((StatusCode::FORBIDDEN, "Token lacks the required scope")),
+        None => Err((StatusCode::UNAUTHORIZED, "Missing or unknown token")),
+    }
+}
+
+/// Compare secrets without leaking where they differ through timing
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+async fn transcribe(
+    State(server): State<Arc<Server>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Err(denied) = authorize(&server, &headers, Scope::Transcribe) {
+        return denied.into_response();
+    }
+
+    match dictate(&server, body.to_vec(), true).await {
+        Ok(transcript) => axum::Json(result_json(&server, &transcript)).into_response(),
+        Err(e) => {
+            let status = if e == audio::MUTED {
+                StatusCode::UNPROCESSABLE_ENTITY
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            };
+            (status, axum::Json(serde_json::json!({ "error": e }))).into_response()
+        }
+    }
+}
+
+/// Accept an audio file from a phone shortcut or script
+///
+/// The result goes to history (see `/history`) and the notes file, so the
+/// client doesn't have to wait for transcription and correction.
+async fn upload(State(server): State<Arc<Server>>, request: Request) -> Response {
+    if let Err(denied) = authorize(&server, request.headers(), Scope::Transcribe) {
+        return denied.into_response();
+    }
+
+    let is_multipart = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("multipart/form-data"));
+
+    let audio = if is_multipart {
+        multipart_file(request).await
+    } else {
+        Bytes::from_request(request, &())
+            .await
+            .map_err(|e| e.to_string())
+    };
+
+    let audio = match audio {
+        Ok(audio) if !audio.is_empty() => audio,
+        Ok(_) => return (StatusCode::BAD_REQUEST, "No audio").into_response(),
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    tokio::spawn(async move {
+        let _ = dictate(&server, audio.to_vec(), false).await;
+    });
+
+    (
+        StatusCode::ACCEPTED,
+        axum::Json(serde_json::json!({ "status": "accepted" })),
+    )
+        .into_response()
+}
+
+/// Contents of the `file` field of a multipart upload
+async fn multipart_file(request: Request) -> Result<Bytes, String> {
+    let mut multipart = Multipart::from_request(request, &())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| e.to_string())? {
+        if field.name() == Some("file") {
+            return field.bytes().await.map_err(|e| e.to_string());
+        }
+    }
+    Err("Missing 'file' field".to_string())
+}
+
+/// Run a dictation through the pipeline, recording metrics and notes
+///
+/// `focused` applies the focused app's profile (`app_profiles`), for requests
+/// from this machine rather than a phone or browser elsewhere.
+///
+/// Audio that is digital silence, from a muted microphone or one blocked by
+/// the OS privacy setting, is refused before reaching the backend.
+pub async fn dictate(
+    server: &Server,
+    wav_data: Vec<u8>,
+    focused: bool,
+) -> Result<Transcript, String> {
+    if audio::wav_is_digital_silence(&wav_data) {
+        systemd::error(audio::MUTED);
+        return Err(audio::MUTED.to_string());
+    }
+
+    let backend = server.pipeline.backend.name().to_string();
+    let started = Instant::now();
+
+    let result = if focused {
+        server.pipeline.run_focused(wav_data).await
+    } else {
+        server.pipeline.run(wav_data).await
+    };
+    let result = result.map_err(|e| e.to_string());
+
+    match result {
+        Ok(transcript) => {
+            server.metrics.dictation(&backend, started.elapsed());
+            server.live.push(&transcript.text);
+            if let Some(path) = &server.output
+                && let Err(e) = append_note(path, &transcript.text)
+            {
+                systemd::warning(format!("Failed to append to {}: {}", path.display(), e));
+            }
+            Ok(transcript)
+        }
+        Err(e) => {
+            server.metrics.error(&backend);
+            systemd::error(&e);
+            Err(e)
+        }
+    }
+}
+
+/// `{"text", "segments", "meta"}` answered by the HTTP API
+fn result_json(server: &Server, transcript: &Transcript) -> serde_json::Value {
+    let threshold = server.pipeline.config.confidence_threshold;
+    server.pipeline.with_meta(serde_json::json!({
+        "text": transcript.text,
+        "segments": segments_json(transcript, threshold),
+    }))
+}
+
+fn append_note(path: &PathBuf, text: &str) -> std::io::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", text)
+}
+
+#[derive(serde::Deserialize)]
+struct TokenQuery {
+    #[serde(default)]
+    token: String,
+}
+
+/// First message of a remote microphone stream
+#[derive(serde::Deserialize)]
+struct MicStart {
+    sample_rate: u32,
+}
+
+/// Browsers can't set headers on WebSockets, so the token comes in the query
+async fn mic_socket(
+    State(server): State<Arc<Server>>,
+    Query(query): Query<TokenQuery>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    if !same_origin(&headers) {
+        return (
+            StatusCode::FORBIDDEN,
+            "WebSocket from another site's page refused",
+        )
+            .into_response();
+    }
+    if let Err(denied) = check_token(&server, &query.token, Scope::Transcribe) {
+        return denied.into_response();
+    }
+    ws.on_upgrade(move |socket| remote_mic(server, socket))
+}
+
+/// Whether a WebSocket upgrade comes from a page of this server, or from a
+/// client other than a browser (which sends no Origin)
+///
+/// WebSockets aren't bound by the same-origin policy: without this, any page
+/// open in the browser could stream audio to a server on localhost.
+fn same_origin(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return true;
+    };
+    let origin = origin
+        .to_str()
+        .ok()
+        .and_then(|origin| origin.split_once("://"));
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    match (origin, host) {
+        (Some((_, authority)), Some(host)) => authority.eq_ignore_ascii_case(host),
+        _ => false,
+    }
+}
+
+/// Receive `{"sample_rate": N}`, then 16-bit mono PCM frames until `stop`,
+/// and answer with the transcription
+///
+/// Like a recording, the audio stops at the memory limits: `{"stopped"}`
+/// says why, the audio so far is transcribed and the socket closed.
+async fn remote_mic(server: Arc<Server>, mut socket: WebSocket) {
+    let mut sample_rate = None;
+    let mut samples: Vec<f32> = Vec::new();
+    let mut check = tokio::time::interval(crate::LIMITS_INTERVAL);
+    let mut stopped = None;
+
+    loop {
+        let message = tokio::select! {
+            message = socket.recv() => message,
+            _ = check.tick() => {
+                let buffered = samples.len() * std::mem::size_of::<f32>();
+                stopped = limits::capture_exceeded(&server.pipeline.config.limits, buffered);
+                if stopped.is_some() {
+                    break;
+                }
+                continue;
+            }
+        };
+        let Some(Ok(message)) = message else {
+            break;
+        };
+        match message {
+            Message::Binary(data) => samples.extend(
+                data.chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0),
+            ),
+            Message::Text(text) if text.as_str() == "stop" => break,
+            Message::Text(text) => {
+                if let Ok(start) = serde_json::from_str::<MicStart>(&text) {
+                    sample_rate = Some(start.sample_rate);
+                }
+            }
+            Message::Close(_) => return,
+            _ => {}
+        }
+    }
+
+    if let Some(reason) = &stopped {
+        eprintln!("Warning: Remote microphone: {}", reason);
+        let notice = serde_json::json!({ "stopped": reason }).to_string();
+        let _ = socket.send(Message::Text(notice.into())).await;
+    }
+
+    let wav_data = match sample_rate {
+        _ if samples.is_empty() => Err("No audio".to_string()),
+        None => Err("Missing sample rate".to_string()),
+        Some(rate) => audio::encode_wav(&samples, rate, 1).map_err(|e| e.to_string()),
+    };
+    let result = match wav_data {
+        Ok(wav_data) => dictate(&server, wav_data, false)
+            .await
+            .map(|transcript| result_json(&server, &transcript)),
+        Err(e) => Err(e),
+    };
+
+    let reply = result.unwrap_or_else(|e| serde_json::json!({ "error": e }));
+    let _ = socket.send(Message::Text(reply.to_string().into())).await;
+    if let Some(reason) = stopped {
+        let close = CloseFrame {
+            code: close_code::SIZE,
+            reason: reason.into(),
+        };
+        let _ = socket.send(Message::Close(Some(close))).await;
+    }
+}
+
+/// Like history, the transcript needs the history scope; EventSource can't
+/// set headers either, so the token comes in the query
+async fn live_events(
+    State(server): State<Arc<Server>>,
+    Query(query): Query<TokenQuery>,
+) -> Response {
+    if let Err(denied) = check_token(&server, &query.token, Scope::History) {
+        return denied.into_response();
+    }
+    server.live.events().into_response()
+}
+
+#[derive(serde::Deserialize)]
+struct HistoryQuery {
+    n: Option<usize>,
+}
+
+async fn history(
+    State(server): State<Arc<Server>>,
+    headers: HeaderMap,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    if let Err(denied) = authorize(&server, &headers, Scope::History) {
+        return denied.into_response();
+    }
+
+    match Config::load_history() {
+        Ok(history) => {
+            let start = history.len().saturating_sub(query.n.unwrap_or(10));
+            axum::Json(&history[start..]).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            axum::Json(serde_json::json!({ "error": e.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+async fn metrics(State(server): State<Arc<Server>>, headers: HeaderMap) -> Response {
+    if let Err(denied) = authorize(&server, &headers, Scope::Metrics) {
+        return denied.into_response();
+    }
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        server.metrics.render(),
+    )
+        .into_response()
+}
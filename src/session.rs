@@ -0,0 +1,99 @@
+//! Named recording sessions grouping several dictations
+
+use crate::config::{Config, HistoryEntry};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Check that a session name can be used as a directory name
+pub fn validate(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && !name.starts_with('.');
+    if !valid {
+        return Err(format!(
+            "Invalid session name: {} (use letters, digits, '-', '_' and '.')",
+            name
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Directory holding the audio of a session
+pub fn session_dir(name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = dirs::data_dir()
+        .ok_or("Could not find data directory")?
+        .join("rec")
+        .join("sessions")
+        .join(name);
+
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Save the audio of a dictation in its session directory
+pub fn save_audio(name: &str, wav_data: &[u8]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f");
+    let path = session_dir(name)?.join(format!("{}.wav", timestamp));
+    fs::write(&path, wav_data)?;
+    Ok(path)
+}
+
+/// Speaker names given in a session, by diarization label
+pub fn speaker_names(name: &str) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+    let path = session_dir(name)?.join("speakers.json");
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Remember speaker names for the next dictations of a session
+pub fn save_speaker_names(
+    name: &str,
+    names: &BTreeMap<String, String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = session_dir(name)?.join("speakers.json");
+    fs::write(path, serde_json::to_string_pretty(names)?)?;
+    Ok(())
+}
+
+/// History entries of a session, oldest first
+pub fn entries(name: &str) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
+    Ok(Config::load_history()?
+        .into_iter()
+        .filter(|entry| entry.session.as_deref() == Some(name))
+        .collect())
+}
+
+/// Session names with their number of dictations, in order of first use
+pub fn list() -> Result<Vec<(String, usize)>, Box<dyn std::error::Error>> {
+    let mut sessions: Vec<(String, usize)> = Vec::new();
+    for entry in Config::load_history()? {
+        let Some(name) = entry.session else { continue };
+        match sessions.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, count)) => *count += 1,
+            None => sessions.push((name, 1)),
+        }
+    }
+    Ok(sessions)
+}
+
+/// All dictations of a session as a single Markdown document
+pub fn export(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let entries = entries(name)?;
+    if entries.is_empty() {
+        return Err(format!("No dictations in session {}", name).into());
+    }
+
+    let mut document = format!("# {}\n", name);
+    for entry in &entries {
+        document.push('\n');
+        document.push_str(entry.corrected.trim());
+        document.push('\n');
+    }
+    Ok(document)
+}
@@ -0,0 +1,117 @@
+//! Dictation split into parts pasted one at a time (`--split-on`)
+//!
+//! Filling a form takes one dictation: say "separator" (or dictate the
+//! separator itself) between fields, the first part is copied right away and
+//! each `rec next`, bound to a hotkey, copies the following one. The parts
+//! left wait in the runtime directory, so `rec next` works after the
+//! dictating `rec` exited.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Spoken word splitting the text besides the `--split-on` separator
+const SPOKEN: &str = "separator";
+
+/// Parts of a dictation and the next one to copy
+#[derive(Serialize, Deserialize)]
+struct Queue {
+    parts: Vec<String>,
+    next: usize,
+}
+
+/// Parts of `text` between separators, empty ones dropped
+pub fn split(text: &str, separator: &str) -> Vec<String> {
+    let pieces: Vec<&str> = if separator.is_empty() {
+        vec![text]
+    } else {
+        text.split(separator).collect()
+    };
+    let mut parts = Vec::new();
+    for piece in pieces {
+        let mut start = 0;
+        for (at, word) in words(piece) {
+            if word.eq_ignore_ascii_case(SPOKEN) {
+                // "Subject. Separator. Body": the sentence punctuation
+                // around the spoken word goes with it
+                parts.push(piece[start..at].trim_end_matches(is_cut));
+                start = at + word.len();
+            }
+        }
+        parts.push(&piece[start..]);
+    }
+    parts
+        .into_iter()
+        .map(|part| part.trim_start_matches(is_cut).trim_end())
+        .filter(|part| !part.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn is_cut(c: char) -> bool {
+    c.is_whitespace() || ",.;:".contains(c)
+}
+
+/// Words of `text` with their byte offset
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(move |word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+}
+
+fn queue_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = dirs::runtime_dir()
+        .or_else(dirs::cache_dir)
+        .ok_or("Could not find runtime directory")?
+        .join("rec");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir.join("slots.json"))
+}
+
+/// Keep the parts after the first for `rec next`, replacing older ones
+pub fn save(parts: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let path = queue_path()?;
+    if parts.len() < 2 {
+        // Nothing left for `rec next`, not even an earlier dictation's parts
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        return Ok(());
+    }
+    let queue = Queue {
+        parts: parts.to_vec(),
+        next: 1,
+    };
+    crate::write_private(&path, &serde_json::to_string(&queue)?)?;
+    Ok(())
+}
+
+/// Part handed out by [`next`]
+pub struct Part {
+    pub text: String,
+    /// From 1
+    pub number: usize,
+    pub total: usize,
+}
+
+/// Next part to copy, if any are left
+pub fn next() -> Result<Option<Part>, Box<dyn std::error::Error>> {
+    let path = queue_path()?;
+    let Ok(content) = std::fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+    let mut queue: Queue = serde_json::from_str(&content)?;
+    let Some(text) = queue.parts.get(queue.next).cloned() else {
+        return Ok(None);
+    };
+    queue.next += 1;
+    if queue.next < queue.parts.len() {
+        crate::write_private(&path, &serde_json::to_string(&queue)?)?;
+    } else {
+        std::fs::remove_file(&path)?;
+    }
+    Ok(Some(Part {
+        text,
+        number: queue.next,
+        total: queue.parts.len(),
+    }))
+}
@@ -0,0 +1,190 @@
+//! Reading the final text aloud (--speak)
+//!
+//! Uses the platform's speech synthesizer by default, a custom command, or
+//! an OpenAI-style speech endpoint whose audio is played with the system
+//! player.
+
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SpeakConfig {
+    /// Shell command reading the text on stdin, instead of the platform synthesizer
+    #[serde(default)]
+    pub command: Option<String>,
+    /// OpenAI-style speech endpoint (e.g. https://api.openai.com/v1/audio/speech),
+    /// used instead of any command when set
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Environment variable holding the endpoint's API key (sent as a bearer token)
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    #[serde(default = "default_model")]
+    pub model: String,
+    #[serde(default = "default_voice")]
+    pub voice: String,
+}
+
+impl Default for SpeakConfig {
+    fn default() -> Self {
+        Self {
+            command: None,
+            url: None,
+            api_key_env: None,
+            model: default_model(),
+            voice: default_voice(),
+        }
+    }
+}
+
+fn default_model() -> String {
+    "tts-1".to_string()
+}
+
+fn default_voice() -> String {
+    "alloy".to_string()
+}
+
+/// Platform synthesizers reading text on stdin, tried in order
+#[cfg(target_os = "macos")]
+const SYNTHESIZERS: &[(&str, &[&str])] = &[("say", &["-f", "-"])];
+#[cfg(windows)]
+const SYNTHESIZERS: &[(&str, &[&str])] = &[(
+    "powershell",
+    &[
+        "-NoProfile",
+        "-Command",
+        "Add-Type -AssemblyName System.Speech; \
+         (New-Object System.Speech.Synthesis.SpeechSynthesizer).Speak([Console]::In.ReadToEnd())",
+    ],
+)];
+#[cfg(not(any(target_os = "macos", windows)))]
+const SYNTHESIZERS: &[(&str, &[&str])] = &[
+    ("spd-say", &["--wait", "--pipe-mode"]),
+    ("espeak-ng", &["--stdin"]),
+    ("espeak", &["--stdin"]),
+];
+
+/// Audio players taking a WAV file path, tried in order
+#[cfg(target_os = "macos")]
+const PLAYERS: &[&str] = &["afplay"];
+#[cfg(not(target_os = "macos"))]
+const PLAYERS: &[&str] = &["pw-play", "paplay", "aplay"];
+
+/// Read text aloud, returning once it has been spoken
+pub async fn speak(config: &SpeakConfig, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if text.trim().is_empty() {
+        return Ok(());
+    }
+
+    if let Some(url) = &config.url {
+        let wav = synthesize(config, url, text).await?;
+        return play(&wav).await;
+    }
+
+    if let Some(command) = &config.command {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        return run(cmd, text).await;
+    }
+
+    for (program, args) in SYNTHESIZERS {
+        let mut cmd = Command::new(program);
+        cmd.args(*args);
+        match run(cmd, text).await {
+            Err(e) if is_not_found(e.as_ref()) => continue,
+            result => return result,
+        }
+    }
+    Err(format!(
+        "No speech synthesizer found (install {} or set speak.command)",
+        SYNTHESIZERS
+            .iter()
+            .map(|(program, _)| *program)
+            .collect::<Vec<_>>()
+            .join(" or ")
+    )
+    .into())
+}
+
+/// Get the text as WAV from an OpenAI-style speech endpoint
+async fn synthesize(
+    config: &SpeakConfig,
+    url: &str,
+    text: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut req = reqwest::Client::new().post(url).json(&serde_json::json!({
+        "model": config.model,
+        "voice": config.voice,
+        "input": text,
+        "response_format": "wav",
+    }));
+    if let Some(var) = &config.api_key_env {
+        let key = std::env::var(var).map_err(|_| format!("{} not set", var))?;
+        req = req.bearer_auth(key);
+    }
+
+    let resp = req.send().await?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        return Err(format!("Speech API error ({}): {}", status, body.trim()).into());
+    }
+    Ok(resp.bytes().await?.to_vec())
+}
+
+/// Play a WAV buffer with the first available system player
+async fn play(wav: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::temp_dir().join(format!("rec-speak-{}.wav", std::process::id()));
+    tokio::fs::write(&path, wav).await?;
+
+    let mut result =
+        Err(format!("No audio player found (install {})", PLAYERS.join(" or ")).into());
+    for player in PLAYERS {
+        match Command::new(player)
+            .arg(&path)
+            .stderr(Stdio::null())
+            .status()
+            .await
+        {
+            Ok(status) if status.success() => {
+                result = Ok(());
+                break;
+            }
+            Ok(status) => {
+                result = Err(format!("{} exited with {}", player, status).into());
+                break;
+            }
+            Err(_) => continue,
+        }
+    }
+
+    tokio::fs::remove_file(&path).await.ok();
+    result
+}
+
+/// Run a synthesizer with the text on stdin and wait for it to finish
+async fn run(mut cmd: Command, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).await?;
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(format!("Speech synthesizer exited with {}", status).into());
+    }
+    Ok(())
+}
+
+fn is_not_found(error: &(dyn std::error::Error + 'static)) -> bool {
+    error
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+}
@@ -0,0 +1,330 @@
+//! Encrypted sync of history and config between machines (`rec sync`)
+//!
+//! Everything is kept in one bundle, encrypted with a key derived from
+//! `REC_SYNC_PASSPHRASE`, so the remote (a WebDAV file, an S3 object or a
+//! git repository) never sees dictations or API keys. Syncing merges the
+//! remote bundle into the local files, then uploads the result.
+
+use crate::config::{Config, HistoryEntry};
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU32;
+use std::process::Command;
+
+/// Start of every bundle, also authenticated with the contents
+const MAGIC: &[u8] = b"RECSYNC1";
+
+const SALT_LEN: usize = 16;
+
+/// PBKDF2-HMAC-SHA256 rounds for deriving the key from the passphrase
+const PBKDF2_ROUNDS: u32 = 600_000;
+
+/// File holding the bundle in a git remote
+const GIT_FILE: &str = "rec-sync.bundle";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SyncConfig {
+    /// Where the bundle is kept: an http(s) WebDAV file URL, `s3://bucket/key`,
+    /// or a git repository (`git+<url>`, or any URL ending in `.git`)
+    #[serde(default)]
+    pub remote: Option<String>,
+    /// WebDAV user name; the password comes from REC_SYNC_PASSWORD
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+/// What is synced, before encryption
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    config: Config,
+    history: Vec<HistoryEntry>,
+}
+
+enum Remote {
+    WebDav(String),
+    S3(String),
+    Git(String),
+}
+
+impl Remote {
+    fn parse(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if url.starts_with("s3://") {
+            Ok(Self::S3(url.to_string()))
+        } else if let Some(url) = url.strip_prefix("git+") {
+            Ok(Self::Git(url.to_string()))
+        } else if url.ends_with(".git") || url.starts_with("git@") {
+            Ok(Self::Git(url.to_string()))
+        } else if url.starts_with("https://") || url.starts_with("http://") {
+            Ok(Self::WebDav(url.to_string()))
+        } else {
+            Err(format!(
+                "Unsupported sync remote: {} (use an http(s) WebDAV URL, s3://bucket/key or a git repository)",
+                url
+            )
+            .into())
+        }
+    }
+}
+
+/// Merge the remote bundle into local history and config, then upload the result
+///
+/// `remote` overrides `sync.remote` from config. On a machine without a
+/// config file yet, the remote config is adopted as is.
+pub async fn sync(remote: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let passphrase = std::env::var("REC_SYNC_PASSPHRASE")
+        .map_err(|_| "REC_SYNC_PASSPHRASE not set (the bundle is encrypted with it)")?;
+    let fresh = !Config::exists();
+    let mut config = Config::load()?;
+    let url = remote
+        .map(str::to_string)
+        .or_else(|| config.sync.remote.clone())
+        .ok_or("No sync remote: set sync.remote in config or pass --remote")?;
+    let target = Remote::parse(&url)?;
+    let username = config.sync.username.clone();
+
+    let mut history = Config::load_history()?;
+    let local_count = history.len();
+
+    eprintln!("Fetching {}...", url);
+    if let Some(data) = fetch(&target, username.as_deref()).await? {
+        let bundle: Bundle = serde_json::from_slice(&decrypt(&data, &passphrase)?)?;
+        if fresh {
+            config = bundle.config;
+            config.sync.remote = Some(url.clone());
+        } else {
+            for word in bundle.config.custom_words {
+                config.add_custom_word(word);
+            }
+            for (heard, written) in bundle.config.word_overrides {
+                config.word_overrides.entry(heard).or_insert(written);
+            }
+            for (word, aliases) in bundle.config.word_aliases {
+                for alias in aliases {
+                    config.add_word_alias(&word, &alias);
+                }
+            }
+        }
+        history = merge_history(history, bundle.history);
+    }
+    let received = history.len() - local_count;
+
+    config.save()?;
+    Config::save_history(&history)?;
+
+    let bundle = serde_json::to_vec(&Bundle {
+        config: config.clone(),
+        history,
+    })?;
+    eprintln!("Uploading...");
+    push(&target, username.as_deref(), encrypt(&bundle, &passphrase)?).await?;
+
+    eprintln!(
+        "Synced with {}: {} dictation(s) received, {} custom word(s)",
+        url,
+        received,
+        config.custom_words.len()
+    );
+    Ok(())
+}
+
+/// Union of two histories, in time order
+///
+/// The same dictation on both sides is kept once, preferring the copy with
+/// feedback so a verdict given on either machine is kept.
+fn merge_history(local: Vec<HistoryEntry>, remote: Vec<HistoryEntry>) -> Vec<HistoryEntry> {
+    let mut merged: Vec<HistoryEntry> = local;
+    for entry in remote {
+        let same = merged
+            .iter_mut()
+            .find(|e| e.timestamp == entry.timestamp && e.original == entry.original);
+        match same {
+            Some(existing) => {
+                if existing.feedback.is_none() && entry.feedback.is_some() {
+                    *existing = entry;
+                }
+            }
+            None => merged.push(entry),
+        }
+    }
+    merged.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    merged
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<LessSafeKey, Box<dyn std::error::Error>> {
+    let mut key = [0u8; 32];
+    ring::pbkdf2::derive(
+        ring::pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ROUNDS).unwrap(),
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key).map_err(|_| "Invalid sync key")?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// `MAGIC`, salt, nonce, then ChaCha20-Poly1305 ciphertext and tag
+fn encrypt(plain: &[u8], passphrase: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt).map_err(|_| "No randomness available")?;
+    rng.fill(&mut nonce)
+        .map_err(|_| "No randomness available")?;
+
+    let mut data = plain.to_vec();
+    derive_key(passphrase, &salt)?
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(MAGIC),
+            &mut data,
+        )
+        .map_err(|_| "Encryption failed")?;
+
+    Ok([MAGIC, &salt, &nonce, &data].concat())
+}
+
+fn decrypt(bundle: &[u8], passphrase: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let rest = bundle
+        .strip_prefix(MAGIC)
+        .filter(|rest| rest.len() >= SALT_LEN + NONCE_LEN)
+        .ok_or("The remote file is not a rec sync bundle")?;
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, data) = rest.split_at(NONCE_LEN);
+
+    let mut data = data.to_vec();
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "Invalid nonce")?;
+    let plain = derive_key(passphrase, salt)?
+        .open_in_place(nonce, Aad::from(MAGIC), &mut data)
+        .map_err(|_| "Could not decrypt the sync bundle: wrong REC_SYNC_PASSPHRASE?")?;
+    Ok(plain.to_vec())
+}
+
+/// Download the bundle, or None if the remote has none yet
+async fn fetch(
+    remote: &Remote,
+    username: Option<&str>,
+) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    match remote {
+        Remote::WebDav(url) => {
+            let resp = webdav(reqwest::Client::new().get(url), username)
+                .send()
+                .await?;
+            if resp.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            if !resp.status().is_success() {
+                return Err(format!("GET {} failed: {}", url, resp.status()).into());
+            }
+            Ok(Some(resp.bytes().await?.to_vec()))
+        }
+        Remote::S3(url) => {
+            let output = Command::new("aws")
+                .args(["s3", "cp", url, "-"])
+                .output()
+                .map_err(|e| format!("Failed to run aws (needed for s3:// remotes): {}", e))?;
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if output.status.success() {
+                Ok(Some(output.stdout))
+            } else if stderr.contains("404") || stderr.contains("does not exist") {
+                Ok(None)
+            } else {
+                Err(format!("aws s3 cp failed: {}", stderr.trim()).into())
+            }
+        }
+        Remote::Git(url) => {
+            let dir = clone(url)?;
+            let data = std::fs::read(dir.join(GIT_FILE)).ok();
+            std::fs::remove_dir_all(&dir).ok();
+            Ok(data)
+        }
+    }
+}
+
+/// Upload the bundle, replacing the previous one
+async fn push(
+    remote: &Remote,
+    username: Option<&str>,
+    data: Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match remote {
+        Remote::WebDav(url) => {
+            let resp = webdav(reqwest::Client::new().put(url), username)
+                .body(data)
+                .send()
+                .await?;
+            if !resp.status().is_success() {
+                return Err(format!("PUT {} failed: {}", url, resp.status()).into());
+            }
+        }
+        Remote::S3(url) => {
+            use std::io::Write;
+
+            let mut child = Command::new("aws")
+                .args(["s3", "cp", "-", url])
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::null())
+                .spawn()
+                .map_err(|e| format!("Failed to run aws (needed for s3:// remotes): {}", e))?;
+            child.stdin.take().ok_or("No stdin")?.write_all(&data)?;
+            if !child.wait()?.success() {
+                return Err("aws s3 cp failed".into());
+            }
+        }
+        Remote::Git(url) => {
+            let dir = clone(url)?;
+            let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+                std::fs::write(dir.join(GIT_FILE), &data)?;
+                git(&dir, &["add", GIT_FILE])?;
+                // A machine may have no git identity configured for the commit
+                let mut commit = Vec::new();
+                if git(&dir, &["config", "user.email"]).is_err() {
+                    commit.extend(["-c", "user.name=rec", "-c", "user.email=rec@localhost"]);
+                }
+                commit.extend(["commit", "--quiet", "-m", "Update rec sync bundle"]);
+                git(&dir, &commit)?;
+                git(&dir, &["push", "--quiet", "origin", "HEAD"])
+            })();
+            std::fs::remove_dir_all(&dir).ok();
+            result?;
+        }
+    }
+    Ok(())
+}
+
+fn webdav(req: reqwest::RequestBuilder, username: Option<&str>) -> reqwest::RequestBuilder {
+    match username {
+        Some(user) => req.basic_auth(user, std::env::var("REC_SYNC_PASSWORD").ok()),
+        None => req,
+    }
+}
+
+/// Fresh shallow clone of a git remote in a temporary directory
+fn clone(url: &str) -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("rec-sync-{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    let path = dir.to_string_lossy().to_string();
+    git(
+        &std::env::temp_dir(),
+        &["clone", "--quiet", "--depth", "1", url, &path],
+    )?;
+    Ok(dir)
+}
+
+fn git(dir: &std::path::Path, args: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(())
+}
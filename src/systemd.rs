@@ -0,0 +1,108 @@
+//! Running as a systemd service (`rec serve --systemd`)
+//!
+//! Implements the parts of the systemd protocols the server needs without
+//! linking libsystemd: readiness notification (`Type=notify`), taking the
+//! listening socket from a `.socket` unit, and log lines the journal can
+//! sort by priority.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// First file descriptor passed by socket activation (SD_LISTEN_FDS_START)
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Log for the journal for the rest of the run: plain status lines and
+/// syslog priority prefixes on errors and warnings
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+    crate::progress::plain();
+}
+
+/// Log an error, at error priority in the journal
+pub fn error(msg: impl std::fmt::Display) {
+    eprintln!("{}Error: {}", prefix(3), msg);
+}
+
+/// Log a warning, at warning priority in the journal
+pub fn warning(msg: impl std::fmt::Display) {
+    eprintln!("{}Warning: {}", prefix(4), msg);
+}
+
+/// `<N>` prefix journald reads as the line's priority (SyslogLevelPrefix)
+fn prefix(priority: u8) -> String {
+    match ENABLED.load(Ordering::Relaxed) {
+        true => format!("<{}>", priority),
+        false => String::new(),
+    }
+}
+
+/// Tell the service manager about a state change, e.g. `READY=1`
+///
+/// Does nothing when not started by systemd (no NOTIFY_SOCKET).
+#[cfg(unix)]
+pub fn notify(state: &str) -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let path = path.to_string_lossy().to_string();
+    // '@' stands for the abstract namespace
+    let addr = match path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            SocketAddr::from_abstract_name(name)?
+        }
+        _ => SocketAddr::from_pathname(&path)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn notify(_state: &str) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(())
+}
+
+/// Listening socket passed by a systemd `.socket` unit, if any
+///
+/// Only a single TCP socket (`ListenStream=127.0.0.1:7373`) is supported.
+#[cfg(unix)]
+pub fn listener() -> Result<Option<std::net::TcpListener>, Box<dyn std::error::Error>> {
+    use std::os::fd::FromRawFd;
+
+    // LISTEN_PID guards against variables inherited from another service
+    let pid = std::env::var("LISTEN_PID").ok();
+    if pid.and_then(|p| p.parse::<u32>().ok()) != Some(std::process::id()) {
+        return Ok(None);
+    }
+    let count: i32 = match std::env::var("LISTEN_FDS") {
+        Ok(count) => count.parse()?,
+        Err(_) => return Ok(None),
+    };
+    match count {
+        0 => return Ok(None),
+        1 => {}
+        _ => return Err(format!("Expected one socket from systemd, got {}", count).into()),
+    }
+
+    // SAFETY: systemd passes the socket open at LISTEN_FDS_START, and
+    // nothing else in the process owns it
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(LISTEN_FDS_START) };
+    if listener.local_addr().is_err() {
+        return Err(
+            "The socket passed by systemd is not a TCP socket (use ListenStream=<address>:<port>)"
+                .into(),
+        );
+    }
+    listener.set_nonblocking(true)?;
+    Ok(Some(listener))
+}
+
+#[cfg(not(unix))]
+pub fn listener() -> Result<Option<std::net::TcpListener>, Box<dyn std::error::Error>> {
+    Ok(None)
+}
@@ -0,0 +1,232 @@
+//! Text comparison and cleanup helpers
+
+/// Similarity between two texts in [0, 1], ignoring case and punctuation
+///
+/// Based on the character-level edit distance of the normalized texts.
+pub fn similarity(a: &str, b: &str) -> f32 {
+    let a = normalize(a);
+    let b = normalize(b);
+    let len = a.len().max(b.len());
+    if len == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(&a, &b) as f32 / len as f32
+}
+
+/// Whether `text` contains `word` (or phrase) as whole words, ignoring case and punctuation
+pub fn contains_word(text: &str, word: &str) -> bool {
+    let word: String = normalize(word).into_iter().collect();
+    if word.is_empty() {
+        return false;
+    }
+    let text: String = normalize(text).into_iter().collect();
+    format!(" {} ", text).contains(&format!(" {} ", word))
+}
+
+/// Replace whole-word occurrences of `from` (a word or phrase), ignoring case
+pub fn replace_word(text: &str, from: &str, to: &str) -> String {
+    let from: Vec<char> = from.chars().collect();
+    if from.is_empty() {
+        return text.to_string();
+    }
+    let chars: Vec<char> = text.chars().collect();
+    let same = |a: char, b: char| a == b || a.to_lowercase().eq(b.to_lowercase());
+
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let at_start = i == 0 || !chars[i - 1].is_alphanumeric();
+        let end = i + from.len();
+        let matches = at_start
+            && end <= chars.len()
+            && chars[i..end].iter().zip(&from).all(|(&a, &b)| same(a, b))
+            && chars.get(end).is_none_or(|c| !c.is_alphanumeric());
+        if matches {
+            out.push_str(to);
+            i = end;
+        } else {
+            out.push(chars[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Remove whole-word occurrences of `fillers`, ignoring case, with the
+/// commas setting them off
+///
+/// "So, uh, we ship" becomes "So we ship", and "Um, the build" becomes
+/// "The build".
+pub fn remove_fillers(text: &str, fillers: &[String]) -> String {
+    if fillers.is_empty() {
+        return text.to_string();
+    }
+    let words = fillers
+        .iter()
+        .map(|w| regex::escape(w.trim()))
+        .collect::<Vec<_>>()
+        .join("|");
+    let Ok(pattern) = regex::Regex::new(&format!(r"(?i)(?:,[ \t]*)?\b(?:{})\b(?:[ \t]*,)?", words))
+    else {
+        return text.to_string();
+    };
+
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    let mut capitalize = false;
+    for found in pattern.find_iter(text) {
+        push_capitalized(&mut out, &text[last..found.start()], &mut capitalize);
+        out.truncate(out.trim_end_matches([' ', '\t']).len());
+        let rest = text[found.end()..].trim_start_matches([' ', '\t']);
+        last = text.len() - rest.len();
+
+        let sentence_start = out.is_empty() || out.ends_with(['.', '!', '?', '\n']);
+        let filler = found.as_str().trim_start_matches([',', ' ', '\t']);
+        if sentence_start {
+            capitalize |= filler.starts_with(char::is_uppercase);
+            if !out.is_empty() && !out.ends_with('\n') && !rest.is_empty() {
+                out.push(' ');
+            }
+        } else if !(rest.is_empty() || rest.starts_with([',', '.', ';', ':', '!', '?', '…', '\n']))
+        {
+            out.push(' ');
+        }
+    }
+    push_capitalized(&mut out, &text[last..], &mut capitalize);
+    out
+}
+
+/// Append `part`, uppercasing its first letter if `capitalize` is set
+fn push_capitalized(out: &mut String, part: &str, capitalize: &mut bool) {
+    let mut chars = part.chars();
+    if *capitalize && let Some(first) = chars.next() {
+        out.extend(first.to_uppercase());
+        out.push_str(chars.as_str());
+        *capitalize = false;
+    } else {
+        out.push_str(part);
+    }
+}
+
+/// Split text after sentence-ending punctuation, keeping all characters
+pub fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let at_end = matches!(c, '.' | '!' | '?' | '\n')
+            && chars.peek().is_none_or(|&(_, next)| next.is_whitespace());
+        if at_end {
+            let end = i + c.len_utf8();
+            sentences.push(&text[start..end]);
+            start = end;
+        }
+    }
+    if start < text.len() {
+        sentences.push(&text[start..]);
+    }
+    sentences
+}
+
+/// Lowercase letters and digits, with single spaces between words
+fn normalize(text: &str) -> Vec<char> {
+    let cleaned: String = text
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .to_lowercase();
+    cleaned
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .collect()
+}
+
+pub fn levenshtein<T: PartialEq>(a: &[T], b: &[T]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, x) in a.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let cost = usize::from(x != y);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[b.len()]
+}
+
+/// How a reference word was transcribed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordEdit<'a> {
+    Same(&'a str),
+    Substituted { reference: &'a str, actual: &'a str },
+    Deleted(&'a str),
+    Inserted(&'a str),
+}
+
+/// Word-level alignment of a transcript against a reference text
+///
+/// Words are compared ignoring case and punctuation, and reported as written.
+pub fn word_edits<'a>(reference: &'a str, actual: &'a str) -> Vec<WordEdit<'a>> {
+    let words = |text: &'a str| -> Vec<(&'a str, String)> {
+        text.split_whitespace()
+            .map(|word| (word, normalize(word).into_iter().collect::<String>()))
+            .filter(|(_, key)| !key.is_empty())
+            .collect()
+    };
+    let a = words(reference);
+    let b = words(actual);
+
+    // dist[i][j]: edits to turn the first i reference words into the first j actual words
+    let mut dist = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in dist.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in dist[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1].1 != b[j - 1].1);
+            dist[i][j] = (dist[i - 1][j - 1] + cost)
+                .min(dist[i - 1][j] + 1)
+                .min(dist[i][j - 1] + 1);
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 || j > 0 {
+        if i > 0 && j > 0 {
+            let cost = usize::from(a[i - 1].1 != b[j - 1].1);
+            if dist[i][j] == dist[i - 1][j - 1] + cost {
+                edits.push(if cost == 0 {
+                    WordEdit::Same(b[j - 1].0)
+                } else {
+                    WordEdit::Substituted {
+                        reference: a[i - 1].0,
+                        actual: b[j - 1].0,
+                    }
+                });
+                i -= 1;
+                j -= 1;
+                continue;
+            }
+        }
+        if i > 0 && dist[i][j] == dist[i - 1][j] + 1 {
+            edits.push(WordEdit::Deleted(a[i - 1].0));
+            i -= 1;
+        } else {
+            edits.push(WordEdit::Inserted(b[j - 1].0));
+            j -= 1;
+        }
+    }
+
+    edits.reverse();
+    edits
+}
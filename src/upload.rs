@@ -0,0 +1,172 @@
+//! Resumable transcription of long recordings, sent in parts
+
+use crate::audio;
+use crate::backend::{Backend, TranscribeOptions, Transcript};
+use crate::limits::{self, LimitsConfig};
+use crate::progress;
+use crate::watchdog::{self, WatchdogConfig};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+/// Recordings longer than this (in seconds) are sent in parts of this length,
+/// unless the backend takes less at once
+const PART_SECS: usize = 300;
+
+/// Transcribe audio, sending long recordings in parts
+///
+/// Each part's transcript is cached as soon as it arrives, so when some parts
+/// fail, running again on the same audio only re-sends those. The audio is
+/// kept next to the cache so a recording from the mic can be resumed too,
+/// unless that would leave less free disk than `limits` allows.
+///
+/// Every request is watched by the `watchdog`, which may send it to the
+/// `failover` backends in turn.
+pub async fn transcribe(
+    backend: &Backend,
+    failover: &[Backend],
+    opts: TranscribeOptions,
+    limits: &LimitsConfig,
+    watchdog: &WatchdogConfig,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    let chain: Vec<&Backend> = std::iter::once(backend).chain(failover).collect();
+    let part_secs = chain
+        .iter()
+        .filter_map(|backend| backend.capabilities().max_secs)
+        .fold(PART_SECS, usize::min);
+    let Some(parts) = split(&opts.wav_data, part_secs) else {
+        return watchdog::transcribe(&chain, opts, watchdog).await;
+    };
+
+    let dir = parts_dir(&cache_key(backend, &opts))?;
+    let count = parts.len();
+    let mut transcripts = Vec::new();
+
+    for (i, wav_data) in parts.into_iter().enumerate() {
+        let path = dir.join(format!("{}.json", i));
+        let cached = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<Transcript>(&json).ok());
+        if let Some(transcript) = cached {
+            transcripts.push(Some(transcript));
+            continue;
+        }
+
+        progress::status(&format!("Transcribing part {}/{}...", i + 1, count));
+        let part = TranscribeOptions {
+            wav_data,
+            model: opts.model.clone(),
+            language: opts.language.clone(),
+            context_bias: opts.context_bias.clone(),
+            prompt: opts.prompt.clone(),
+        };
+        match watchdog::transcribe(&chain, part, watchdog).await {
+            Ok(transcript) => {
+                fs::write(&path, serde_json::to_string(&transcript)?)?;
+                transcripts.push(Some(transcript));
+            }
+            Err(e) => {
+                progress::status("");
+                eprintln!("Part {}/{} failed: {}", i + 1, count, e);
+                transcripts.push(None);
+            }
+        }
+    }
+    progress::status("");
+
+    let failed = transcripts.iter().filter(|t| t.is_none()).count();
+    if failed > 0 {
+        let audio_path = dir.join("audio.wav");
+        if !audio_path.exists() {
+            if let Err(e) = limits::check_disk(limits, &dir, opts.wav_data.len()) {
+                return Err(format!(
+                    "{} of {} parts failed; the audio was not kept to resume: {}",
+                    failed, count, e
+                )
+                .into());
+            }
+            fs::write(&audio_path, &opts.wav_data)?;
+        }
+        return Err(format!(
+            "{} of {} parts failed; run `rec -f {}` to send only those again",
+            failed,
+            count,
+            audio_path.display()
+        )
+        .into());
+    }
+
+    let mut merged = Transcript::default();
+    for (i, transcript) in transcripts.into_iter().flatten().enumerate() {
+        let offset = (i * part_secs) as f32;
+        if !merged.text.is_empty() {
+            merged.text.push(' ');
+        }
+        merged.text.push_str(transcript.text.trim());
+        merged
+            .segments
+            .extend(transcript.segments.into_iter().map(|mut segment| {
+                segment.start += offset;
+                segment.end += offset;
+                segment
+            }));
+    }
+
+    let _ = fs::remove_dir_all(&dir);
+    Ok(merged)
+}
+
+/// Split a 16-bit WAV longer than `part_secs` into WAV parts
+///
+/// Returns `None` for short recordings and other formats, which are sent whole.
+fn split(wav_data: &[u8], part_secs: usize) -> Option<Vec<Vec<u8>>> {
+    let reader = hound::WavReader::new(std::io::Cursor::new(wav_data)).ok()?;
+    let spec = reader.spec();
+    if spec.sample_format != hound::SampleFormat::Int || spec.bits_per_sample != 16 {
+        return None;
+    }
+
+    let part_len = part_secs * spec.sample_rate as usize * spec.channels as usize;
+    if reader.len() as usize <= part_len {
+        return None;
+    }
+
+    let samples: Vec<f32> = reader
+        .into_samples::<i16>()
+        .map(|s| s.map(|s| s as f32 / 32768.0))
+        .collect::<Result<_, _>>()
+        .ok()?;
+
+    samples
+        .chunks(part_len)
+        .map(|part| audio::encode_wav(part, spec.sample_rate, spec.channels).ok())
+        .collect()
+}
+
+/// Identify a transcription by its audio and the settings that affect the result
+fn cache_key(backend: &Backend, opts: &TranscribeOptions) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(&opts.wav_data);
+    hasher.update(backend.name().as_bytes());
+    hasher.update(opts.model.as_bytes());
+    hasher.update(opts.language.as_deref().unwrap_or("").as_bytes());
+    hasher.update(opts.prompt.as_deref().unwrap_or("").as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .take(16)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Cache directory for the parts of one transcription
+fn parts_dir(key: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = dirs::cache_dir()
+        .ok_or("Could not find cache directory")?
+        .join("rec")
+        .join("parts")
+        .join(key);
+
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
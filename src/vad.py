@@ -0,0 +1,46 @@
+# Classify the 30 ms frames of the 16 kHz mono WAV on stdin as speech or not
+# with the engine in argv[1] (webrtc or silero) and its setting in argv[2]
+# (aggressiveness from 0 to 3, or speech probability), printing a JSON list
+# with one boolean per frame
+import io
+import json
+import sys
+import wave
+
+RATE = 16000
+FRAME = RATE * 30 // 1000
+
+audio = wave.open(io.BytesIO(sys.stdin.buffer.read()))
+pcm = audio.readframes(audio.getnframes())
+frames = len(pcm) // 2 // FRAME
+
+if sys.argv[1] == "webrtc":
+    import webrtcvad
+
+    vad = webrtcvad.Vad(int(sys.argv[2]))
+    speech = [
+        vad.is_speech(pcm[i * FRAME * 2 : (i + 1) * FRAME * 2], RATE)
+        for i in range(frames)
+    ]
+else:
+    import torch
+    from silero_vad import load_silero_vad
+
+    # The model takes 32 ms chunks at 16 kHz, each frame gets the
+    # probability of the chunk its middle falls in
+    CHUNK = 512
+    model = load_silero_vad()
+    samples = torch.frombuffer(bytearray(pcm), dtype=torch.int16).float() / 32768
+    probabilities = [
+        model(samples[start : start + CHUNK], RATE).item()
+        for start in range(0, len(samples) - CHUNK + 1, CHUNK)
+    ]
+    threshold = float(sys.argv[2])
+    speech = [
+        bool(probabilities)
+        and probabilities[min((i * FRAME + FRAME // 2) // CHUNK, len(probabilities) - 1)]
+        > threshold
+        for i in range(frames)
+    ]
+
+print(json.dumps(speech))
@@ -0,0 +1,275 @@
+//! Voice activity detection, used to drop silence before transcription
+//!
+//! The energy and adaptive detectors are built in. WebRTC's GMM detector
+//! and the Silero neural network run through their Python packages
+//! (`pip install webrtcvad` or `silero-vad`) by a short script, as Vosk
+//! does, so `rec` needs no native library for them.
+
+use crate::audio::rms;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Length of the frames the detectors classify
+pub const FRAME_MS: usize = 30;
+
+/// Audio kept around speech so word edges aren't cut
+const PADDING_MS: usize = 300;
+
+/// Script run with the engine and its setting as arguments, a 16 kHz mono
+/// WAV on stdin
+const SCRIPT: &str = include_str!("vad.py");
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VadEngine {
+    /// Send the audio as recorded
+    #[default]
+    None,
+    /// Fixed loudness threshold: cheapest, fine in a quiet room
+    Energy,
+    /// Threshold relative to the background noise, for noisy places
+    Adaptive,
+    /// WebRTC's detector, telling voice from noise by its spectrum
+    Webrtc,
+    /// Silero's neural network: the most accurate, and the slowest to load
+    Silero,
+}
+
+impl std::str::FromStr for VadEngine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "energy" => Ok(Self::Energy),
+            "adaptive" => Ok(Self::Adaptive),
+            "webrtc" => Ok(Self::Webrtc),
+            "silero" => Ok(Self::Silero),
+            _ => Err(format!(
+                "Unknown VAD: {} (expected none, energy, adaptive, webrtc or silero)",
+                s
+            )),
+        }
+    }
+}
+
+/// Voice activity detection settings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VadConfig {
+    #[serde(default)]
+    pub engine: VadEngine,
+    /// RMS level (0-1) above which a frame is speech, for the energy detector
+    #[serde(default = "default_threshold")]
+    pub threshold: f32,
+    /// How many times louder than the background speech must be, for the adaptive detector
+    #[serde(default = "default_ratio")]
+    pub ratio: f32,
+    /// Background level the adaptive detector starts from (measured by `rec calibrate`)
+    #[serde(default)]
+    pub noise_floor: Option<f32>,
+    /// How readily the webrtc detector takes a frame for silence, from 0 to 3
+    #[serde(default = "default_aggressiveness")]
+    pub aggressiveness: u8,
+    /// Speech probability (0-1) above which a frame is speech, for the silero detector
+    #[serde(default = "default_probability")]
+    pub probability: f32,
+    /// Python interpreter with the webrtcvad or silero-vad package installed
+    #[serde(default = "default_python")]
+    pub python: String,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            engine: VadEngine::default(),
+            threshold: default_threshold(),
+            ratio: default_ratio(),
+            noise_floor: None,
+            aggressiveness: default_aggressiveness(),
+            probability: default_probability(),
+            python: default_python(),
+        }
+    }
+}
+
+fn default_threshold() -> f32 {
+    0.01
+}
+
+fn default_ratio() -> f32 {
+    3.0
+}
+
+fn default_aggressiveness() -> u8 {
+    2
+}
+
+fn default_probability() -> f32 {
+    0.5
+}
+
+fn default_python() -> String {
+    "python3".to_string()
+}
+
+/// Classifies audio frames as speech or not
+pub trait Vad {
+    fn is_speech(&mut self, frame: &[f32]) -> bool;
+}
+
+/// Speech is anything louder than a fixed level
+pub struct Energy {
+    threshold: f32,
+}
+
+impl Vad for Energy {
+    fn is_speech(&mut self, frame: &[f32]) -> bool {
+        rms(frame) > self.threshold
+    }
+}
+
+/// Speech is anything clearly louder than the background noise, which is
+/// tracked from the frames classified as non-speech
+pub struct Adaptive {
+    ratio: f32,
+    noise_floor: Option<f32>,
+}
+
+impl Vad for Adaptive {
+    fn is_speech(&mut self, frame: &[f32]) -> bool {
+        let level = rms(frame).max(1e-5);
+        let floor = *self.noise_floor.get_or_insert(level);
+
+        let speech = level > floor * self.ratio;
+        if !speech {
+            // Follow rising background noise slowly, and drops right away
+            self.noise_floor = Some(if level < floor {
+                level
+            } else {
+                floor * 0.95 + level * 0.05
+            });
+        }
+        speech
+    }
+}
+
+/// Frames classified beforehand, in order, by a detector running outside `rec`
+struct Classified {
+    speech: std::vec::IntoIter<bool>,
+}
+
+impl Vad for Classified {
+    fn is_speech(&mut self, _frame: &[f32]) -> bool {
+        self.speech.next().unwrap_or(false)
+    }
+}
+
+/// The detector selected in config for these samples, if any
+///
+/// The webrtc and silero detectors classify all the frames up front.
+pub fn detector(
+    config: &VadConfig,
+    samples: &[f32],
+    sample_rate: u32,
+    channels: u16,
+) -> Result<Option<Box<dyn Vad>>, Box<dyn std::error::Error>> {
+    let setting = match config.engine {
+        VadEngine::None => return Ok(None),
+        VadEngine::Energy => {
+            return Ok(Some(Box::new(Energy {
+                threshold: config.threshold,
+            })));
+        }
+        VadEngine::Adaptive => {
+            return Ok(Some(Box::new(Adaptive {
+                ratio: config.ratio,
+                noise_floor: config.noise_floor,
+            })));
+        }
+        VadEngine::Webrtc => config.aggressiveness.min(3).to_string(),
+        VadEngine::Silero => config.probability.to_string(),
+    };
+    let speech = classify(config, &setting, samples, sample_rate, channels)?;
+    Ok(Some(Box::new(Classified {
+        speech: speech.into_iter(),
+    })))
+}
+
+/// Speech flag of each frame, from the webrtcvad or silero-vad package
+fn classify(
+    config: &VadConfig,
+    setting: &str,
+    samples: &[f32],
+    sample_rate: u32,
+    channels: u16,
+) -> Result<Vec<bool>, Box<dyn std::error::Error>> {
+    let (engine, package) = match config.engine {
+        VadEngine::Webrtc => ("webrtc", "webrtcvad"),
+        _ => ("silero", "silero-vad"),
+    };
+    // Both take 16-bit mono audio at 16 kHz
+    let wav =
+        crate::enhance::to_model_rate(&crate::audio::encode_wav(samples, sample_rate, channels)?)?;
+
+    let mut child = Command::new(&config.python)
+        .arg("-c")
+        .arg(SCRIPT)
+        .arg(engine)
+        .arg(setting)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", config.python, e))?;
+
+    // The script reads all of stdin before it prints anything. If it fails
+    // first (package missing), writing breaks the pipe and stderr says why
+    let mut stdin = child.stdin.take().ok_or("No stdin")?;
+    stdin.write_all(&wav).ok();
+    drop(stdin);
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let hint = match stderr.contains("No module named") {
+            true => format!(
+                " (install it with `{} -m pip install {}`)",
+                config.python, package
+            ),
+            false => String::new(),
+        };
+        return Err(format!(
+            "{} error: {}{}",
+            package,
+            stderr.trim().lines().last().unwrap_or_default(),
+            hint
+        )
+        .into());
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// Keep only the speech in interleaved samples, with some padding around it
+///
+/// Returns an empty buffer when no speech is found.
+pub fn trim(samples: &[f32], sample_rate: u32, channels: u16, vad: &mut dyn Vad) -> Vec<f32> {
+    let frame_len = sample_rate as usize * FRAME_MS / 1000 * channels as usize;
+    if frame_len == 0 {
+        return samples.to_vec();
+    }
+
+    let frames: Vec<&[f32]> = samples.chunks(frame_len).collect();
+    let speech: Vec<bool> = frames.iter().map(|frame| vad.is_speech(frame)).collect();
+
+    let padding = PADDING_MS / FRAME_MS;
+    let mut kept = Vec::new();
+    for (i, frame) in frames.iter().enumerate() {
+        let start = i.saturating_sub(padding);
+        let end = (i + padding + 1).min(speech.len());
+        if speech[start..end].iter().any(|&s| s) {
+            kept.extend_from_slice(frame);
+        }
+    }
+    kept
+}
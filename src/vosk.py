@@ -0,0 +1,23 @@
+# Transcribe the WAV on stdin with the Vosk model in argv[1], printing the
+# recognizer's results (one per utterance, with word timings) as a JSON list
+import io
+import json
+import sys
+import wave
+
+from vosk import KaldiRecognizer, Model, SetLogLevel
+
+SetLogLevel(-1)
+audio = wave.open(io.BytesIO(sys.stdin.buffer.read()))
+recognizer = KaldiRecognizer(Model(sys.argv[1]), audio.getframerate())
+recognizer.SetWords(True)
+
+results = []
+while True:
+    data = audio.readframes(4000)
+    if not data:
+        break
+    if recognizer.AcceptWaveform(data):
+        results.append(json.loads(recognizer.Result()))
+results.append(json.loads(recognizer.FinalResult()))
+print(json.dumps(results))
@@ -0,0 +1,35 @@
+//! Vosk, a small offline recognizer (`--backend vosk`)
+//!
+//! Vosk models are a few dozen megabytes and run on any CPU, which makes
+//! them a fit for quick dictation where whisper.cpp would be overkill. The
+//! recognizer is driven through its Python binding (`pip install vosk`) by
+//! a short script, so `rec` needs no native library to support it.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Script run with the model directory as argument, WAV on stdin
+pub const SCRIPT: &str = include_str!("vosk.py");
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VoskConfig {
+    /// Unpacked model directory (e.g. /home/me/models/vosk-model-small-en-us-0.15)
+    #[serde(default)]
+    pub model_dir: Option<PathBuf>,
+    /// Python interpreter with the vosk package installed
+    #[serde(default = "default_python")]
+    pub python: String,
+}
+
+impl Default for VoskConfig {
+    fn default() -> Self {
+        Self {
+            model_dir: None,
+            python: default_python(),
+        }
+    }
+}
+
+fn default_python() -> String {
+    "python3".to_string()
+}
@@ -0,0 +1,253 @@
+//! Deadline on transcription requests (`watchdog` in config)
+//!
+//! A backend that accepts the upload and never answers would leave the
+//! status line on "Transcribing..." until Ctrl+C. Each request gets a hard
+//! deadline instead; past it, the request is dropped and sent again, then
+//! sent to the next backend of the failover chain if there is one. Local
+//! backends get no deadline: they don't hang on a network, and a large model
+//! on a CPU can take several times the length of the audio.
+//!
+//! The chain is `failover` in config, e.g. `["groq", "mistral", "local"]`,
+//! followed by `watchdog.fallback`. Rate limits and server errors are first
+//! sent again after a growing, randomized pause (or as long as the service's
+//! `Retry-After` asks), then move on to the next backend too. The audio stays
+//! in memory meanwhile, so a passing 429 or 503 doesn't lose the recording.
+
+use crate::backend::{Backend, TranscribeOptions, Transcript};
+use crate::progress;
+use serde::{Deserialize, Serialize};
+use std::hash::{BuildHasher, RandomState};
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WatchdogConfig {
+    /// Seconds a request may take on top of the length of its audio (0 disables; local backends have none)
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Times a request past its deadline is sent again
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Backend to send the audio to once retries are exhausted (e.g. 'local'), after those of `failover`
+    #[serde(default)]
+    pub fallback: Option<String>,
+    /// Times a request answered with a rate limit or a server error is sent again
+    #[serde(default = "default_error_retries")]
+    pub error_retries: u32,
+    /// Pause before the first of those, doubling with each one
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+    /// Longest pause; a longer `Retry-After` moves on to the next backend instead
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: default_timeout_secs(),
+            retries: default_retries(),
+            fallback: None,
+            error_retries: default_error_retries(),
+            backoff_ms: default_backoff_ms(),
+            max_backoff_secs: default_max_backoff_secs(),
+        }
+    }
+}
+
+fn default_timeout_secs() -> u64 {
+    60
+}
+
+fn default_retries() -> u32 {
+    1
+}
+
+fn default_error_retries() -> u32 {
+    3
+}
+
+fn default_backoff_ms() -> u64 {
+    500
+}
+
+fn default_max_backoff_secs() -> u64 {
+    30
+}
+
+/// Transcribe with the first backend of `chain` that answers
+///
+/// Each backend's requests get a deadline and are sent again when it
+/// passes. When a backend still doesn't answer, or answers with a rate
+/// limit or a server error, the audio goes to the next one. Other errors
+/// (a bad key, unsupported audio) fail right away, as the next backend
+/// isn't what was asked for.
+pub async fn transcribe(
+    chain: &[&Backend],
+    opts: TranscribeOptions,
+    watchdog: &WatchdogConfig,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    let mut chain = chain.iter();
+    let mut backend = *chain.next().ok_or("No backend to transcribe with")?;
+    let mut failed = Vec::new();
+
+    loop {
+        let result = watched(backend, opts.clone(), watchdog).await;
+        let next = match &result {
+            Ok(_) if !failed.is_empty() => {
+                progress::status("");
+                eprintln!(
+                    "Transcribed by {} ({} failed)",
+                    backend.name(),
+                    failed.join(", ")
+                );
+                crate::events::emit(
+                    "failover",
+                    serde_json::json!({ "backend": backend.name(), "failed": failed }),
+                );
+                None
+            }
+            Err(e) if e.is::<TimedOut>() || crate::http::is_transient(e.as_ref()) => chain.next(),
+            _ => None,
+        };
+        let Some(next) = next else {
+            return match result {
+                Err(e) if e.is::<TimedOut>() && failed.is_empty() => {
+                    Err(format!("{}; set failover to try other backends", e).into())
+                }
+                result => result,
+            };
+        };
+
+        // Timeouts were reported as they happened
+        if let Err(e) = result
+            && !e.is::<TimedOut>()
+        {
+            progress::status("");
+            eprintln!("Warning: {}", e);
+        }
+        failed.push(backend.name());
+        backend = *next;
+        progress::stage(&format!("Falling back to {}", backend.name()));
+    }
+}
+
+/// Backend that didn't answer within its deadline, retries included
+#[derive(Debug)]
+struct TimedOut {
+    backend: String,
+    deadline: Duration,
+    attempts: u32,
+}
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} did not answer within {}s, {} time(s)",
+            self.backend,
+            self.deadline.as_secs(),
+            self.attempts
+        )
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Transcribe with one backend, retrying passing errors after a pause
+async fn watched(
+    backend: &Backend,
+    opts: TranscribeOptions,
+    watchdog: &WatchdogConfig,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    let mut failures = 0;
+    loop {
+        let delay = match deadlined(backend, opts.clone(), watchdog).await {
+            Err(e)
+                if failures < watchdog.error_retries && crate::http::is_transient(e.as_ref()) =>
+            {
+                match backoff(watchdog, failures, crate::http::retry_after(e.as_ref())) {
+                    Some(delay) => {
+                        progress::status("");
+                        eprintln!("Warning: {}", e);
+                        delay
+                    }
+                    None => return Err(e),
+                }
+            }
+            result => return result,
+        };
+
+        failures += 1;
+        progress::stage(&format!(
+            "Retrying {} in {:.1}s ({}/{})",
+            backend.name(),
+            delay.as_secs_f32(),
+            failures,
+            watchdog.error_retries
+        ));
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Pause before sending a request again after `failures` passing errors
+///
+/// The service's `Retry-After` when it gave one, else `backoff_ms` doubled
+/// for each failure, cut by up to half at random so that clients limited
+/// together don't all come back at once. `None` when the service asks for
+/// longer than `max_backoff_secs`: the next backend is better off with it.
+fn backoff(
+    watchdog: &WatchdogConfig,
+    failures: u32,
+    retry_after: Option<Duration>,
+) -> Option<Duration> {
+    let max = Duration::from_secs(watchdog.max_backoff_secs);
+    if let Some(wait) = retry_after {
+        return (wait <= max).then_some(wait);
+    }
+    let full = Duration::from_millis(watchdog.backoff_ms)
+        .saturating_mul(2u32.saturating_pow(failures))
+        .min(max);
+    // A fresh random seed each time, without a random number crate
+    let share = (RandomState::new().hash_one(failures) % 1000) as u32;
+    Some(full / 2 + full / 2 * share / 1000)
+}
+
+/// Transcribe with one backend, retrying requests that pass their deadline
+async fn deadlined(
+    backend: &Backend,
+    opts: TranscribeOptions,
+    watchdog: &WatchdogConfig,
+) -> Result<Transcript, Box<dyn std::error::Error>> {
+    if watchdog.timeout_secs == 0 || backend.is_local() {
+        return backend.transcribe(opts).await;
+    }
+
+    // Slow backends take about as long as the audio
+    let audio = crate::audio::wav_duration(&opts.wav_data).unwrap_or_default();
+    let deadline = Duration::from_secs(watchdog.timeout_secs) + Duration::from_secs_f32(audio);
+
+    for attempt in 0..=watchdog.retries {
+        if attempt > 0 {
+            progress::stage(&format!("Retrying {}/{}", attempt, watchdog.retries));
+        }
+        match tokio::time::timeout(deadline, backend.transcribe(opts.clone())).await {
+            Ok(result) => return result,
+            Err(_) => timed_out(backend, deadline),
+        }
+    }
+
+    Err(Box::new(TimedOut {
+        backend: backend.name().to_string(),
+        deadline,
+        attempts: watchdog.retries + 1,
+    }))
+}
+
+fn timed_out(backend: &Backend, deadline: Duration) {
+    progress::status("");
+    eprintln!(
+        "Warning: {} did not answer within {}s",
+        backend.name(),
+        deadline.as_secs()
+    );
+}